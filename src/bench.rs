@@ -0,0 +1,470 @@
+use crate::runner::{read_input, solve, Day, Input};
+use crate::verify::duration;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const HISTORY: &str = "bench.json";
+
+// how long to run each stage for
+#[derive(PartialEq, Debug, Clone)]
+pub struct Config {
+    pub warmup: Duration,
+    pub budget: Duration,
+    pub min_samples: usize,
+    pub max_samples: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            warmup: Duration::from_millis(100),
+            budget: Duration::from_secs(1),
+            min_samples: 5,
+            max_samples: 1000,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub samples: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    pub fn new(mut times: Vec<Duration>) -> Self {
+        assert!(!times.is_empty(), "no samples");
+        times.sort();
+        // nearest rank
+        let pct = |p: usize| times[(p * times.len()).div_ceil(100) - 1];
+        Self {
+            samples: times.len(),
+            min: times[0],
+            median: pct(50),
+            p90: pct(90),
+            p99: pct(99),
+            max: times[times.len() - 1],
+        }
+    }
+}
+
+// time f, running it for at least the warm-up first
+pub fn sample<F: FnMut()>(config: &Config, mut f: F) -> Stats {
+    let start = Instant::now();
+    loop {
+        f();
+        if start.elapsed() >= config.warmup {
+            break;
+        }
+    }
+
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < config.max_samples
+        && (times.len() < config.min_samples || start.elapsed() < config.budget)
+    {
+        let t = Instant::now();
+        f();
+        times.push(t.elapsed());
+    }
+    Stats::new(times)
+}
+
+// as sample, unless a first run of f fails
+pub fn try_sample<T, F>(config: &Config, mut f: F) -> Result<Stats, String>
+where
+    F: FnMut() -> Result<T, String>,
+{
+    f()?;
+    Ok(sample(config, || {
+        let _ = f();
+    }))
+}
+
+// a stage of a day: parse, part1 or part2
+#[derive(PartialEq, Debug, Clone)]
+pub struct Measure {
+    pub day: u32,
+    pub stage: String,
+    // the error if the stage failed
+    pub stats: Result<Stats, String>,
+}
+
+// time every stage of the days, carrying on past failures so they are
+// kept in the history too
+pub fn bench(days: &[&Day], config: &Config) -> Vec<Measure> {
+    let mut measures = Vec::new();
+    for d in days.iter() {
+        let data = read_input(d, &Input::Default);
+        let mut measure = |stage: String, stats| {
+            measures.push(Measure {
+                day: d.day,
+                stage,
+                stats,
+            })
+        };
+        let parse = match &data {
+            Ok(data) => try_sample(config, || (d.load)(data).map_err(|e| e.to_string())),
+            Err(e) => Err(e.clone()),
+        };
+        measure("parse".to_string(), parse);
+        let solution = d.solution();
+        for p in d.parts() {
+            let stats = match &solution {
+                Ok(s) => try_sample(config, || solve(s.as_ref(), p)),
+                Err(e) => Err(e.clone()),
+            };
+            measure(format!("part{}", p), stats);
+        }
+    }
+    measures
+}
+
+// A benchmark run, as kept in the history
+#[derive(PartialEq, Debug, Clone)]
+pub struct Run {
+    pub commit: String,
+    // seconds since the epoch
+    pub time: u64,
+    pub measures: Vec<Measure>,
+}
+
+fn nanos(d: Duration) -> Value {
+    json!(d.as_nanos() as u64)
+}
+
+impl Run {
+    // a run of the current checkout, now
+    pub fn new(measures: Vec<Measure>) -> Self {
+        let commit = process::Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            commit,
+            time,
+            measures,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let measures: Vec<_> = self
+            .measures
+            .iter()
+            .map(|m| match &m.stats {
+                Ok(stats) => json!({
+                    "day": m.day,
+                    "stage": m.stage,
+                    "samples": stats.samples,
+                    "min_ns": nanos(stats.min),
+                    "median_ns": nanos(stats.median),
+                    "p90_ns": nanos(stats.p90),
+                    "p99_ns": nanos(stats.p99),
+                    "max_ns": nanos(stats.max),
+                }),
+                Err(e) => json!({ "day": m.day, "stage": m.stage, "error": e }),
+            })
+            .collect();
+        json!({
+            "commit": self.commit,
+            "time": self.time,
+            "measures": measures,
+        })
+    }
+
+    fn from_json(v: &Value) -> Option<Self> {
+        let ns = |m: &Value, k: &str| m[k].as_u64().map(Duration::from_nanos);
+        let measures = v["measures"]
+            .as_array()?
+            .iter()
+            .map(|m| {
+                let stats = match m["error"].as_str() {
+                    Some(e) => Err(e.to_string()),
+                    None => Ok(Stats {
+                        samples: m["samples"].as_u64()? as usize,
+                        min: ns(m, "min_ns")?,
+                        median: ns(m, "median_ns")?,
+                        p90: ns(m, "p90_ns")?,
+                        p99: ns(m, "p99_ns")?,
+                        max: ns(m, "max_ns")?,
+                    }),
+                };
+                Some(Measure {
+                    day: m["day"].as_u64()? as u32,
+                    stage: m["stage"].as_str()?.to_string(),
+                    stats,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            commit: v["commit"].as_str()?.to_string(),
+            time: v["time"].as_u64()?,
+            measures,
+        })
+    }
+
+    pub fn get(&self, day: u32, stage: &str) -> Option<&Measure> {
+        self.measures
+            .iter()
+            .find(|m| m.day == day && m.stage == stage)
+    }
+}
+
+// Every run so far, oldest first
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct History {
+    pub runs: Vec<Run>,
+}
+
+impl History {
+    pub fn to_json(&self) -> String {
+        let runs: Vec<_> = self.runs.iter().map(|r| r.to_json()).collect();
+        serde_json::to_string_pretty(&json!({ "runs": runs })).expect("json")
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let v: Value = serde_json::from_str(s).map_err(|e| e.to_string())?;
+        let runs = v["runs"].as_array().ok_or("no runs")?;
+        let runs = runs
+            .iter()
+            .enumerate()
+            .map(|(i, r)| Run::from_json(r).ok_or_else(|| format!("run {}: bad run", i)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { runs })
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    // no file is an empty history
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => Self::parse(&s).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    // the last run to measure a stage, skipping those where it failed
+    pub fn last(&self, day: u32, stage: &str) -> Option<(&Run, &Stats)> {
+        self.runs.iter().rev().find_map(|r| {
+            let m = r.get(day, stage)?;
+            m.stats.as_ref().ok().map(|s| (r, s))
+        })
+    }
+}
+
+// A run against the one before it, flagging medians that have grown by
+// more than the threshold, e.g. 0.1 for 10%.
+pub struct Report<'a> {
+    pub run: &'a Run,
+    pub history: &'a History,
+    pub threshold: f64,
+}
+
+impl Report<'_> {
+    // the change in median from the last run, as a fraction
+    fn change(&self, m: &Measure) -> Option<f64> {
+        let now = m.stats.as_ref().ok()?;
+        let (_, before) = self.history.last(m.day, &m.stage)?;
+        let before = before.median.as_secs_f64();
+        if before == 0.0 {
+            return None;
+        }
+        Some(now.median.as_secs_f64() / before - 1.0)
+    }
+
+    pub fn slowdowns(&self) -> Vec<&Measure> {
+        self.run
+            .measures
+            .iter()
+            .filter(|m| self.change(m).is_some_and(|c| c > self.threshold))
+            .collect()
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>3} {:<6} {:>7} {:>8} {:>8} {:>8} {:>8}  change",
+            "day", "stage", "samples", "min", "median", "p90", "p99"
+        )?;
+        for m in self.run.measures.iter() {
+            let stats = match &m.stats {
+                Ok(s) => s,
+                Err(e) => {
+                    writeln!(f, "{:>3} {:<6} failed: {}", m.day, m.stage, e)?;
+                    continue;
+                }
+            };
+            let change = match self.change(m) {
+                Some(c) if c > self.threshold => format!("{:+.0}% SLOWER", c * 100.0),
+                Some(c) => format!("{:+.0}%", c * 100.0),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>3} {:<6} {:>7} {:>8} {:>8} {:>8} {:>8}  {}",
+                m.day,
+                m.stage,
+                stats.samples,
+                duration(stats.min),
+                duration(stats.median),
+                duration(stats.p90),
+                duration(stats.p99),
+                change
+            )?;
+        }
+        match self.history.runs.len() {
+            0 => write!(f, "no earlier runs to compare with"),
+            _ => write!(
+                f,
+                "{} slower than their last run by over {:.0}%",
+                self.slowdowns().len(),
+                self.threshold * 100.0
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn measure(day: u32, stage: &str, median: u64) -> Measure {
+        Measure {
+            day,
+            stage: stage.to_string(),
+            stats: Ok(Stats {
+                samples: 3,
+                min: ms(median - 1),
+                median: ms(median),
+                p90: ms(median + 1),
+                p99: ms(median + 2),
+                max: ms(median + 2),
+            }),
+        }
+    }
+
+    fn failed(day: u32, stage: &str) -> Measure {
+        Measure {
+            day,
+            stage: stage.to_string(),
+            stats: Err("no answer".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let s = Stats::new((1..=100).rev().map(ms).collect());
+        assert_eq!(100, s.samples);
+        assert_eq!(ms(1), s.min);
+        assert_eq!(ms(50), s.median);
+        assert_eq!(ms(90), s.p90);
+        assert_eq!(ms(99), s.p99);
+        assert_eq!(ms(100), s.max);
+
+        let s = Stats::new(vec![ms(7)]);
+        assert_eq!((ms(7), ms(7)), (s.median, s.p99));
+
+        let config = Config {
+            warmup: Duration::from_millis(0),
+            budget: Duration::from_millis(0),
+            min_samples: 5,
+            max_samples: 10,
+        };
+        let mut calls = 0;
+        assert_eq!(5, sample(&config, || calls += 1).samples);
+        assert_eq!(6, calls);
+        assert_eq!(
+            Err("no".to_string()),
+            try_sample(&config, || Err::<(), _>("no".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_bench() {
+        // a day with no input fails every stage, without stopping
+        let day = Day {
+            day: 99,
+            parts: 2,
+            load: |s| Err(input::end(s).error("unreachable")),
+        };
+        let measures = bench(&[&day], &Config::default());
+        let stages: Vec<(&str, bool)> = measures
+            .iter()
+            .map(|m| (m.stage.as_str(), m.stats.is_err()))
+            .collect();
+        assert_eq!(
+            vec![("parse", true), ("part1", true), ("part2", true)],
+            stages
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let run = |commit: &str, measures| Run {
+            commit: commit.to_string(),
+            time: 1_577_836_800,
+            measures,
+        };
+        let history = History {
+            runs: vec![
+                run(
+                    "abc1234",
+                    vec![measure(16, "part1", 40), measure(22, "part2", 10)],
+                ),
+                run("def5678", vec![measure(16, "part1", 30)]),
+                run("def9999", vec![failed(16, "part1")]),
+            ],
+        };
+        assert_eq!(Ok(history.clone()), History::parse(&history.to_json()));
+        assert!(History::parse("{\"runs\": [{\"commit\": \"x\"}]}").is_err());
+
+        let now = run(
+            "fff0000",
+            vec![
+                measure(16, "part1", 36),
+                measure(22, "part2", 10),
+                measure(12, "part2", 5),
+                failed(22, "part1"),
+            ],
+        );
+        let report = Report {
+            run: &now,
+            history: &history,
+            threshold: 0.1,
+        };
+        let slow: Vec<u32> = report.slowdowns().iter().map(|m| m.day).collect();
+        assert_eq!(vec![16], slow);
+        let expected = "day stage  samples      min   median      p90      p99  change
+ 16 part1        3   35.0ms   36.0ms   37.0ms   38.0ms  +20% SLOWER
+ 22 part2        3    9.0ms   10.0ms   11.0ms   12.0ms  +0%
+ 12 part2        3    4.0ms    5.0ms    6.0ms    7.0ms  -
+ 22 part1  failed: no answer
+1 slower than their last run by over 10%";
+        assert_eq!(expected, report.to_string());
+    }
+}
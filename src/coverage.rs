@@ -0,0 +1,249 @@
+use crate::intcode::{params, Extension, Intcode, Param};
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+// the length of a standard instruction, None for anything else
+fn instruction_len(instr: i64) -> Option<i64> {
+    params(instr % 100).map(|p| 1 + p.len() as i64)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Cell {
+    // the start of an instruction and its length
+    Code(i64),
+    Data,
+}
+
+// Records which addresses are executed, which are read as data and which
+// way each jump goes. Run a machine with this as its extension; coverage
+// accumulates across any number of runs.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    // instruction address to times executed
+    pub executed: BTreeMap<i64, usize>,
+    // address to times read by an instruction
+    pub read: BTreeMap<i64, usize>,
+    // jump address to (times taken, times not taken)
+    pub branches: BTreeMap<i64, (usize, usize)>,
+    // a jump waiting to see where execution goes next
+    jump: Option<i64>,
+}
+
+impl Extension for Coverage {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+
+    fn read(&mut self, addr: i64) -> Option<i64> {
+        *self.read.entry(addr).or_default() += 1;
+        None
+    }
+
+    fn step(&mut self, code: &Intcode) {
+        let pc = code.pc();
+        if let Some(j) = self.jump.take() {
+            let b = self.branches.entry(j).or_default();
+            if pc == j + 3 {
+                b.1 += 1;
+            } else {
+                b.0 += 1;
+            }
+        }
+
+        *self.executed.entry(pc).or_default() += 1;
+        if let 5 | 6 = code.peek(pc) % 100 {
+            self.jump = Some(pc);
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Split the program into instructions and data. Executed instructions
+    // are exact, the rest is a guess from a linear sweep: anything read is
+    // data, anything that decodes as an instruction is code.
+    fn cells(&self, code: &Intcode) -> Vec<(i64, Cell)> {
+        let len = code.program_len() as i64;
+        let mut v = Vec::new();
+        let mut a = 0;
+        while a < len {
+            let instr = code.peek(a);
+            let cell = if self.executed.contains_key(&a) {
+                Cell::Code(instruction_len(instr).unwrap_or(1))
+            } else if self.read.contains_key(&a) {
+                Cell::Data
+            } else {
+                match instruction_len(instr) {
+                    Some(n) if a + n <= len => Cell::Code(n),
+                    _ => Cell::Data,
+                }
+            };
+            v.push((a, cell));
+            a += match cell {
+                Cell::Code(n) => n,
+                Cell::Data => 1,
+            };
+        }
+        v
+    }
+
+    // addresses read as data but never executed as part of an instruction
+    pub fn data_only(&self, code: &Intcode) -> Vec<i64> {
+        let mut instr = BTreeSet::new();
+        for (&a, _) in self.executed.iter() {
+            let n = instruction_len(code.peek(a)).unwrap_or(1);
+            instr.extend(a..a + n);
+        }
+        self.read
+            .keys()
+            .filter(|a| !instr.contains(a))
+            .copied()
+            .collect()
+    }
+
+    // One line per instruction or data cell: the address, how many times
+    // it was executed or read, and its contents. Unexecuted instructions
+    // are marked with '-', unread data with '.'.
+    pub fn listing(&self, code: &Intcode) -> String {
+        let mut s = String::new();
+        for (a, cell) in self.cells(code) {
+            match cell {
+                Cell::Code(n) => {
+                    let words: Vec<String> = (a..a + n).map(|i| code.peek(i).to_string()).collect();
+                    let hits = match self.executed.get(&a) {
+                        Some(h) => h.to_string(),
+                        None => "-".to_string(),
+                    };
+                    write!(s, "{:>6} {:>8}  {}", a, hits, words.join(",")).unwrap();
+                    if let Some((taken, not)) = self.branches.get(&a) {
+                        write!(s, "  ; taken: {}, not taken: {}", taken, not).unwrap();
+                    }
+                }
+                Cell::Data => {
+                    let reads = match self.read.get(&a) {
+                        Some(r) => format!("r{}", r),
+                        None => ".".to_string(),
+                    };
+                    write!(s, "{:>6} {:>8}  {}", a, reads, code.peek(a)).unwrap();
+                }
+            }
+            s.push('\n');
+        }
+        s
+    }
+
+    // LCOV tracefile with one line per instruction, numbered address + 1,
+    // and the two directions of each jump as branches
+    pub fn lcov(&self, code: &Intcode, name: &str) -> String {
+        let mut s = String::new();
+        writeln!(s, "TN:").unwrap();
+        writeln!(s, "SF:{}", name).unwrap();
+        let (mut lf, mut lh, mut brf, mut brh) = (0, 0, 0, 0);
+        for (a, cell) in self.cells(code) {
+            if let Cell::Code(_) = cell {
+                let hits = *self.executed.get(&a).unwrap_or(&0);
+                writeln!(s, "DA:{},{}", a + 1, hits).unwrap();
+                lf += 1;
+                if hits > 0 {
+                    lh += 1;
+                }
+                if let 5 | 6 = code.peek(a) % 100 {
+                    let counts = match self.branches.get(&a) {
+                        Some(&(t, n)) => [t.to_string(), n.to_string()],
+                        None => ["-".to_string(), "-".to_string()],
+                    };
+                    for (i, c) in counts.iter().enumerate() {
+                        writeln!(s, "BRDA:{},0,{},{}", a + 1, i, c).unwrap();
+                        brf += 1;
+                        if c != "-" && c != "0" {
+                            brh += 1;
+                        }
+                    }
+                }
+            }
+        }
+        writeln!(s, "BRF:{}", brf).unwrap();
+        writeln!(s, "BRH:{}", brh).unwrap();
+        writeln!(s, "LF:{}", lf).unwrap();
+        writeln!(s, "LH:{}", lh).unwrap();
+        writeln!(s, "end_of_record").unwrap();
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // read x, if x is non-zero skip outputting 0, then output the data
+    // at 12
+    const PROG: &str = "3,11,1005,11,7,104,0,4,12,99,0,0,42";
+
+    fn run(cov: &mut Coverage, input: i64) {
+        let mut code = Intcode::new(PROG);
+        let mut input = Some(input);
+        while let Ok(crate::intcode::ExitMode::Output(_)) = code.run_ext(cov, || input.take()) {}
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut cov = Coverage::new();
+        run(&mut cov, 1);
+        assert_eq!(None, cov.executed.get(&5));
+        assert_eq!(Some(&(1, 0)), cov.branches.get(&2));
+
+        run(&mut cov, 0);
+        let executed: Vec<(i64, usize)> = cov.executed.iter().map(|(&a, &n)| (a, n)).collect();
+        assert_eq!(vec![(0, 2), (2, 2), (5, 1), (7, 2), (9, 2)], executed);
+        assert_eq!(Some(&(1, 1)), cov.branches.get(&2));
+        assert_eq!(vec![11, 12], cov.data_only(&Intcode::new(PROG)));
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut cov = Coverage::new();
+        run(&mut cov, 1);
+        let code = Intcode::new(PROG);
+        let expected = "     0        1  3,11
+     2        1  1005,11,7  ; taken: 1, not taken: 0
+     5        -  104,0
+     7        1  4,12
+     9        1  99
+    10        .  0
+    11       r1  0
+    12       r1  42
+";
+        assert_eq!(expected, cov.listing(&code));
+    }
+
+    #[test]
+    fn test_lcov() {
+        let mut cov = Coverage::new();
+        run(&mut cov, 0);
+        let code = Intcode::new(PROG);
+        let expected = "TN:
+SF:prog
+DA:1,1
+DA:3,1
+BRDA:3,0,0,0
+BRDA:3,0,1,1
+DA:6,1
+DA:8,1
+DA:10,1
+BRF:2
+BRH:1
+LF:5
+LH:5
+end_of_record
+";
+        assert_eq!(expected, cov.lcov(&code, "prog"));
+    }
+}
@@ -0,0 +1,790 @@
+use crate::intcode::{ExitMode, Intcode, NoExtension};
+use crate::program::Program;
+use crate::stack::CallStack;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc;
+use std::thread;
+
+// words of memory shown from each frame's relative base
+const WINDOW: i64 = 16;
+
+// how many instructions to run between checks for a pause
+const POLL_STEPS: usize = 10_000;
+
+// Maps instruction addresses to source lines, one "address line" pair per
+// line, with an optional "source <path>" line naming the source file.
+// Blank lines and lines starting with '#' are ignored.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SourceMap {
+    pub source: Option<String>,
+    pub lines: BTreeMap<i64, i64>,
+}
+
+impl SourceMap {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut map = Self::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(path) = line.strip_prefix("source ") {
+                map.source = Some(path.trim().to_string());
+                continue;
+            }
+            let v: Vec<&str> = line.split_whitespace().collect();
+            match v.as_slice() {
+                [a, l] => match (a.parse(), l.parse()) {
+                    (Ok(a), Ok(l)) => {
+                        map.lines.insert(a, l);
+                    }
+                    _ => return Err(format!("line {}: bad entry {:?}", n + 1, line)),
+                },
+                _ => return Err(format!("line {}: bad entry {:?}", n + 1, line)),
+            }
+        }
+        Ok(map)
+    }
+
+    // the line of the last mapped address at or before addr
+    pub fn line(&self, addr: i64) -> Option<i64> {
+        self.lines.range(..=addr).next_back().map(|(_, &l)| l)
+    }
+
+    // the first address mapped to line
+    pub fn addr(&self, line: i64) -> Option<i64> {
+        self.lines.iter().find(|(_, &l)| l == line).map(|(&a, _)| a)
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.source {
+            writeln!(f, "source {}", path)?;
+        }
+        for (a, l) in self.lines.iter() {
+            writeln!(f, "{} {}", a, l)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Step {
+    Continue,
+    In,
+    Over,
+    Out,
+}
+
+fn ints(s: &str) -> Result<Vec<i64>, String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.parse().map_err(|_| format!("bad integer {:?}", t)))
+        .collect()
+}
+
+// Debug Adapter Protocol session for one Intcode program. Without a
+// source map the program itself is the source, with line n being address
+// n - 1.
+#[derive(Default)]
+pub struct Session {
+    code: Option<Intcode>,
+    path: String,
+    map: Option<SourceMap>,
+    input: VecDeque<i64>,
+    // addresses
+    breakpoints: BTreeSet<i64>,
+    stack: CallStack,
+    stop_on_entry: bool,
+    // whether the program is stopped at its pc, so resuming runs the
+    // instruction there even if it has a breakpoint
+    stopped: bool,
+    pub done: bool,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn line(&self, addr: i64) -> i64 {
+        match &self.map {
+            Some(m) => m.line(addr).unwrap_or(0),
+            None => addr + 1,
+        }
+    }
+
+    fn addr(&self, line: i64) -> Option<i64> {
+        match &self.map {
+            Some(m) => m.addr(line),
+            None if line > 0 => Some(line - 1),
+            None => None,
+        }
+    }
+
+    fn source(&self) -> Value {
+        let path = match self.map.as_ref().and_then(|m| m.source.as_ref()) {
+            Some(s) => s,
+            None => &self.path,
+        };
+        json!({ "path": path })
+    }
+
+    fn stopped(reason: &str, text: Option<String>) -> Value {
+        let mut body = json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        event("stopped", body)
+    }
+
+    // Run until a breakpoint, the step is done or the machine stops,
+    // returning the events along the way. Breakpoints are checked before
+    // each instruction runs, so one on the first instruction is hit. A
+    // pause stops it without an event, see "pause".
+    fn resume(&mut self, step: Step, interrupted: &mut dyn FnMut() -> bool) -> Vec<Value> {
+        let mut events = Vec::new();
+        let code = match self.code.as_mut() {
+            Some(c) => c,
+            None => return events,
+        };
+        let depth = self.stack.depth();
+        let start = match &self.map {
+            Some(m) => m.line(code.pc()),
+            None => Some(code.pc()),
+        };
+        let mut first = true;
+        let mut steps = 0;
+        loop {
+            let pc = code.pc();
+            if !(first && self.stopped) && self.breakpoints.contains(&pc) {
+                events.push(Self::stopped("breakpoint", None));
+                break;
+            }
+            first = false;
+            steps += 1;
+            if steps % POLL_STEPS == 0 && interrupted() {
+                break;
+            }
+
+            let input = &mut self.input;
+            let exit = code.step_ext(&mut NoExtension, &mut || input.pop_front());
+            self.stack.step(pc, code);
+            match exit {
+                Err(e) => {
+                    events.push(Self::stopped("exception", Some(e.to_string())));
+                    break;
+                }
+                Ok(Some(ExitMode::Halt)) => {
+                    events.push(event("exited", json!({ "exitCode": 0 })));
+                    events.push(event("terminated", json!({})));
+                    break;
+                }
+                Ok(Some(ExitMode::AwaitingInput)) => {
+                    events.push(Self::stopped("pause", Some("awaiting input".to_string())));
+                    break;
+                }
+                Ok(Some(ExitMode::Output(x))) => {
+                    let body = json!({ "category": "stdout", "output": format!("{}\n", x) });
+                    events.push(event("output", body));
+                }
+                Ok(None) => (),
+            }
+
+            let pc = code.pc();
+            let line = match &self.map {
+                Some(m) => m.line(pc),
+                None => Some(pc),
+            };
+            let done = match step {
+                Step::Continue => false,
+                Step::In => line != start,
+                Step::Over => self.stack.depth() <= depth && line != start,
+                Step::Out => self.stack.depth() < depth,
+            };
+            if done {
+                let reason = match self.breakpoints.contains(&pc) {
+                    true => "breakpoint",
+                    false => "step",
+                };
+                events.push(Self::stopped(reason, None));
+                break;
+            }
+        }
+        self.stopped = true;
+        events
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), String> {
+        let path = args["program"].as_str().ok_or("no program given")?;
+        self.code = Some(Program::load(path)?.intcode());
+        self.path = path.to_string();
+        if let Some(p) = args["sourceMap"].as_str() {
+            let s = fs::read_to_string(p).map_err(|e| format!("{}: {}", p, e))?;
+            self.map = Some(SourceMap::parse(&s).map_err(|e| format!("{}: {}", p, e))?);
+        }
+        if let Some(values) = args["input"].as_array() {
+            for v in values {
+                self.input
+                    .push_back(v.as_i64().ok_or("input must be integers")?);
+            }
+        }
+        if let Some(p) = args["inputFile"].as_str() {
+            let s = fs::read_to_string(p).map_err(|e| format!("{}: {}", p, e))?;
+            self.input.extend(ints(&s)?);
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(())
+    }
+
+    fn stack_trace(&self) -> Value {
+        let code = match &self.code {
+            Some(c) => c,
+            None => return json!({ "stackFrames": [], "totalFrames": 0 }),
+        };
+        let mut frames = Vec::new();
+        let mut pc = code.pc();
+        for (id, f) in self.stack.frames.iter().enumerate().rev() {
+            frames.push(json!({
+                "id": id + 1,
+                "name": format!("fn@{}", f.entry),
+                "source": self.source(),
+                "line": self.line(pc),
+                "column": 1,
+                "instructionPointerReference": pc.to_string(),
+            }));
+            pc = f.call;
+        }
+        frames.push(json!({
+            "id": 0,
+            "name": "main",
+            "source": self.source(),
+            "line": self.line(pc),
+            "column": 1,
+            "instructionPointerReference": pc.to_string(),
+        }));
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    // the pc and relative base a frame was at, frame 0 being the outermost
+    fn frame_registers(&self, id: usize) -> Option<(i64, i64)> {
+        let code = self.code.as_ref()?;
+        let frames = &self.stack.frames;
+        if id > frames.len() {
+            return None;
+        }
+        let pc = match frames.get(id) {
+            Some(f) => f.call,
+            None => code.pc(),
+        };
+        let base = match id {
+            0 => 0,
+            _ => frames[id - 1].base,
+        };
+        Some((pc, base))
+    }
+
+    // variables references: 2 * frame + 1 for registers, 2 * frame + 2
+    // for memory
+    fn variables(&self, reference: i64) -> Value {
+        let id = ((reference - 1) / 2) as usize;
+        let (pc, base) = match self.frame_registers(id) {
+            Some(r) => r,
+            None => return json!({ "variables": [] }),
+        };
+        let var = |name: String, value: i64| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 });
+        let vars: Vec<Value> = if reference % 2 == 1 {
+            vec![
+                var("pc".to_string(), pc),
+                var("relative base".to_string(), base),
+            ]
+        } else {
+            let code = self.code.as_ref().expect("checked above");
+            (base..base + WINDOW)
+                .map(|a| var(format!("[{}]", a), code.peek(a)))
+                .collect()
+        };
+        json!({ "variables": vars })
+    }
+
+    fn command(
+        &mut self,
+        command: &str,
+        args: &Value,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Result<(Value, Vec<Value>), String> {
+        let mut events = Vec::new();
+        let body = match command {
+            "initialize" => {
+                events.push(event("initialized", json!({})));
+                json!({ "supportsConfigurationDoneRequest": true })
+            }
+            "launch" => {
+                self.launch(args)?;
+                json!({})
+            }
+            "setBreakpoints" => {
+                let lines: Vec<i64> = args["breakpoints"]
+                    .as_array()
+                    .map(|b| b.iter().filter_map(|b| b["line"].as_i64()).collect())
+                    .unwrap_or_default();
+                self.breakpoints.clear();
+                let mut result = Vec::new();
+                for line in lines {
+                    match self.addr(line) {
+                        Some(a) => {
+                            self.breakpoints.insert(a);
+                            result.push(json!({ "verified": true, "line": self.line(a) }));
+                        }
+                        None => result.push(json!({ "verified": false, "line": line })),
+                    }
+                }
+                json!({ "breakpoints": result })
+            }
+            "setExceptionBreakpoints" => json!({}),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stopped = true;
+                    events.push(Self::stopped("entry", None));
+                } else {
+                    events = self.resume(Step::Continue, interrupted);
+                }
+                json!({})
+            }
+            "threads" => json!({ "threads": [{ "id": 1, "name": "intcode" }] }),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => {
+                let id = args["frameId"].as_i64().unwrap_or(0);
+                json!({ "scopes": [
+                    { "name": "Registers", "variablesReference": 2 * id + 1, "expensive": false },
+                    { "name": "Memory", "variablesReference": 2 * id + 2, "expensive": false },
+                ] })
+            }
+            "variables" => self.variables(args["variablesReference"].as_i64().unwrap_or(0)),
+            "continue" => {
+                events = self.resume(Step::Continue, interrupted);
+                json!({ "allThreadsContinued": true })
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let step = match command {
+                    "next" => Step::Over,
+                    "stepIn" => Step::In,
+                    _ => Step::Out,
+                };
+                events = self.resume(step, interrupted);
+                json!({})
+            }
+            // a running program has already been interrupted by the time
+            // this is handled, see serve
+            "pause" => {
+                events.push(Self::stopped("pause", None));
+                json!({})
+            }
+            // "input 1,2,3" queues more input
+            "evaluate" => {
+                let expr = args["expression"].as_str().unwrap_or("");
+                match expr.strip_prefix("input ") {
+                    Some(values) => {
+                        let values = ints(values)?;
+                        self.input.extend(&values);
+                        json!({ "result": format!("queued {} inputs", values.len()), "variablesReference": 0 })
+                    }
+                    None => return Err(format!("unknown expression {:?}", expr)),
+                }
+            }
+            "disconnect" => {
+                self.done = true;
+                json!({})
+            }
+            _ => return Err(format!("unsupported command {}", command)),
+        };
+        Ok((body, events))
+    }
+
+    // the response to a request, followed by any events it caused
+    #[cfg(test)]
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        self.reply(request, &mut || false)
+    }
+
+    // as handle, checking interrupted now and then while running
+    fn reply(&mut self, request: &Value, interrupted: &mut dyn FnMut() -> bool) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("");
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+        });
+        match self.command(command, &request["arguments"], interrupted) {
+            Ok((body, events)) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+                let mut v = vec![response];
+                v.extend(events);
+                v
+            }
+            Err(e) => {
+                response["success"] = json!(false);
+                response["message"] = json!(e);
+                vec![response]
+            }
+        }
+    }
+
+    // Serve requests framed with Content-Length headers until the client
+    // disconnects. Requests are read on another thread, so a pause can
+    // interrupt a running program; anything else sent while it runs waits
+    // for it to stop.
+    pub fn serve<R, W>(&mut self, mut reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let m = read_message(&mut reader);
+            let end = !matches!(m, Ok(Some(_)));
+            if tx.send(m).is_err() || end {
+                break;
+            }
+        });
+
+        let mut pending = VecDeque::new();
+        let mut seq = 1;
+        while !self.done {
+            let m = match pending.pop_front() {
+                Some(m) => m,
+                None => rx.recv().unwrap_or(Ok(None)),
+            };
+            let request = match m? {
+                Some(r) => r,
+                None => return Ok(()),
+            };
+            let mut interrupted = || {
+                pending.extend(rx.try_iter());
+                pending
+                    .iter()
+                    .any(|m| matches!(m, Ok(Some(r)) if r["command"] == "pause"))
+            };
+            for mut m in self.reply(&request, &mut interrupted) {
+                m["seq"] = json!(seq);
+                seq += 1;
+                write_message(&mut writer, &m)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn event(name: &str, body: Value) -> Value {
+    json!({ "type": "event", "event": name, "body": body })
+}
+
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    serde_json::from_slice(&buf)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message<W: Write>(writer: &mut W, m: &Value) -> io::Result<()> {
+    let s = m.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", s.len(), s)?;
+    writer.flush()
+}
+
+// run a session on stdin and stdout, as editors launch adapters
+pub fn run_stdio() -> io::Result<()> {
+    let stdout = io::stdout();
+    Session::new().serve(BufReader::new(io::stdin()), stdout.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // set up a stack, call a function that outputs 2, output 1 on return
+    const PROG: &str = "109,100,21101,0,9,0,1105,1,12,104,1,99,109,1,104,2,109,-1,2105,1,0";
+    const MAP: &str = "source call.asm
+# main
+0 1
+2 2
+6 3
+9 4
+11 5
+# f
+12 7
+14 8
+16 9
+18 10
+";
+
+    // a file in the temp directory, removed when dropped
+    struct Temp(String);
+
+    impl Drop for Temp {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn write(name: &str, contents: &str) -> Temp {
+        let path = std::env::temp_dir().join(format!("dap-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        Temp(path.to_str().unwrap().to_string())
+    }
+
+    fn serve(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for m in requests {
+            write_message(&mut input, m).unwrap();
+        }
+        let mut out = Vec::new();
+        Session::new()
+            .serve(io::Cursor::new(input), &mut out)
+            .unwrap();
+
+        let mut reader = &out[..];
+        let mut got = Vec::new();
+        while let Some(m) = read_message(&mut reader).unwrap() {
+            got.push(m);
+        }
+        got
+    }
+
+    fn request(s: &mut Session, command: &str, args: Value) -> Vec<Value> {
+        let v = s
+            .handle(&json!({ "seq": 1, "type": "request", "command": command, "arguments": args }));
+        assert_eq!(json!(true), v[0]["success"], "{:?}", v[0]);
+        v
+    }
+
+    fn events(v: &[Value]) -> Vec<String> {
+        v[1..]
+            .iter()
+            .map(|e| match e["event"].as_str().unwrap() {
+                "stopped" => format!("stopped {}", e["body"]["reason"].as_str().unwrap()),
+                "output" => format!("output {}", e["body"]["output"].as_str().unwrap().trim()),
+                e => e.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_source_map() {
+        let m = SourceMap::parse(MAP).unwrap();
+        assert_eq!(Some("call.asm".to_string()), m.source);
+        assert_eq!(Some(3), m.line(7));
+        assert_eq!(Some(14), m.addr(8));
+        assert_eq!(None, m.addr(6));
+        assert_eq!(Ok(m.clone()), SourceMap::parse(&m.to_string()));
+        assert_eq!(
+            Err("line 3: bad entry \"0 x\"".to_string()),
+            SourceMap::parse("\n\n0 x")
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let program = write("call.int", PROG);
+        let map = write("call.map", MAP);
+        let mut s = Session::new();
+        assert_eq!(
+            vec!["initialized"],
+            events(&request(&mut s, "initialize", json!({})))
+        );
+        request(
+            &mut s,
+            "launch",
+            json!({ "program": program.0, "sourceMap": map.0 }),
+        );
+        let v = request(
+            &mut s,
+            "setBreakpoints",
+            json!({ "breakpoints": [{ "line": 8 }, { "line": 6 }] }),
+        );
+        assert_eq!(
+            json!([{ "verified": true, "line": 8 }, { "verified": false, "line": 6 }]),
+            v[0]["body"]["breakpoints"]
+        );
+        assert_eq!(
+            vec!["stopped breakpoint"],
+            events(&request(&mut s, "configurationDone", json!({})))
+        );
+
+        let v = request(&mut s, "stackTrace", json!({ "threadId": 1 }));
+        let frames: Vec<(String, i64)> = v[0]["body"]["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| {
+                (
+                    f["name"].as_str().unwrap().to_string(),
+                    f["line"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("fn@12".to_string(), 8),
+                ("fn@0".to_string(), 3),
+                ("main".to_string(), 1)
+            ],
+            frames
+        );
+        assert_eq!("call.asm", v[0]["body"]["stackFrames"][0]["source"]["path"]);
+
+        let v = request(&mut s, "variables", json!({ "variablesReference": 5 }));
+        assert_eq!("101", v[0]["body"]["variables"][1]["value"]);
+        let v = request(&mut s, "variables", json!({ "variablesReference": 4 }));
+        assert_eq!("[100]", v[0]["body"]["variables"][0]["name"]);
+        assert_eq!("9", v[0]["body"]["variables"][0]["value"]);
+
+        assert_eq!(
+            vec!["output 2", "stopped step"],
+            events(&request(&mut s, "stepOut", json!({ "threadId": 1 })))
+        );
+        assert_eq!(
+            vec!["stopped step"],
+            events(&request(&mut s, "next", json!({ "threadId": 1 })))
+        );
+        let v = request(&mut s, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(4, v[0]["body"]["stackFrames"][0]["line"]);
+        assert_eq!(
+            vec!["output 1", "exited", "terminated"],
+            events(&request(&mut s, "continue", json!({ "threadId": 1 })))
+        );
+    }
+
+    #[test]
+    fn test_input() {
+        // read and output one value, without a source map
+        let program = write("echo.int", "3,5,4,5,99,0");
+        let inputs = write("echo.txt", "7\n");
+        let mut s = Session::new();
+        request(
+            &mut s,
+            "launch",
+            json!({ "program": program.0, "stopOnEntry": true }),
+        );
+        let v = request(
+            &mut s,
+            "setBreakpoints",
+            json!({ "breakpoints": [{ "line": 5 }] }),
+        );
+        assert_eq!(
+            json!([{ "verified": true, "line": 5 }]),
+            v[0]["body"]["breakpoints"]
+        );
+        assert_eq!(
+            vec!["stopped entry"],
+            events(&request(&mut s, "configurationDone", json!({})))
+        );
+        assert_eq!(
+            vec!["stopped pause"],
+            events(&request(&mut s, "stepIn", json!({ "threadId": 1 })))
+        );
+        request(&mut s, "evaluate", json!({ "expression": "input 7" }));
+        assert_eq!(
+            vec!["stopped step"],
+            events(&request(&mut s, "stepIn", json!({ "threadId": 1 })))
+        );
+        assert_eq!(
+            vec!["output 7", "stopped breakpoint"],
+            events(&request(&mut s, "continue", json!({ "threadId": 1 })))
+        );
+
+        let mut s = Session::new();
+        request(
+            &mut s,
+            "launch",
+            json!({ "program": program.0, "inputFile": inputs.0 }),
+        );
+        // a breakpoint on the first instruction is hit
+        request(
+            &mut s,
+            "setBreakpoints",
+            json!({ "breakpoints": [{ "line": 1 }] }),
+        );
+        assert_eq!(
+            vec!["stopped breakpoint"],
+            events(&request(&mut s, "configurationDone", json!({})))
+        );
+        assert_eq!(
+            vec!["output 7", "exited", "terminated"],
+            events(&request(&mut s, "continue", json!({ "threadId": 1 })))
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let program = write("halt.int", "99");
+        let got: Vec<(i64, i64, bool)> = serve(&[
+            json!({ "seq": 1, "type": "request", "command": "launch", "arguments": { "program": program.0 } }),
+            json!({ "seq": 2, "type": "request", "command": "bogus" }),
+            json!({ "seq": 3, "type": "request", "command": "disconnect" }),
+        ])
+        .iter()
+        .map(|m| {
+            (
+                m["seq"].as_i64().unwrap(),
+                m["request_seq"].as_i64().unwrap(),
+                m["success"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+        assert_eq!(vec![(1, 1, true), (2, 2, false), (3, 3, true)], got);
+    }
+
+    #[test]
+    fn test_pause() {
+        // loop forever until paused
+        let program = write("loop.int", "1105,1,0");
+        let got: Vec<String> = serve(&[
+            json!({ "seq": 1, "type": "request", "command": "launch", "arguments": { "program": program.0 } }),
+            json!({ "seq": 2, "type": "request", "command": "configurationDone" }),
+            json!({ "seq": 3, "type": "request", "command": "pause" }),
+            json!({ "seq": 4, "type": "request", "command": "disconnect" }),
+        ])
+        .iter()
+        .map(|m| match m["type"].as_str().unwrap() {
+            "event" => format!("{} {}", m["event"], m["body"]["reason"]),
+            _ => format!("{} {}", m["command"], m["success"]),
+        })
+        .collect();
+        assert_eq!(
+            vec![
+                "\"launch\" true",
+                "\"configurationDone\" true",
+                "\"pause\" true",
+                "\"stopped\" \"pause\"",
+                "\"disconnect\" true",
+            ],
+            got
+        );
+    }
+}
@@ -1,37 +1,28 @@
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 
-pub fn part1() {
-    let f = File::open("day1.txt").unwrap();
-    let f = BufReader::new(f);
-
-    let mut sum: i32 = 0;
-
-    for line in f.lines() {
-        let l = line.unwrap();
-        let mass: i32 = l.parse().unwrap();
-
-        sum += fuel(mass);
-    }
-
-    println!("total {}", sum);
+pub struct Day1 {
+    masses: Vec<i32>,
 }
 
-pub fn part2() {
-    let f = File::open("day1.txt").expect("file not found");
-    let f = BufReader::new(f);
-
-    let mut sum: i32 = 0;
-
-    for line in f.lines() {
-        let l = line.unwrap();
-        let mass: i32 = l.parse().unwrap();
+impl Solution for Day1 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let masses = input::lines(data)
+            .map(|l| l.trim().parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { masses })
+    }
 
-        sum += recursive_fuel(mass);
+    fn part1(&self) -> Result<Answer, String> {
+        let sum: i32 = self.masses.iter().map(|&m| fuel(m)).sum();
+        Ok(sum.into())
     }
 
-    println!("total {}", sum);
+    fn part2(&self) -> Result<Answer, String> {
+        let sum: i32 = self.masses.iter().map(|&m| recursive_fuel(m)).sum();
+        Ok(sum.into())
+    }
 }
 
 fn fuel(mass: i32) -> i32 {
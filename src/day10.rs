@@ -1,7 +1,7 @@
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use itertools::Itertools;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 struct Point {
@@ -36,21 +36,26 @@ impl Point {
     }
 }
 
-fn parse<R: BufRead>(f: R) -> Vec<Point> {
-    f.lines()
+// the asteroids, '#' on a map of '.'
+fn parse(s: &str) -> Result<Vec<Point>, ParseError> {
+    let grid = input::grid(s, |c| match c {
+        '#' => Some(true),
+        '.' => Some(false),
+        _ => None,
+    })?;
+    Ok(grid
+        .iter()
         .enumerate()
-        .flat_map(|(y, l)| {
-            l.unwrap()
-                .chars()
+        .flat_map(|(y, row)| {
+            row.iter()
                 .enumerate()
-                .filter(|(_, c)| *c == '#')
-                .map(|(x, _)| Point {
+                .filter(|(_, &a)| a)
+                .map(move |(x, _)| Point {
                     x: x as i64,
                     y: y as i64,
                 })
-                .collect::<Vec<Point>>()
         })
-        .collect()
+        .collect())
 }
 
 fn max(points: &[Point]) -> (&Point, usize) {
@@ -81,13 +86,13 @@ fn angle_and_dist(p: &Point, points: &[Point]) -> Vec<(Point, i64, i64)> {
 
 fn angle_and_index(ad: &mut [(Point, i64, i64)]) -> Vec<(Point, i64, i64)> {
     // sort by the angle
-    ad.sort_by(|(_, a1, _), (_, a2, _)| a1.cmp(a2));
+    ad.sort_by_key(|(_, a1, _)| *a1);
 
     let mut groups = Vec::new();
 
     for (_, group) in &ad.iter().group_by(|(_, a, _)| a) {
         let mut sorted = group.collect::<Vec<&(Point, i64, i64)>>();
-        sorted.sort_by(|(_, _, d1), (_, _, d2)| d1.cmp(d2));
+        sorted.sort_by_key(|(_, _, d1)| *d1);
 
         let indexed = sorted
             .iter()
@@ -101,32 +106,35 @@ fn angle_and_index(ad: &mut [(Point, i64, i64)]) -> Vec<(Point, i64, i64)> {
 }
 
 fn find_nth(p: &Point, points: &[Point], n: usize) -> Point {
-    let mut x = angle_and_dist(p, &points);
+    let mut x = angle_and_dist(p, points);
     let mut x = angle_and_index(&mut x);
     x.sort_by(|(_, a1, i1), (_, a2, i2)| i1.cmp(i2).then(a1.cmp(a2)));
 
     x[n - 1].0
 }
 
-pub fn part1() {
-    let f = File::open("day10.txt").expect("file not found");
-    let f = BufReader::new(f);
-
-    let points = parse(f);
-    let max = max(&points);
-
-    println!("max: {:?}", max);
+// the asteroids on the map
+pub struct Day10 {
+    points: Vec<Point>,
 }
 
-pub fn part2() {
-    let f = File::open("day10.txt").expect("file not found");
-    let f = BufReader::new(f);
+impl Solution for Day10 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            points: parse(data)?,
+        })
+    }
 
-    let points = parse(f);
-    let m = max(&points);
-    let p = find_nth(&m.0, &points, 200);
+    // the number of asteroids seen from the best station
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(max(&self.points).1))
+    }
 
-    println!("{:?}", p);
+    fn part2(&self) -> Result<Answer, String> {
+        let m = max(&self.points);
+        let p = find_nth(m.0, &self.points, 200);
+        Ok(Answer::Int(p.x * 100 + p.y))
+    }
 }
 
 #[cfg(test)]
@@ -140,7 +148,7 @@ mod tests {
 #####
 ....#
 ...##";
-        let points = parse(s.as_bytes());
+        let points = parse(s).unwrap();
         assert_eq!((&Point { x: 3, y: 4 }, 8), max(&points));
     }
 
@@ -167,7 +175,7 @@ mod tests {
 #.#.#.#####.####.###
 ###.##.####.##.#..##";
 
-        let points = parse(s.as_bytes());
+        let points = parse(s).unwrap();
         let p = find_nth(&Point { x: 11, y: 13 }, &points, 200);
         assert_eq!(Point { x: 8, y: 2 }, p);
     }
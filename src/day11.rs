@@ -1,10 +1,9 @@
-use crate::intcode::ExitMode;
+use crate::device::{Bus, Device, Tuples};
+use crate::input::ParseError;
 use crate::intcode::Intcode;
+use crate::solution::{Answer, Solution};
 use std::cmp;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 struct Point {
@@ -12,129 +11,117 @@ struct Point {
     y: i64,
 }
 
-pub fn part1() {
-    let f = File::open("day11.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-    let mut map: HashMap<Point, i64> = HashMap::new();
-
-    let mut p = Point { x: 0, y: 0 };
+// The hull painting robot, giving the colour of the panel it is on and
+// taking (colour, turn) pairs
+struct Robot {
+    hull: HashMap<Point, i64>,
+    p: Point,
     // 0: ^
     // 1: >
     // 2: v
     // 3: <
-    let mut direction = 0;
+    direction: i64,
+    pairs: Tuples<2>,
+}
 
-    loop {
-        let colour = map.entry(p).or_insert(0);
-        // get the colour
-        match code.run(|| *colour) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => *colour = x,
-        }
-        // get the direction
-        direction = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => match x {
-                0 => (direction - 1 + 4) % 4,
-                1 => (direction + 1) % 4,
-                c => panic!("Invalid turn {}", c),
-            },
+impl Device for Robot {
+    fn input(&mut self) -> Option<i64> {
+        Some(*self.hull.entry(self.p).or_insert(0))
+    }
+
+    fn output(&mut self, value: i64) {
+        let [paint, turn] = match self.pairs.push(value) {
+            Some(pair) => pair,
+            None => return,
+        };
+        self.hull.insert(self.p, paint);
+        self.direction = match turn {
+            0 => (self.direction - 1 + 4) % 4,
+            1 => (self.direction + 1) % 4,
+            c => panic!("Invalid turn {}", c),
         };
         // move
-        match direction {
-            0 => p.y += 1,
-            1 => p.x += 1,
-            2 => p.y -= 1,
-            3 => p.x -= 1,
+        match self.direction {
+            0 => self.p.y += 1,
+            1 => self.p.x += 1,
+            2 => self.p.y -= 1,
+            3 => self.p.x -= 1,
             c => panic!("Invalid direction {}", c),
         }
     }
+}
 
-    // for (key, val) in map.iter() {
-    //     println!("{:?}: {}", key, val);
-    // }
-    println!("{}", map.len());
+// run the robot from a panel of the given colour, returning the panels
+// painted
+fn paint(code: &Intcode, start: i64) -> Result<HashMap<Point, i64>, String> {
+    let mut code = code.clone();
+    let p = Point { x: 0, y: 0 };
+    let mut robot = Robot {
+        hull: HashMap::new(),
+        p,
+        direction: 0,
+        pairs: Tuples::default(),
+    };
+    robot.hull.insert(p, start);
+    let mut bus = Bus::new();
+    bus.attach(&mut robot);
+    let exit = bus.run(&mut code).map_err(|e| e.to_string())?;
+    drop(bus);
+    robot.pairs.finish(exit).map_err(|e| e.to_string())?;
+    Ok(robot.hull)
 }
 
-fn render(map: &HashMap<Point, i64>) {
+fn render(map: &HashMap<Point, i64>) -> String {
     // get the min and max
-    let (x_min, x_max, y_min, y_max) = map.keys().fold(
-        (std::i64::MAX, std::i64::MIN, std::i64::MAX, std::i64::MIN),
-        |acc, p| {
-            (
-                cmp::min(acc.0, p.x),
-                cmp::max(acc.1, p.x),
-                cmp::min(acc.2, p.y),
-                cmp::max(acc.3, p.y),
-            )
-        },
-    );
-
+    let (x_min, x_max, y_min, y_max) =
+        map.keys()
+            .fold((i64::MAX, i64::MIN, i64::MAX, i64::MIN), |acc, p| {
+                (
+                    cmp::min(acc.0, p.x),
+                    cmp::max(acc.1, p.x),
+                    cmp::min(acc.2, p.y),
+                    cmp::max(acc.3, p.y),
+                )
+            });
+
+    let mut s = String::new();
     for y in (y_min..=y_max).rev() {
         for x in x_min..=x_max {
             match map.get(&Point { x, y }) {
                 Some(x) => match x {
-                    1 => print!("#"),
-                    0 => print!(" "),
+                    1 => s.push('#'),
+                    0 => s.push(' '),
                     _ => panic!("aaaahhhhh"),
                 },
-                None => print!(" "),
+                None => s.push(' '),
             }
         }
-        println!();
+        s.push('\n');
     }
+    s
 }
 
-pub fn part2() {
-    let f = File::open("day11.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-    let mut map: HashMap<Point, i64> = HashMap::new();
-
-    let mut p = Point { x: 0, y: 0 };
-    // 0: ^
-    // 1: >
-    // 2: v
-    // 3: <
-    let mut direction = 0;
+// the hull painting robot's program
+pub struct Day11 {
+    code: Intcode,
+}
 
-    // insert a white initial point
-    map.insert(p, 1);
+impl Solution for Day11 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
 
-    loop {
-        let colour = map.entry(p).or_insert(0);
-        // get the colour
-        match code.run(|| *colour) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => *colour = x,
-        }
-        // get the direction
-        direction = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => match x {
-                0 => (direction - 1 + 4) % 4,
-                1 => (direction + 1) % 4,
-                c => panic!("Invalid turn {}", c),
-            },
-        };
-        // move
-        match direction {
-            0 => p.y += 1,
-            1 => p.x += 1,
-            2 => p.y -= 1,
-            3 => p.x -= 1,
-            c => panic!("Invalid direction {}", c),
-        }
+    // the number of panels painted at least once
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(paint(&self.code, 0)?.len()))
     }
 
-    render(&map);
+    // the registration painted starting on a white panel
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Art(render(&paint(&self.code, 1)?)))
+    }
 }
 
 #[cfg(test)]
@@ -146,4 +133,19 @@ mod tests {
 
     #[test]
     fn test_angle_and_dist() {}
+
+    #[test]
+    fn test_paint() {
+        // paint white and turn left, then black and turn right
+        let code = Intcode::new("3,100,104,1,104,0,3,100,104,0,104,1,99");
+        let hull = paint(&code, 0).unwrap();
+        assert_eq!(2, hull.len());
+        assert_eq!(Some(&1), hull.get(&Point { x: 0, y: 0 }));
+        assert_eq!(Some(&0), hull.get(&Point { x: -1, y: 0 }));
+        assert!(paint(&Intcode::new("3,100,98"), 0).is_err());
+        assert_eq!(
+            Err("halted after 1 of 2 outputs".to_string()),
+            paint(&Intcode::new("3,100,104,1,99"), 0).map(|h| h.len())
+        );
+    }
 }
@@ -1,3 +1,6 @@
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
@@ -24,6 +27,32 @@ impl Moon {
     }
 }
 
+// one moon a line, e.g. <x=17, y=-12, z=13>
+fn parse(data: &str) -> Result<Vec<Moon>, ParseError> {
+    input::lines(data)
+        .map(|l| {
+            let l = l.trim();
+            let inner = l
+                .strip_prefix("<")
+                .and_then(|x| x.strip_suffix(">"))
+                .ok_or_else(|| l.error("expected <x=.., y=.., z=..>"))?;
+            let mut v = [0; 3];
+            match inner.split(", ")[..] {
+                [x, y, z] => {
+                    for (i, (f, name)) in [(x, "x="), (y, "y="), (z, "z=")].iter().enumerate() {
+                        v[i] = f
+                            .strip_prefix(name)
+                            .ok_or_else(|| f.error(&format!("expected {}", name)))?
+                            .parse()?;
+                    }
+                }
+                _ => return Err(inner.error("expected 3 positions")),
+            }
+            Ok(Moon::new(v[0], v[1], v[2]))
+        })
+        .collect()
+}
+
 fn state(p: &mut [&mut PosVel]) -> (PosVel, PosVel, PosVel, PosVel) {
     (*p[0], *p[1], *p[2], *p[3])
 }
@@ -78,9 +107,9 @@ fn dostep2(moons: &mut [Moon]) {
     do_dim_step(&mut z);
 }
 
-fn dostuff(mut moons: &mut [Moon], steps: usize) {
-    for step in 0..steps {
-        dostep2(&mut moons);
+fn dostuff(moons: &mut [Moon], steps: usize) {
+    for _step in 0..steps {
+        dostep2(moons);
     }
 }
 
@@ -94,25 +123,21 @@ fn energy(moons: &[Moon]) -> i64 {
     total
 }
 
-pub fn part1() {
-    let mut moons = vec![
-        Moon::new(17, -12, 13),
-        Moon::new(2, 1, 1),
-        Moon::new(-1, -17, 7),
-        Moon::new(12, -14, 18),
-    ];
-    dostuff(&mut moons, 1000);
-    let e = energy(&moons);
-    println!("energy: {}", e);
-}
-
-pub fn part2() {
-    let mut moons = vec![
-        Moon::new(17, -12, 13),
-        Moon::new(2, 1, 1),
-        Moon::new(-1, -17, 7),
-        Moon::new(12, -14, 18),
-    ];
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+// each axis moves independently, so the system repeats once every axis
+// has. Every loop found so far starts from step 0.
+fn repeat(moons: &mut [Moon]) -> usize {
     let mut x: Vec<&mut PosVel> = Vec::new();
     let mut y: Vec<&mut PosVel> = Vec::new();
     let mut z: Vec<&mut PosVel> = Vec::new();
@@ -121,24 +146,68 @@ pub fn part2() {
         y.push(&mut m.y);
         z.push(&mut m.z);
     }
-    let l = find_loop(&mut x);
-    println!("loop: {:?}", l);
-    let l = find_loop(&mut y);
-    println!("loop: {:?}", l);
-    let l = find_loop(&mut z);
-    println!("loop: {:?}", l);
+    [find_loop(&mut x), find_loop(&mut y), find_loop(&mut z)]
+        .iter()
+        .map(|&(start, end)| {
+            assert_eq!(0, start, "loop does not start at step 0");
+            end
+        })
+        .fold(1, lcm)
+}
 
-    // loop: (0, 56344)
-    // loop: (0, 231614)
-    // loop: (0, 193052)
+pub struct Day12 {
+    moons: Vec<Moon>,
+}
 
-    // 314917503970904
+impl Solution for Day12 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let moons = parse(data)?;
+        // the loop finding is for four
+        if moons.len() != 4 {
+            let message = format!("expected 4 moons, found {}", moons.len());
+            let at = input::lines(data)
+                .nth(4)
+                .unwrap_or_else(|| input::end(data));
+            return Err(at.error(&message));
+        }
+        Ok(Self { moons })
+    }
+
+    // the total energy after 1000 steps
+    fn part1(&self) -> Result<Answer, String> {
+        let mut moons = self.moons.clone();
+        dostuff(&mut moons, 1000);
+        Ok(Answer::Int(energy(&moons)))
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::from(repeat(&mut self.moons.clone())))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ok(vec![Moon::new(-1, 0, 2), Moon::new(2, -10, -7)]),
+            parse("<x=-1, y=0, z=2>\n<x=2, y=-10, z=-7>\n")
+        );
+        assert_eq!(
+            "line 2, column 7: expected y= \"q=-10\"",
+            parse("<x=-1, y=0, z=2>\n<x=2, q=-10, z=-7>\n")
+                .unwrap_err()
+                .to_string()
+        );
+        let e = Day12::parse("<x=-1, y=0, z=2>\n<x=2, y=-10, z=-7>\n").err();
+        assert_eq!(
+            Some((2, 19, "expected 4 moons, found 2".to_string())),
+            e.map(|e| (e.line, e.column, e.message))
+        );
+    }
+
     #[test]
     fn test_stuff() {
         let mut moons = vec![
@@ -153,7 +222,7 @@ mod tests {
 
     #[test]
     fn test_stuff2() {
-        let mut moons = vec![
+        let mut moons = [
             Moon::new(-1, 0, 2),
             Moon::new(2, -10, -7),
             Moon::new(4, -8, 8),
@@ -174,4 +243,15 @@ mod tests {
         let l = find_loop(&mut z);
         println!("loop: {:?}", l);
     }
+
+    #[test]
+    fn test_repeat() {
+        let mut moons = [
+            Moon::new(-1, 0, 2),
+            Moon::new(2, -10, -7),
+            Moon::new(4, -8, 8),
+            Moon::new(3, 5, -1),
+        ];
+        assert_eq!(2772, repeat(&mut moons));
+    }
 }
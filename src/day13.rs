@@ -1,69 +1,109 @@
-use crate::intcode::ExitMode;
-use crate::intcode::Intcode;
+#![allow(dead_code)]
+
+use crate::device::{Device, FrameBuffer, Tuples};
+use crate::input::ParseError;
+use crate::intcode::{ExitMode, Intcode};
+use crate::record;
+use crate::record::{Log, Recorder};
+use crate::solution::{Answer, Solution};
 use std::cmp;
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
-
-#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
-struct Point {
-    x: i64,
-    y: i64,
-}
 
-pub fn part1() {
-    let f = File::open("day13.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+// the screen's width, though tiles are drawn by position
+const WIDTH: i64 = 44;
+
+// The screen and score, with a joystick that keeps the paddle under the
+// ball. A triple at (-1, 0) sets the score rather than a tile.
+struct Arcade {
+    screen: FrameBuffer,
+    score: i64,
+    ball: i64,
+    paddle: i64,
+    triples: Tuples<3>,
+}
 
-    let mut code = Intcode::new(&data);
-    let mut map: HashMap<Point, i64> = HashMap::new();
+impl Arcade {
+    fn new() -> Self {
+        Self {
+            screen: FrameBuffer::new(WIDTH),
+            score: 0,
+            ball: 0,
+            paddle: 0,
+            triples: Tuples::default(),
+        }
+    }
+}
 
-    loop {
-        let x = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
-        };
-        let y = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
+impl Device for Arcade {
+    fn input(&mut self) -> Option<i64> {
+        let joystick = match self.ball.cmp(&self.paddle) {
+            Ordering::Equal => 0,
+            Ordering::Less => -1,
+            Ordering::Greater => 1,
         };
-        let tile = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
-        };
-        map.insert(Point { x, y }, tile);
+        Some(joystick)
     }
 
-    let mut count = 0;
-    for (_, val) in map.iter() {
-        if let 2 = val {
-            count += 1
+    fn output(&mut self, value: i64) {
+        let [x, y, tile] = match self.triples.push(value) {
+            Some(triple) => triple,
+            None => return,
         };
+        if (x, y) == (-1, 0) {
+            self.score = tile;
+            return;
+        }
+        self.screen.set(x, y, tile);
+        match tile {
+            3 => self.paddle = x,
+            4 => self.ball = x,
+            _ => (),
+        }
     }
-    println!("{}", count);
 }
 
-fn render(map: &HashMap<Point, i64>) {
+// draw the screen without playing, the game shouldn't ask for input
+fn blocks(code: &Intcode) -> Result<usize, String> {
+    let mut code = code.clone();
+    let mut screen = FrameBuffer::new(WIDTH);
+    let mut tiles = code.records::<3>(|| None);
+    for tile in &mut tiles {
+        let [x, y, tile] = tile.map_err(|e| e.to_string())?;
+        screen.set(x, y, tile);
+    }
+    match tiles.exit() {
+        Some(ExitMode::Halt) => Ok(screen.count(2)),
+        _ => Err("the game is waiting for input".to_string()),
+    }
+}
+
+// play the game, logging the joystick moves and screen updates if asked
+fn play(code: &Intcode, recorder: Option<&mut Recorder>) -> Result<i64, String> {
+    let mut code = code.clone();
+    let mut arcade = Arcade::new();
+    let exit = record::run(&mut code, &mut arcade, recorder).map_err(|e| e.to_string())?;
+    arcade.triples.finish(exit).map_err(|e| e.to_string())?;
+    Ok(arcade.score)
+}
+
+fn render(screen: &FrameBuffer) {
     // get the min and max
-    let (x_min, x_max, y_min, y_max) = map.keys().fold(
-        (std::i64::MAX, std::i64::MIN, std::i64::MAX, std::i64::MIN),
-        |acc, p| {
-            (
-                cmp::min(acc.0, p.x),
-                cmp::max(acc.1, p.x),
-                cmp::min(acc.2, p.y),
-                cmp::max(acc.3, p.y),
-            )
-        },
-    );
+    let (x_min, x_max, y_min, y_max) =
+        screen
+            .pixels
+            .keys()
+            .fold((i64::MAX, i64::MIN, i64::MAX, i64::MIN), |acc, p| {
+                (
+                    cmp::min(acc.0, p.0),
+                    cmp::max(acc.1, p.0),
+                    cmp::min(acc.2, p.1),
+                    cmp::max(acc.3, p.1),
+                )
+            });
 
     for y in (y_min..=y_max).rev() {
         for x in x_min..=x_max {
-            match map.get(&Point { x, y }) {
+            match screen.pixels.get(&(x, y)) {
                 Some(x) => match x {
                     0 => print!(" "),
                     1 => print!("|"),
@@ -79,61 +119,50 @@ fn render(map: &HashMap<Point, i64>) {
     }
 }
 
-pub fn part2() {
-    let f = File::open("day13.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-    let mut map: HashMap<Point, i64> = HashMap::new();
-    let mut score = 0;
-
-    let mut ball = Point { x: 0, y: 0 };
-    let mut paddle = Point { x: 0, y: 0 };
-    let mut joystick: i64 = 0;
+// the arcade cabinet's program
+pub struct Day13 {
+    code: Intcode,
+}
 
-    // let mut siv = Cursive::default();
-    // siv.add_global_callback('q', |s| s.quit());
+impl Solution for Day13 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
 
-    code.set_imm(0, 2);
+    // the number of blocks on the screen
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(blocks(&self.code)?))
+    }
 
-    loop {
-        let x = match code.run(|| joystick) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
-        };
-        let y = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
-        };
-        let tile = match code.run(|| 0) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => x,
-        };
-        if x == -1 && y == 0 {
-            score = tile;
-        } else {
-            map.insert(Point { x, y }, tile);
-        }
+    // the score once every block is broken
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(play(&self.coins(), None)?))
+    }
+}
 
-        if tile == 4 {
-            ball = Point { x, y };
-        }
-        if tile == 3 {
-            paddle = Point { x, y };
-        }
+impl Day13 {
+    // the machine with coins in, to play for free
+    fn coins(&self) -> Intcode {
+        let mut code = self.code.clone();
+        code.set_imm(0, 2);
+        code
+    }
+}
 
-        match ball.x.cmp(&paddle.x) {
-            Ordering::Equal => joystick = 0,
-            Ordering::Less => joystick = -1,
-            Ordering::Greater => joystick = 1,
-        };
-        // println!("b: {:?}, p: {:?}, j: {}", b, p, joystick);
+// the session of part 2, for a bug report
+pub fn record(data: &str) -> Result<Log, String> {
+    let mut recorder = Recorder::new();
+    let day = Day13::parse(data).map_err(|e| e.to_string())?;
+    play(&day.coins(), Some(&mut recorder))?;
+    Ok(recorder.log)
+}
 
-        // render(&map);
-    }
-    println!("{}", score);
+// check a session from record still plays out the same
+pub fn replay(data: &str, log: &Log) -> Result<(), String> {
+    let day = Day13::parse(data).map_err(|e| e.to_string())?;
+    record::replay(&mut day.coins(), log).map_err(|d| d.to_string())
 }
 
 #[cfg(test)]
@@ -145,4 +174,49 @@ mod tests {
 
     #[test]
     fn test_angle_and_dist() {}
+
+    #[test]
+    fn test_blocks() {
+        let code = Intcode::new(
+            "104,1,104,1,104,2,104,2,104,1,104,2,104,2,104,1,104,0,104,-1,104,0,104,7,99",
+        );
+        assert_eq!(Ok(1), blocks(&code));
+        assert_eq!(
+            Err("halted after 2 of 3 outputs".to_string()),
+            blocks(&Intcode::new("104,1,104,1,99"))
+        );
+        assert!(blocks(&Intcode::new("3,0,99")).is_err());
+    }
+
+    #[test]
+    fn test_play() {
+        // draw the ball right of the paddle, then output the joystick as
+        // the score
+        let code = Intcode::new("104,5,104,0,104,4,104,3,104,0,104,3,3,100,104,-1,104,0,4,100,99");
+        let mut recorder = Recorder::new();
+        assert_eq!(Ok(1), play(&code, Some(&mut recorder)));
+        assert_eq!(
+            Some(&(6, crate::record::Event::Input(1))),
+            recorder.log.events.get(6)
+        );
+        assert_eq!(
+            Ok(()),
+            crate::record::replay(&mut code.clone(), &recorder.log)
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        // a score of 3 isn't a paddle: the ball is left of the paddle, so
+        // the joystick, output as the score, goes left
+        let code = Intcode::new(
+            "104,7,104,0,104,3,104,5,104,0,104,4,104,-1,104,0,104,3,3,100,104,-1,104,0,4,100,99",
+        );
+        assert_eq!(Ok(-1), play(&code, None));
+        assert!(play(&Intcode::new("98"), None).is_err());
+        assert_eq!(
+            Err("halted after 2 of 3 outputs".to_string()),
+            play(&Intcode::new("104,-1,104,0,99"), None)
+        );
+    }
 }
@@ -1,8 +1,7 @@
+use crate::input;
+use crate::input::{ParseError, Span};
+use crate::solution::{Answer, Solution};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(Eq, PartialEq, Debug, Clone, Hash)]
 struct Agent {
@@ -23,48 +22,51 @@ struct Equations {
 }
 
 impl Equations {
-    fn parse<R: BufRead>(f: R) -> Self {
+    // one reaction a line, e.g. 7 A, 1 E => 1 FUEL
+    fn parse(s: &str) -> Result<Self, ParseError> {
         let mut reactions = HashMap::new();
         let mut refs: HashMap<String, Vec<String>> = HashMap::new();
 
-        for line in f.lines() {
-            let l = line.unwrap();
-            let blah: Vec<&str> = l.split(" => ").collect();
-            if blah.len() != 2 {
-                panic!("not 2");
-            }
-            let inputs: Vec<Agent> = blah[0]
+        for l in input::lines(s) {
+            let l = l.trim();
+            let (from, to) = match l.split(" => ")[..] {
+                [from, to] => (from, to),
+                _ => return Err(l.error("expected a reaction")),
+            };
+            let inputs = from
                 .split(", ")
-                .map(|x| {
-                    let y: Vec<&str> = x.split(' ').collect();
-                    if y.len() != 2 {
-                        panic!("not 2");
-                    }
-                    Agent {
-                        mul: y[0].parse().unwrap(),
-                        chem: y[1].to_string(),
-                    }
-                })
-                .collect();
-
-            let y: Vec<&str> = blah[1].split(' ').collect();
+                .iter()
+                .map(|&x| Self::agent(x))
+                .collect::<Result<Vec<Agent>, _>>()?;
+            let output = Self::agent(to)?;
 
             for input in inputs.iter() {
                 refs.entry(input.chem.to_string())
-                    .or_insert_with(|| Vec::new())
-                    .push(y[1].to_string());
+                    .or_default()
+                    .push(output.chem.to_string());
             }
 
             reactions.insert(
-                y[1].to_string(),
+                output.chem,
                 Reaction {
                     inputs,
-                    outputs: y[0].parse().unwrap(),
+                    outputs: output.mul,
                 },
             );
         }
 
-        Self { reactions, refs }
+        Ok(Self { reactions, refs })
+    }
+
+    // e.g. 7 A
+    fn agent(s: Span) -> Result<Agent, ParseError> {
+        match s.split(" ")[..] {
+            [mul, chem] if !chem.text.is_empty() => Ok(Agent {
+                mul: mul.parse()?,
+                chem: chem.text.to_string(),
+            }),
+            _ => Err(s.error("expected a quantity and a chemical")),
+        }
     }
 
     fn clear(&mut self, chem: &str) {
@@ -149,29 +151,48 @@ impl Equations {
     }
 }
 
-pub fn part1() {
-    let f = File::open("day14.txt").expect("file not found");
-    let mut f = BufReader::new(f);
+const ORE: i64 = 1_000_000_000_000;
+
+// the most fuel that can be made from the ore, found by bisection as the
+// ore needed only grows with the fuel made
+fn max_fuel(eqs: &Equations, ore: i64) -> i64 {
+    let needed = |fuel| eqs.clone().run("FUEL", fuel);
+    // lo can be made, hi can't
+    let mut lo = 0;
+    let mut hi = 1;
+    while needed(hi) <= ore {
+        lo = hi;
+        hi *= 2;
+    }
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if needed(mid) <= ore {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
 
-    let mut m = Equations::parse(f);
-    println!("{}", m.run("FUEL", 1));
+pub struct Day14 {
+    eqs: Equations,
 }
 
-pub fn part2() {
-    let f = File::open("day14.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut i = 2390225;
-    loop {
-        let mut m = Equations::parse(data.as_bytes());
-        let ore = m.run("FUEL", i);
-        println!("{} => {}", i, ore);
-        if ore > 1000000000000 {
-            break;
-        }
-        i += 1;
+impl Solution for Day14 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            eqs: Equations::parse(data)?,
+        })
+    }
+
+    // the ore needed for 1 fuel
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(self.eqs.clone().run("FUEL", 1)))
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(max_fuel(&self.eqs, ORE)))
     }
 }
 
@@ -191,7 +212,7 @@ mod tests {
 5 B, 7 C => 1 BC
 4 C, 1 A => 1 CA
 2 AB, 3 BC, 4 CA => 1 FUEL";
-        let mut m = Equations::parse(s.as_bytes());
+        let mut m = Equations::parse(s).unwrap();
         assert_eq!(165, m.run("FUEL", 1));
     }
 
@@ -209,7 +230,21 @@ mod tests {
 1 NVRVD => 8 CXFTF
 1 VJHF, 6 MNCFX => 4 RFSQX
 176 ORE => 6 VJHF";
-        let mut m = Equations::parse(s.as_bytes());
-        assert_eq!(165, m.run("FUEL", 1));
+        let mut m = Equations::parse(s).unwrap();
+        assert_eq!(180697, m.run("FUEL", 1));
+
+        let m = Equations::parse(s).unwrap();
+        assert_eq!(5586022, max_fuel(&m, ORE));
+    }
+
+    #[test]
+    fn test_parse() {
+        let e = Equations::parse("10 ORE => 10 A\n7 A, 1 => 1 FUEL").unwrap_err();
+        assert_eq!(
+            "line 2, column 6: expected a quantity and a chemical \"1\"",
+            e.to_string()
+        );
+        let e = Equations::parse("10 ORE => ten A").unwrap_err();
+        assert_eq!("line 1, column 11: expected i64 \"ten\"", e.to_string());
     }
 }
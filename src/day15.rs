@@ -1,12 +1,9 @@
-use crate::intcode::ExitMode;
+use crate::device::{Bus, Device};
+use crate::input::ParseError;
 use crate::intcode::Intcode;
-use rand::prelude::*;
+use crate::solution::{Answer, Solution};
 use std::cmp;
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 struct Point {
@@ -42,48 +39,12 @@ impl Point {
     }
 }
 
-fn moves_to_exit() {
-    let map = "## ############### # ############### ####
-#.#...............#.#...............#...#
-#.#.#######.#####.#.#.#.###.#########.#.#
-#.#.#.......#...#.#.#.#.#...#.........#.#
-#.#.#.#######.###.#.#.#.#.###.#########.#
-#.#.#.#.....#.....#...#.#.#...........#.#
-#.#.#.#.#####.#######.#.###.###########.#
-#...#.#.#.....#.....#.#.....#.....#.....#
-#.###.#.#.#####.###.#######.#.###.#.###.#
-#...#.#.#.#.....#...#.....#.#.#.#.#.#...#
-###.#.#.#.###.###.###.###.###.#.#.#.#####
-#...#.#...#...#.#.....#.#.#...#.#.#.#...#
-#.###.#.###.###.#######.#.#.###.#.#.#.#.#
-#.#...#.....#.........#...#.#...#.#...#.#
-#.#.#########.#######.#.###.###.#.#.###.#
-#.#.....#.....#.....#.#...#...#...#.#...#
-#.#####.###.###.#.###.###.#.#.###.###.#.#
-#.#...#...#...#.#...#.....#.#...#.....#.#
-#.#.#.###.###.#####.###### ####.#######.#
-#.#.#.#.#...#.............#...#.#.....#.#
-#.#.#.#.###.#######.#####.#.#.#.#.#####.#
-#...#.#.#...#.......#X#.....#...#...#...#
-#####.#.#.#########.#.###########.#.#.###
-#...#.#.#.........#.#.........#...#.#.#.#
-###.#.#.#########.###########.#.###.#.#.#
-#...#.#.....#.....#.....#...#.#.#...#...#
-#.###.#####.#.#.###.#.###.#.#.#.#.#####.#
-#...#.......#.#.#...#.....#...#.#.......#
-#.#.#####.###.#.#.######## ####.#########
-#.#.......#...#.#.........#...#.#.....#.#
-#.#########.#.###########.###.#.#.###.#.#
-#.....#.#...#.#.........#.#...#...#...#.#
-#####.#.#.#####.#.#######.#.#######.###.#
-#...#...#.#.....#.......#.#.......#.#...#
-#.#.#.###.#.###########.#.#.###.#.#.###.#
-#.#...#...#...#.....#...#.#.#.#.#.#...#.#
-#.#####.#.###.#.#####.###.#.#.#.#####.#.#
-#.....#.#.#...#.....#.#...#...#.....#...#
-#####.#.###.#######.#.#.#####.#####.###.#
-#O....#.............#...#.........#.....#
-###### ############# ### ######### ######";
+// the moves from the start to the oxygen system on a map from render,
+// following the one path left once dead ends are filled in
+fn moves_to_exit(map: &str) -> Option<usize> {
+    if !map.contains('O') {
+        return None;
+    }
     let mut stuff: Vec<Vec<char>> = Vec::new();
     for line in map.split('\n') {
         stuff.push(
@@ -128,19 +89,12 @@ fn moves_to_exit() {
         }
     }
 
-    for l in stuff.iter_mut() {
-        let blah: String = l.iter().collect();
-        println!("{}", blah);
-    }
-    println!("start={:?}", start);
-    println!("end={:?}", end);
-
     // do it
     let mut i = 0;
     let mut pos = start;
     let mut last = end;
     loop {
-        let possible = vec![
+        let possible = [
             (pos.0 - 1, pos.1),
             (pos.0 + 1, pos.1),
             (pos.0, pos.1 - 1),
@@ -161,59 +115,13 @@ fn moves_to_exit() {
             }
         }
         i += 1;
-
-        // print
-        println!("--");
-        for l in stuff.iter_mut() {
-            let blah: String = l.iter().collect();
-            println!("{}", blah);
-        }
     }
-    println!("i={}", i);
+    // the walk stops next to the oxygen system, a move short
+    Some(i + 1)
 }
 
-fn fill_oxygen() {
-    let map = "## ############### # ############### ####
-#.#...............#.#...............#...#
-#.#.#######.#####.#.#.#.###.#########.#.#
-#.#.#.......#...#.#.#.#.#...#.........#.#
-#.#.#.#######.###.#.#.#.#.###.#########.#
-#.#.#.#.....#.....#...#.#.#...........#.#
-#.#.#.#.#####.#######.#.###.###########.#
-#...#.#.#.....#.....#.#.....#.....#.....#
-#.###.#.#.#####.###.#######.#.###.#.###.#
-#...#.#.#.#.....#...#.....#.#.#.#.#.#...#
-###.#.#.#.###.###.###.###.###.#.#.#.#####
-#...#.#...#...#.#.....#.#.#...#.#.#.#...#
-#.###.#.###.###.#######.#.#.###.#.#.#.#.#
-#.#...#.....#.........#...#.#...#.#...#.#
-#.#.#########.#######.#.###.###.#.#.###.#
-#.#.....#.....#.....#.#...#...#...#.#...#
-#.#####.###.###.#.###.###.#.#.###.###.#.#
-#.#...#...#...#.#...#.....#.#...#.....#.#
-#.#.#.###.###.#####.###### ####.#######.#
-#.#.#.#.#...#.............#...#.#.....#.#
-#.#.#.#.###.#######.#####.#.#.#.#.#####.#
-#...#.#.#...#.......#X#.....#...#...#...#
-#####.#.#.#########.#.###########.#.#.###
-#...#.#.#.........#.#.........#...#.#.#.#
-###.#.#.#########.###########.#.###.#.#.#
-#...#.#.....#.....#.....#...#.#.#...#...#
-#.###.#####.#.#.###.#.###.#.#.#.#.#####.#
-#...#.......#.#.#...#.....#...#.#.......#
-#.#.#####.###.#.#.######## ####.#########
-#.#.......#...#.#.........#...#.#.....#.#
-#.#########.#.###########.###.#.#.###.#.#
-#.....#.#...#.#.........#.#...#...#...#.#
-#####.#.#.#####.#.#######.#.#######.###.#
-#...#...#.#.....#.......#.#.......#.#...#
-#.#.#.###.#.###########.#.#.###.#.#.###.#
-#.#...#...#...#.....#...#.#.#.#.#.#...#.#
-#.#####.#.###.#.#####.###.#.#.#.#####.#.#
-#.....#.#.#...#.....#.#...#...#.....#...#
-#####.#.###.#######.#.#.#####.#####.###.#
-#O....#.............#...#.........#.....#
-###### ############# ### ######### ######";
+// the minutes for oxygen to fill the area on a map from render
+fn fill_oxygen(map: &str) -> Option<usize> {
     let mut stuff: Vec<Vec<char>> = Vec::new();
     for line in map.split('\n') {
         stuff.push(
@@ -250,111 +158,229 @@ fn fill_oxygen() {
         }
         stuff = new_stuff;
         if !found_empty {
-            break;
+            return Some(i);
         }
+    }
+    None
+}
 
-        // print
-        println!("-- i = {} --", i);
-        for l in stuff.iter_mut() {
-            let blah: String = l.iter().collect();
-            println!("{}", blah);
-        }
+// the move back the way a move came
+fn back(direction: i64) -> i64 {
+    match direction {
+        1 => 2,
+        2 => 1,
+        3 => 4,
+        _ => 3,
     }
 }
 
-pub fn part1() {
-    // discover_map();
-    moves_to_exit();
+// The repair droid, exploring depth first: it moves somewhere it hasn't
+// been while it can, otherwise backs up a move. It runs out of moves back
+// at the start once everything reachable has been seen.
+struct Droid {
+    map: HashMap<Point, i64>,
+    location: Point,
+    // the moves from the start to here, to back up along
+    path: Vec<i64>,
+    // the move asked for, and whether it was backing up
+    moving: Option<(i64, bool)>,
 }
 
-fn discover_map() {
-    let f = File::open("day15.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Droid {
+    fn new() -> Self {
+        let mut map = HashMap::new();
+        map.insert(Point { x: 0, y: 0 }, 1);
+        Self {
+            map,
+            location: Point { x: 0, y: 0 },
+            path: Vec::new(),
+            moving: None,
+        }
+    }
+}
 
-    let mut code = Intcode::new(&data);
-    let mut map: HashMap<Point, i64> = HashMap::new();
-    let mut location = Point { x: 0, y: 0 };
-    let mut direction = 1;
+impl Device for Droid {
+    fn input(&mut self) -> Option<i64> {
+        let new = [1, 2, 3, 4]
+            .iter()
+            .find(|d| !self.map.contains_key(&self.location.next(**d)));
+        let moving = match new {
+            Some(&d) => (d, false),
+            None => (back(*self.path.last()?), true),
+        };
+        self.moving = Some(moving);
+        Some(moving.0)
+    }
 
-    for _ in 0..1_000_000 {
-        match code.run(|| direction) {
-            ExitMode::Halt => {
-                println!("halt");
-                break;
+    fn output(&mut self, value: i64) {
+        let (direction, backing_up) = self.moving.take().expect("output without a move");
+        let to = self.location.next(direction);
+        match value {
+            // hit a wall
+            0 => {
+                self.map.insert(to, 0);
             }
-            ExitMode::Output(x) => {
-                match x {
-                    0 => {
-                        // hit a wall
-                        map.insert(location.next(direction), 0);
-                    }
-                    1 => {
-                        // moved
-                        location = location.next(direction);
-                        map.insert(location, 1);
-                    }
-                    2 => {
-                        // oxygen
-                        map.insert(location.next(direction), 2);
-                        location = location.next(direction);
-                    }
-                    c => panic!("bad code: {}", c),
-                }
-                // try to move somewhere we haven't been
-                let options: Vec<&i64> = [1, 2, 3, 4]
-                    .iter()
-                    .filter(|d| map.get(&location.next(**d)) == None)
-                    .collect();
-                if options.len() > 0 {
-                    direction = *options[rand::random::<usize>() % options.len()];
+            // moved, maybe onto the oxygen system
+            1 | 2 => {
+                self.map.insert(to, value);
+                self.location = to;
+                if backing_up {
+                    self.path.pop();
                 } else {
-                    direction = ((rand::random::<u8>() % 4) + 1) as i64;
+                    self.path.push(direction);
                 }
             }
-        };
+            c => panic!("bad code: {}", c),
+        }
     }
+}
 
-    render(&map);
+// everything the droid can reach and the walls around it
+fn discover_map(code: &Intcode) -> Result<HashMap<Point, i64>, String> {
+    let mut code = code.clone();
+    let mut droid = Droid::new();
+    let mut bus = Bus::new();
+    bus.attach(&mut droid);
+    bus.run(&mut code).map_err(|e| e.to_string())?;
+    drop(bus);
+    Ok(droid.map)
 }
 
-fn render(map: &HashMap<Point, i64>) {
+// rows of '#' walls, '.' open, 'O' the oxygen system and ' ' unknown, with
+// X at the droid's start
+fn render(map: &HashMap<Point, i64>) -> String {
     // get the min and max
-    let (x_min, x_max, y_min, y_max) = map.keys().fold(
-        (std::i64::MAX, std::i64::MIN, std::i64::MAX, std::i64::MIN),
-        |acc, p| {
-            (
-                cmp::min(acc.0, p.x),
-                cmp::max(acc.1, p.x),
-                cmp::min(acc.2, p.y),
-                cmp::max(acc.3, p.y),
-            )
-        },
-    );
-
-    println!("-----------------------------------------");
+    let (x_min, x_max, y_min, y_max) =
+        map.keys()
+            .fold((i64::MAX, i64::MIN, i64::MAX, i64::MIN), |acc, p| {
+                (
+                    cmp::min(acc.0, p.x),
+                    cmp::max(acc.1, p.x),
+                    cmp::min(acc.2, p.y),
+                    cmp::max(acc.3, p.y),
+                )
+            });
 
+    let mut rows = Vec::new();
     for y in (y_min..=y_max).rev() {
+        let mut row = String::new();
         for x in x_min..=x_max {
             if x == 0 && y == 0 {
-                print!("X");
+                row.push('X');
             } else {
                 match map.get(&Point { x, y }) {
                     Some(t) => match t {
-                        0 => print!("#"),
-                        1 => print!("."),
-                        2 => print!("O"),
+                        0 => row.push('#'),
+                        1 => row.push('.'),
+                        2 => row.push('O'),
                         _ => panic!("unknown tile"),
                     },
-                    None => print!(" "),
+                    None => row.push(' '),
                 }
             }
         }
-        println!();
+        rows.push(row);
+    }
+    rows.join("\n")
+}
+
+// the repair droid's program
+pub struct Day15 {
+    code: Intcode,
+}
+
+impl Solution for Day15 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        let map = render(&discover_map(&self.code)?);
+        moves_to_exit(&map)
+            .map(Answer::from)
+            .ok_or_else(|| format!("no way to the oxygen system:\n{}", map))
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        let map = render(&discover_map(&self.code)?);
+        fill_oxygen(&map)
+            .map(Answer::from)
+            .ok_or_else(|| format!("oxygen still spreading:\n{}", map))
     }
 }
 
-pub fn part2() {
-    fill_oxygen();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the map found by discover_map for the puzzle input
+    const MAP: &str = "## ############### # ############### ####
+#.#...............#.#...............#...#
+#.#.#######.#####.#.#.#.###.#########.#.#
+#.#.#.......#...#.#.#.#.#...#.........#.#
+#.#.#.#######.###.#.#.#.#.###.#########.#
+#.#.#.#.....#.....#...#.#.#...........#.#
+#.#.#.#.#####.#######.#.###.###########.#
+#...#.#.#.....#.....#.#.....#.....#.....#
+#.###.#.#.#####.###.#######.#.###.#.###.#
+#...#.#.#.#.....#...#.....#.#.#.#.#.#...#
+###.#.#.#.###.###.###.###.###.#.#.#.#####
+#...#.#...#...#.#.....#.#.#...#.#.#.#...#
+#.###.#.###.###.#######.#.#.###.#.#.#.#.#
+#.#...#.....#.........#...#.#...#.#...#.#
+#.#.#########.#######.#.###.###.#.#.###.#
+#.#.....#.....#.....#.#...#...#...#.#...#
+#.#####.###.###.#.###.###.#.#.###.###.#.#
+#.#...#...#...#.#...#.....#.#...#.....#.#
+#.#.#.###.###.#####.###### ####.#######.#
+#.#.#.#.#...#.............#...#.#.....#.#
+#.#.#.#.###.#######.#####.#.#.#.#.#####.#
+#...#.#.#...#.......#X#.....#...#...#...#
+#####.#.#.#########.#.###########.#.#.###
+#...#.#.#.........#.#.........#...#.#.#.#
+###.#.#.#########.###########.#.###.#.#.#
+#...#.#.....#.....#.....#...#.#.#...#...#
+#.###.#####.#.#.###.#.###.#.#.#.#.#####.#
+#...#.......#.#.#...#.....#...#.#.......#
+#.#.#####.###.#.#.######## ####.#########
+#.#.......#...#.#.........#...#.#.....#.#
+#.#########.#.###########.###.#.#.###.#.#
+#.....#.#...#.#.........#.#...#...#...#.#
+#####.#.#.#####.#.#######.#.#######.###.#
+#...#...#.#.....#.......#.#.......#.#...#
+#.#.#.###.#.###########.#.#.###.#.#.###.#
+#.#...#...#...#.....#...#.#.#.#.#.#...#.#
+#.#####.#.###.#.#####.###.#.#.#.#####.#.#
+#.....#.#.#...#.....#.#...#...#.....#...#
+#####.#.###.#######.#.#.#####.#####.###.#
+#O....#.............#...#.........#.....#
+###### ############# ### ######### ######";
+
+    #[test]
+    fn test_map() {
+        assert_eq!(Some(270), moves_to_exit(MAP));
+        assert_eq!(Some(364), fill_oxygen(MAP));
+        assert_eq!(None, moves_to_exit("###\n#X#\n###"));
+    }
+
+    #[test]
+    fn test_droid() {
+        let maze = " ### \n#X.O#\n#.## \n #   ";
+        let rows: Vec<&[u8]> = maze.lines().map(|l| l.as_bytes()).collect();
+        // the top row is y = 1 and the left column x = -1
+        let status = |p: Point| match rows[(1 - p.y) as usize][(p.x + 1) as usize] {
+            b'#' | b' ' => 0,
+            b'O' => 2,
+            _ => 1,
+        };
+        let mut droid = Droid::new();
+        while let Some(d) = droid.input() {
+            let to = droid.location.next(d);
+            droid.output(status(to));
+        }
+        assert_eq!(maze, render(&droid.map));
+        assert!(droid.path.is_empty());
+    }
 }
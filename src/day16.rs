@@ -1,12 +1,11 @@
-use std::cmp;
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+#![allow(dead_code)]
+
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 
 struct Matrix {
-    m: Vec<Vec<i32>>
+    m: Vec<Vec<i32>>,
 }
 
 fn mul(a: &[i32], b: &[i32]) -> Vec<i32> {
@@ -48,10 +47,7 @@ fn invert(m: &[Vec<i32>]) -> Vec<Vec<i32>> {
 
     let mut v: Vec<Vec<i32>> = Vec::new();
     for c in 0..cols {
-        let mut row: Vec<i32> = Vec::new();
-        for r in 0..rows {
-            row.push(m[r][c]);
-        }
+        let row: Vec<i32> = m.iter().map(|r| r[c]).collect();
         v.push(row);
     }
     v
@@ -81,13 +77,13 @@ fn matrix_scalar_mul(m: &[Vec<i32>], s: &[i32], sum: fn(&[i32]) -> i32) -> Vec<i
                 s.len()
             );
         }
-        v[i] = sum(&mul(&m[i], &s));
+        v[i] = sum(&mul(&m[i], s));
     }
     v
 }
 
 fn pattern(round: usize, size: usize) -> Vec<i32> {
-    let base = vec![0, 1, 0, -1];
+    let base = [0, 1, 0, -1];
     let mut patt = vec![0; size + 1];
     for i in 0..=(size + 1 / round) {
         let x = base[i % 4];
@@ -105,8 +101,7 @@ fn pattern(round: usize, size: usize) -> Vec<i32> {
 fn phases(v: &[i32], n: usize) -> Vec<i32> {
     let pat = pattern_matrix(v.len());
     let mut v = v.to_vec();
-    for i in 0..n {
-        println!("{}", i);
+    for _ in 0..n {
         v = matrix_scalar_mul(&pat, &v, sum_abs_mod);
     }
     v
@@ -115,32 +110,36 @@ fn phases(v: &[i32], n: usize) -> Vec<i32> {
 fn phases_sum(v: &[i32], n: usize, sum: fn(&[i32]) -> i32) -> Vec<i32> {
     let pat = pattern_matrix(v.len());
     let mut v = v.to_vec();
-    for i in 0..n {
-        println!("{}", i);
+    for _ in 0..n {
         v = matrix_scalar_mul(&pat, &v, sum);
     }
     v
 }
 
-pub fn part1() {
-    let f = File::open("day16.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let input: Vec<i32> = data
-        .trim()
-        .chars()
-        .map(|x| x.to_string().parse().unwrap())
-        .collect();
+// the input signal
+pub struct Day16 {
+    signal: Vec<i32>,
+}
 
-    let output = phases(&input, 100);
+impl Solution for Day16 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let signal = input::digits(data)?
+            .iter()
+            .flatten()
+            .map(|&d| d as i32)
+            .collect();
+        Ok(Self { signal })
+    }
 
-    println!("{:?}", &output[..8]);
+    // the first eight digits after 100 phases
+    fn part1(&self) -> Result<Answer, String> {
+        let output = phases(&self.signal, 100);
+        Ok(Answer::Text(
+            output[..8].iter().map(|d| d.to_string()).collect(),
+        ))
+    }
 }
 
-pub fn part2() {}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,12 +171,19 @@ mod tests {
     }
 
     #[test]
+    #[ignore = "the phases grow without taking each mod 10, so the first digits are wrong"]
     fn test_phases_sum() {
         let x = phases_sum(&[1, 2, 3, 4, 5, 6, 7, 8], 4, sum);
         let x: Vec<i32> = x.iter().map(|x| x.abs() % 10).collect();
         assert_eq!(&[0, 1, 0, 2, 9, 4, 9, 8], &x[..8],)
     }
 
+    #[test]
+    fn test_phases_sum_abs_mod() {
+        let x = phases_sum(&[1, 2, 3, 4, 5, 6, 7, 8], 4, sum_abs_mod);
+        assert_eq!(&[0, 1, 0, 2, 9, 4, 9, 8], &x[..8],)
+    }
+
     #[test]
     fn test_phases2() {
         let x = phases(&[1, 2, 3, 4, 5, 6, 7, 8], 1);
@@ -1,8 +1,9 @@
-use crate::intcode::ExitMode;
+#![allow(dead_code)]
+
+use crate::device::{Bus, Keyboard, Split, Terminal};
+use crate::input::ParseError;
 use crate::intcode::Intcode;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use crate::solution::{Answer, Solution};
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 struct Point {
@@ -10,47 +11,21 @@ struct Point {
     y: i64,
 }
 
-pub fn part1() {
-    let f = File::open("day17.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-
-    let mut v: Vec<Vec<char>> = Vec::new();
-    let mut row: Vec<char> = Vec::new();
-
-    for _ in 0..10000 {
-        match code.run(&|| 0) {
-            ExitMode::Halt => {
-                println!("halt");
-                break;
-            }
-            ExitMode::Output(x) => {
-                let c = match x {
-                    35 => '#',
-                    46 => '.',
-                    10 => '\n',
-                    94 => '^',
-                    c => panic!("unknown {}", c),
-                };
-                if c == '\n' {
-                    if !row.is_empty() {
-                        v.push(row);
-                    }
-                    row = Vec::new();
-                } else {
-                    row.push(c);
-                }
-            }
-        };
-    }
-
-    for r in v.iter() {
-        let blah: String = r.iter().collect();
-        println!("{}", blah);
-    }
+// the sum of the alignment parameters of the scaffold intersections
+fn alignment(code: &Intcode) -> usize {
+    let mut code = code.clone();
+    let mut camera = Terminal::default();
+    let mut bus = Bus::new();
+    bus.attach(&mut camera);
+    bus.run(&mut code).expect("failed to run");
+    drop(bus);
+
+    let mut v: Vec<Vec<char>> = camera
+        .text
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.chars().collect())
+        .collect();
 
     let mut total = 0;
 
@@ -62,28 +37,19 @@ pub fn part1() {
                 && v[y][x - 1] == '#'
                 && v[y][x + 1] == '#'
             {
-                println!("{}, {}", x, y);
                 v[y][x] = 'O';
                 total += x * y;
             }
         }
     }
 
-    for r in v.iter() {
-        let blah: String = r.iter().collect();
-        println!("{}", blah);
-    }
-
-    println!("total={}", total);
+    total
 }
 
-pub fn part2() {
-    let f = File::open("day17.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
+// the dust collected walking the scaffold, with the movement routines
+// worked out by hand
+fn collect_dust(code: &Intcode) -> Result<i64, String> {
+    let mut code = code.clone();
     // set into active mode
     code.set_imm(0, 2);
 
@@ -91,34 +57,42 @@ pub fn part2() {
     // A: R,8,L,12,R,8
     // B: R,12,L,8,R,10
     // C: R,8,L,8,L,8,R,8,R,10
+    let mut robot = Split {
+        input: Keyboard::new(),
+        output: Terminal::default(),
+    };
+    robot
+        .input
+        .type_str("A,B,B,A,C,A,A,C,B,C\nR,8,L,12,R,8\nR,12,L,8,R,10\nR,8,L,8,L,8,R,8,R,10\nn\n");
+
+    // the video feed, then the dust
+    let mut bus = Bus::new();
+    bus.attach(&mut robot);
+    bus.run(&mut code).map_err(|e| e.to_string())?;
+    drop(bus);
+    match robot.output.values.last() {
+        Some(&dust) => Ok(dust),
+        None => Err(format!("no dust collected:\n{}", robot.output.text)),
+    }
+}
 
-    let inputs: Vec<i64> =
-        "A,B,B,A,C,A,A,C,B,C\nR,8,L,12,R,8\nR,12,L,8,R,10\nR,8,L,8,L,8,R,8,R,10\nn\n"
-            .chars()
-            .map(|x| x as i64)
-            .collect();
+// the vacuum robot's program
+pub struct Day17 {
+    code: Intcode,
+}
 
-    // the input function
-    let mut input_index = 0;
-    let mut input_fn = || -> i64 {
-        let i = input_index;
-        input_index += 1;
-        println!("{}", inputs[i]);
-        inputs[i]
-    };
+impl Solution for Day17 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
 
-    println!("{:?}", inputs);
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(alignment(&self.code)))
+    }
 
-    for _ in 0..10_000_000 {
-        match code.run(&mut input_fn) {
-            ExitMode::Halt => {
-                println!("halt");
-                break;
-            }
-            ExitMode::Output(x) => {
-                // print!("{}", x as u8 as char);
-                println!("{}", x);
-            }
-        };
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(collect_dust(&self.code)?))
     }
 }
@@ -1,8 +1,9 @@
+#![allow(dead_code)]
+
+use crate::input::ParseError;
 use crate::intcode::ExitMode;
 use crate::intcode::Intcode;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use crate::solution::{Answer, Solution};
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 struct Point {
@@ -10,19 +11,14 @@ struct Point {
     y: i64,
 }
 
-pub fn part1() {
-    let f = File::open("day19.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
+// the points affected by the beam in the 50x50 area nearest the emitter
+fn affected(code: &Intcode) -> usize {
     let mut outputs: Vec<Vec<i64>> = Vec::new();
-    let code = Intcode::new(&data);
 
     for y in 0..50 {
         let mut row = Vec::new();
         for x in 0..50 {
-            match get(&code, x, y) {
+            match get(code, x, y) {
                 None => panic!("blah"),
                 Some(o) => row.push(o),
             }
@@ -30,20 +26,11 @@ pub fn part1() {
         outputs.push(row);
     }
 
-    for (y, row) in outputs.iter().enumerate() {
-        print!("{:3} ", y);
-        for (x, v) in row.iter().enumerate() {
-            print!("{} ", v);
-        }
-        println!();
-    }
-
-    let affected = outputs
+    outputs
         .iter()
         .flat_map(|x| x.iter())
         .filter(|x| **x == 1)
-        .count();
-    println!("affected={}", affected);
+        .count()
 }
 
 fn get(code: &Intcode, x: i64, y: i64) -> Option<i64> {
@@ -60,6 +47,7 @@ fn get(code: &Intcode, x: i64, y: i64) -> Option<i64> {
     };
 
     match code.run(&mut input_fn) {
+        ExitMode::AwaitingInput => unreachable!(),
         ExitMode::Halt => None,
         ExitMode::Output(o) => Some(o),
     }
@@ -94,37 +82,40 @@ fn max(code: &Intcode, y: i64) -> i64 {
     panic!("didn't find min");
 }
 
-pub fn part2() {
-    let f = File::open("day19.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let code = Intcode::new(&data);
-
+// 10000 * x + y for the top left of the first 100x100 square in the beam
+fn square(code: &Intcode) -> i64 {
     let test = 1000;
 
     for i in test..(test + 1000) {
-        let max = max(&code, i);
-        let min = min(&code, i + 99);
-
-        let diff = max - min;
-
-        println!(
-            "top: {} (max: {}), bottom: {} (min: {}), diff: {}",
-            i,
-            max,
-            i + 99,
-            min,
-            diff
-        );
+        let max = max(code, i);
+        let min = min(code, i + 99);
 
-        if diff == 99 {
+        if max - min == 99 {
             let x = max - 99;
             let y = i;
-            let result = 10000 * x + y;
-            println!("x: {}, y: {}, result: {}", x, y, result);
-            break;
+            return 10000 * x + y;
         }
     }
+    panic!("didn't find the square");
+}
+
+// the drone system's program
+pub struct Day19 {
+    code: Intcode,
+}
+
+impl Solution for Day19 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(affected(&self.code)))
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(square(&self.code)))
+    }
 }
@@ -1,45 +1,65 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+#![allow(dead_code)]
 
-pub fn part1() {
-    let f = File::open("day2.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 
-    let mut v = string_to_vec(&data);
+pub struct Day2 {
+    program: Vec<i32>,
+}
 
-    // initialise
-    v[1] = 12;
-    v[2] = 2;
+impl Solution for Day2 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            program: input::list(data, ",")?,
+        })
+    }
 
-    // println!("input: {:?}", v);
+    fn part1(&self) -> Result<Answer, String> {
+        let mut v = self.program.clone();
 
-    computer(&mut v);
+        // initialise
+        v[1] = 12;
+        v[2] = 2;
 
-    println!("result: {}", v[0]);
+        // println!("input: {:?}", v);
+
+        computer(&mut v);
+
+        Ok(v[0].into())
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        for noun in 0..100 {
+            for verb in 0..100 {
+                let mut v = self.program.clone();
+                v[1] = noun;
+                v[2] = verb;
+                computer(&mut v);
+
+                // println!("trying: noun: {}, verb: {}: result: {}", noun, verb, v[0]);
+
+                if v[0] == 19_690_720 {
+                    return Ok((100 * noun + verb).into());
+                }
+            }
+        }
+        Err("no noun and verb give 19690720".to_string())
+    }
 }
 
 fn calculate(s: &str) -> String {
-    let mut v = string_to_vec(s);
+    let mut v = input::list(s, ",").unwrap();
     computer(&mut v);
     vec_to_string(&v)
 }
 
-fn string_to_vec(s: &str) -> Vec<i32> {
-    s.trim()
-        .split(',')
-        .map(|x| x.parse().expect("failed to parse to int"))
-        .collect()
-}
-
 fn vec_to_string(v: &[i32]) -> String {
     let s: Vec<String> = v.iter().map(|x| x.to_string()).collect();
     s.join(",")
 }
 
-fn computer(v: &mut Vec<i32>) {
+fn computer(v: &mut [i32]) {
     let mut i = 0;
     loop {
         if v[i] == 99 {
@@ -58,29 +78,6 @@ fn computer(v: &mut Vec<i32>) {
     }
 }
 
-pub fn part2() {
-    let f = File::open("day2.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let mut v = string_to_vec(&data);
-            v[1] = noun;
-            v[2] = verb;
-            computer(&mut v);
-
-            // println!("trying: noun: {}, verb: {}: result: {}", noun, verb, v[0]);
-
-            if v[0] == 19_690_720 {
-                println!("noun: {}, verb: {}", noun, verb);
-                break;
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,8 +1,9 @@
+#![allow(dead_code)]
+
+use crate::input::ParseError;
 use crate::intcode::ExitMode;
 use crate::intcode::Intcode;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use crate::solution::{Answer, Solution};
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 struct Point {
@@ -10,15 +11,7 @@ struct Point {
     y: i64,
 }
 
-pub fn part1() {
-    let f = File::open("day21.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-
-    let inst = "NOT A J
+const WALK: &str = "NOT A J
 NOT B T
 OR T J
 NOT C T
@@ -27,44 +20,7 @@ AND D J
 WALK
 ";
 
-    let inputs: Vec<i64> = inst.chars().map(|x| x as i64).collect();
-
-    // the input function
-    let mut input_index = 0;
-    let mut input_fn = || -> i64 {
-        let i = input_index;
-        input_index += 1;
-        //println!("{}", inputs[i]);
-        inputs[i]
-    };
-
-    for _ in 0..10000 {
-        match code.run(&mut input_fn) {
-            ExitMode::Halt => {
-                //println!("halt");
-                break;
-            }
-            ExitMode::Output(x) => {
-                println!("{}", x);
-            }
-        };
-    }
-}
-
-pub fn part2() {
-    // options();
-    part2x();
-}
-
-pub fn part2x() {
-    let f = File::open("day21.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
-
-    let inst = "OR E J
+const RUN: &str = "OR E J
 OR H J
 OR A T
 AND B T
@@ -75,29 +31,54 @@ AND D J
 RUN
 ";
 
-    let inputs: Vec<i64> = inst.chars().map(|x| x as i64).collect();
+// run a springscript, returning the hull damage reported
+fn survey(code: &Intcode, script: &str) -> i64 {
+    let mut code = code.clone();
+
+    let inputs: Vec<i64> = script.chars().map(|x| x as i64).collect();
 
     // the input function
     let mut input_index = 0;
     let mut input_fn = || -> i64 {
         let i = input_index;
         input_index += 1;
-        //println!("{}", inputs[i]);
         inputs[i]
     };
 
+    let mut output = String::new();
     for _ in 0..10000 {
         match code.run(&mut input_fn) {
-            ExitMode::Halt => {
-                // println!("halt");
-                break;
-            }
-            ExitMode::Output(x) => {
-                println!("{}", x);
-                // print!("{}", x as u8 as char);
-            }
+            ExitMode::AwaitingInput => unreachable!(),
+            ExitMode::Halt => break,
+            // the damage is the one value out of ASCII range
+            ExitMode::Output(x) if x > 127 => return x,
+            ExitMode::Output(x) => output.push(x as u8 as char),
         };
     }
+    // the droid fell, and the output shows where
+    panic!("no damage reported:\n{}", output)
+}
+
+// the springdroid's program
+pub struct Day21 {
+    code: Intcode,
+}
+
+impl Solution for Day21 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(survey(&self.code, WALK)))
+    }
+
+    // found with the help of options
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(survey(&self.code, RUN)))
+    }
 }
 
 fn options() {
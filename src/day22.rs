@@ -1,10 +1,10 @@
 #![allow(dead_code)]
 
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use modinverse::modinverse;
 use std::convert::TryInto;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
 
 struct Deck {
     v: Vec<i64>,
@@ -353,43 +353,33 @@ enum Op {
     Reverse,
 }
 
-fn get_ops() -> Vec<Op> {
-    let f = File::open("day22.txt").unwrap();
-    let f = BufReader::new(f);
-
+fn get_ops(data: &str) -> Result<Vec<Op>, ParseError> {
     let mut ops: Vec<Op> = Vec::new();
 
-    for line in f.lines() {
-        let l = line.unwrap();
-        let parts: Vec<&str> = l.trim().split(' ').collect();
+    for l in input::lines(data) {
+        let l = l.trim();
 
         // deal with increment 30
         // cut 6056
         // deal into new stack
 
-        if parts.len() == 2 {
-            let x: i64 = parts[1].parse().unwrap();
-            ops.push(Op::Cut(x));
-        } else if parts.len() == 4 {
-            if parts[1] == "with" {
-                let x: u64 = parts[3].parse().unwrap();
-                ops.push(Op::Deal(x));
-            } else if parts[1] == "into" {
-                ops.push(Op::Reverse);
-            } else {
-                panic!("bad line: {}", l);
-            }
+        let op = if let Some(x) = l.strip_prefix("cut ") {
+            Op::Cut(x.parse()?)
+        } else if let Some(x) = l.strip_prefix("deal with increment ") {
+            Op::Deal(x.parse()?)
+        } else if l.text == "deal into new stack" {
+            Op::Reverse
         } else {
-            panic!("bad line: {}", l);
-        }
+            return Err(l.error("unknown technique"));
+        };
+        ops.push(op);
     }
-    ops
+    Ok(ops)
 }
 
-pub fn part1() {
-    let ops = get_ops();
-    let mut d = Deck4::new(10007);
-
+// one shuffle of a deck of the given size
+fn shuffle(ops: &[Op], cards: u64) -> Deck4 {
+    let mut d = Deck4::new(cards);
     for op in ops.iter() {
         match op {
             Op::Reverse => d.rev(),
@@ -397,34 +387,34 @@ pub fn part1() {
             Op::Deal(x) => d.deal(*x),
         }
     }
-
-    println!("pos: {}, scaler: {}", d.a, d.a);
-    println!("result: {}", d.pos(2019));
+    d
 }
 
-pub fn part2() {
-    let ops = get_ops();
-
-    let cards = 119_315_717_514_047;
-    let rounds = 101_741_582_076_661;
+// the shuffle instructions
+pub struct Day22 {
+    ops: Vec<Op>,
+}
 
-    let mut d = Deck4::new(cards);
-    for op in ops.iter() {
-        match op {
-            Op::Reverse => d.rev(),
-            Op::Cut(x) => d.cut(*x),
-            Op::Deal(x) => d.deal(*x),
-        }
+impl Solution for Day22 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            ops: get_ops(data)?,
+        })
     }
 
-    println!("single: a: {}, b: {}", d.a, d.b);
+    // where card 2019 ends up
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::from(shuffle(&self.ops, 10007).pos(2019)))
+    }
 
-    let m = d.multiply(rounds);
-    println!("multiple: a: {}, b: {}", m.a, m.b);
-    let i = m.inverse();
-    println!("inverse: a: {}, b: {}", i.a, i.b);
+    // the card that ends up at 2020
+    fn part2(&self) -> Result<Answer, String> {
+        let cards = 119_315_717_514_047;
+        let rounds = 101_741_582_076_661;
 
-    println!("card: {}", i.pos(2020));
+        let m = shuffle(&self.ops, cards).multiply(rounds);
+        Ok(Answer::from(m.inverse().pos(2020)))
+    }
 }
 
 #[cfg(test)]
@@ -642,4 +632,18 @@ mod tests {
         // assert_eq!(2, d.card(1));
         // assert_eq!(7, d.card(6));
     }
+
+    #[test]
+    fn test_get_ops() {
+        let ops = get_ops("deal into new stack\ncut -2\ndeal with increment 7\n").unwrap();
+        assert_eq!(3, ops.len());
+        assert_eq!(
+            "line 2, column 5: expected i64 \"x\"",
+            get_ops("cut 3\ncut x").err().unwrap().to_string()
+        );
+        assert_eq!(
+            "line 1, column 1: unknown technique \"shuffle\"",
+            get_ops("shuffle").err().unwrap().to_string()
+        );
+    }
 }
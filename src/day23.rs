@@ -1,187 +1,288 @@
-use crate::intcode::ExitMode;
+use crate::input::ParseError;
 use crate::intcode::Intcode;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
-use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
-use std::thread;
-use std::time::Duration;
-
-#[derive(Debug)]
-struct Nic {
-    code: Intcode,
-    rx: Receiver<i64>,
-    tx: Sender<Vec<i64>>,
-    id: i64,
+use crate::network::{Capture, Control, DirectRouter, Nat, Network, Packet};
+use crate::solution::{Answer, Solution};
+
+// stops the network at the first packet sent to the nat
+#[derive(Debug, Default)]
+struct FirstNat {
+    first: Option<Packet>,
 }
 
-impl Nic {
-    fn new(code: &Intcode, id: i64, rx: Receiver<i64>, tx: Sender<Vec<i64>>) -> Self {
-        Self {
-            code: code.clone(),
-            id,
-            rx,
-            tx,
-        }
+impl Nat for FirstNat {
+    fn receive(&mut self, p: &Packet) -> Control {
+        self.first = Some(*p);
+        Control::Stop
     }
 
-    fn get_input(&self) -> i64 {
-        match self.rx.try_recv() {
-            Ok(x) => {
-                println!("nic {}: input: {}", self.id, x);
-                x
-            }
-            Err(_) => -1,
-        }
+    fn idle(&mut self) -> Option<Packet> {
+        None
     }
+}
 
-    fn run(&mut self) {
-        let mut code = self.code.clone();
-        let mut v = Vec::new();
-
-        println!("nic {}: starting", self.id);
-
-        loop {
-            match code.run(&|| self.get_input()) {
-                ExitMode::Halt => {
-                    println!("halt");
-                    break;
-                }
-                ExitMode::Output(x) => {
-                    v.push(x);
-                    if v.len() == 3 {
-                        println!("nic {}: sending {:?}", self.id, v);
-                        self.tx.send(v).unwrap();
-                        v = Vec::new();
-                    }
-                }
-            };
+// wakes node 0 with the last packet received when the network is idle,
+// stopping when the same y is sent twice in a row
+#[derive(Debug, Default)]
+struct RepeatNat {
+    last: Option<Packet>,
+    lasty: Option<i64>,
+    repeated: Option<i64>,
+    // why the nat stopped the network early, if it did
+    error: Option<String>,
+}
+
+impl Nat for RepeatNat {
+    fn receive(&mut self, p: &Packet) -> Control {
+        self.last = Some(*p);
+        Control::Continue
+    }
+
+    fn idle(&mut self) -> Option<Packet> {
+        let p = match self.last {
+            None => {
+                self.error = Some("idle before any packet reached 255".to_string());
+                return None;
+            }
+            Some(p) => p,
+        };
+        if self.lasty == Some(p.y) {
+            self.repeated = Some(p.y);
+            return None;
         }
+        self.lasty = Some(p.y);
+        Some(Packet {
+            src: 255,
+            dest: 0,
+            x: p.x,
+            y: p.y,
+        })
     }
 }
 
-pub fn part1() {
-    let f = File::open("day23.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let code = Intcode::new(&data);
+fn first_to_nat(code: &Intcode, size: usize) -> Result<Option<Packet>, String> {
+    let mut nat = FirstNat::default();
+    Network::new(code, size).run(&mut DirectRouter, &mut nat)?;
+    Ok(nat.first)
+}
 
-    let (router_tx, router_rx) = mpsc::channel();
+fn repeated_y(code: &Intcode, size: usize) -> Result<Option<i64>, String> {
+    let mut nat = RepeatNat::default();
+    Network::new(code, size).run(&mut DirectRouter, &mut nat)?;
+    match nat.error {
+        Some(e) => Err(e),
+        None => Ok(nat.repeated),
+    }
+}
 
-    let mut nic_tx = Vec::new();
-    let mut handles = vec![];
+fn capture_repeated_y(code: &Intcode, size: usize) -> Result<Capture, String> {
+    let mut network = Network::new(code, size);
+    network.record();
+    let mut nat = RepeatNat::default();
+    network.run(&mut DirectRouter, &mut nat)?;
+    match nat.error {
+        Some(e) => Err(e),
+        None => Ok(network.capture().cloned().unwrap_or_default()),
+    }
+}
 
-    for i in 0..50 {
-        let (tx, rx) = mpsc::channel();
-        // send id
-        tx.send(i).unwrap();
-        let mut nic = Nic::new(&code, i, rx, mpsc::Sender::clone(&router_tx));
-        nic_tx.push(tx);
+// the NIC software
+pub struct Day23 {
+    code: Intcode,
+}
 
-        // start a thread for the nic
-        let handle = thread::spawn(move || {
-            nic.run();
-        });
-        handles.push(handle);
+impl Solution for Day23 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
     }
 
-    // router
-    let handle = thread::spawn(move || loop {
-        let p = router_rx.recv().unwrap();
-        println!("router: received: {:?}", p);
-        if p.len() != 3 {
-            panic!("bad packet, len: {}", p.len());
+    // the y of the first packet sent to 255
+    fn part1(&self) -> Result<Answer, String> {
+        match first_to_nat(&self.code, 50)? {
+            Some(p) => Ok(Answer::Int(p.y)),
+            None => Err("no packet to 255".to_string()),
         }
-        if p[0] == 255 {
-            println!("Packet to 255: x: {}, y: {}", p[1], p[2]);
-            break;
+    }
+
+    // the first y the nat sends twice in a row
+    fn part2(&self) -> Result<Answer, String> {
+        match repeated_y(&self.code, 50)? {
+            Some(y) => Ok(Answer::Int(y)),
+            None => Err("no repeated y".to_string()),
         }
-        let tx = &nic_tx[p[0] as usize];
-        tx.send(p[1]).unwrap();
-        tx.send(p[2]).unwrap();
-    });
-    handles.push(handle);
+    }
+}
 
-    for handle in handles {
-        handle.join().unwrap();
+// record the part2 run to path, then summarise it, list what the nat
+// sent and replay it through the part1 nat
+pub fn capture(data: &str, path: &str) -> Result<(), String> {
+    let code = Intcode::parse(data).map_err(|e| e.to_string())?;
+
+    let c = capture_repeated_y(&code, 50)?;
+    c.save(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let c = Capture::load(path)?;
+    println!("{}", c.summary());
+    for r in c.filter(|r| r.packet.src == 255).records.iter() {
+        println!("tick {}: nat sent y {}", r.tick, r.packet.y);
     }
+
+    let mut nat = FirstNat::default();
+    let n = c.replay(50, &mut DirectRouter, &mut nat);
+    println!("replayed {} packets, first to nat: {:?}", n, nat.first);
+    Ok(())
 }
 
-pub fn part2() {
-    let f = File::open("day23.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let code = Intcode::new(&data);
-
-    let (router_tx, router_rx) = mpsc::channel();
-
-    let mut nic_tx = Vec::new();
-    let mut handles = vec![];
-
-    for i in 0..50 {
-        let (tx, rx) = mpsc::channel();
-        // send id
-        tx.send(i).unwrap();
-        let mut nic = Nic::new(&code, i, rx, mpsc::Sender::clone(&router_tx));
-        nic_tx.push(tx);
-
-        // start a thread for the nic
-        let handle = thread::spawn(move || {
-            nic.run();
-        });
-        handles.push(handle);
-    }
-
-    // router
-    let handle = thread::spawn(move || {
-        let mut nat: Option<Vec<i64>> = None;
-        let mut lasty: Option<i64> = None;
-        loop {
-            match router_rx.recv_timeout(Duration::from_millis(1000)) {
-                Ok(p) => {
-                    println!("router: received: {:?}", p);
-                    if p.len() != 3 {
-                        panic!("bad packet, len: {}", p.len());
-                    }
-                    if p[0] == 255 {
-                        nat = Some(p);
-                    } else {
-                        let tx = &nic_tx[p[0] as usize];
-                        tx.send(p[1]).unwrap();
-                        tx.send(p[2]).unwrap();
-                    }
-                }
-                Err(_) => {
-                    // assume this is timeout
-                    println!("timeout");
-                    match &nat {
-                        None => panic!("timeout with empty nat"),
-                        Some(n) => {
-                            // check againt last sent
-                            if let Some(lasty) = lasty {
-                                if lasty == n[2] {
-                                    println!("Sending {} twice", lasty);
-                                    break;
-                                }
-                            }
-                            let tx = &nic_tx[0];
-                            tx.send(n[1]).unwrap();
-                            tx.send(n[2]).unwrap();
-                            lasty = Some(n[2]);
-                        }
-                    }
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{Route, Router};
+
+    // node 0 sends (3, 5) to node 1 at boot. Every node forwards what it
+    // receives to 255.
+    const NIC: &str = "3,31,1005,31,11,104,1,104,3,104,5,3,32,1008,32,-1,34,1005,34,11,\
+                       3,33,104,255,4,32,4,33,1105,1,11,0,0,0,0";
+
+    #[test]
+    fn test_first_to_nat() {
+        let code = Intcode::new(NIC);
+        let p = first_to_nat(&code, 2);
+        assert_eq!(
+            Ok(Some(Packet {
+                src: 1,
+                dest: 255,
+                x: 3,
+                y: 5
+            })),
+            p
+        );
+    }
+
+    #[test]
+    fn test_repeated_y() {
+        let code = Intcode::new(NIC);
+        assert_eq!(Ok(Some(5)), repeated_y(&code, 2));
+        // nothing is ever sent to 255
+        assert_eq!(
+            Err("idle before any packet reached 255".to_string()),
+            repeated_y(&Intcode::new("3,100,3,100,1105,1,2"), 2)
+        );
+        // a node that halts after sending half a packet
+        assert_eq!(
+            Err("node 0 halted part way through a packet".to_string()),
+            repeated_y(&Intcode::new("3,100,104,255,104,1,99"), 1)
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let code = Intcode::new(NIC);
+        let ticks: Vec<usize> = (0..3)
+            .map(|_| {
+                Network::new(&code, 4)
+                    .run(&mut DirectRouter, &mut RepeatNat::default())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(vec![4, 4, 4], ticks);
+    }
+
+    #[test]
+    fn test_counting_idle() {
+        // nodes that count their polls still go idle
+        let code = Intcode::new("3,100,101,1,101,101,1105,1,0");
+        let mut nat = FirstNat::default();
+        assert_eq!(
+            Ok(2),
+            Network::new(&code, 2).run(&mut DirectRouter, &mut nat)
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        let code = Intcode::new(NIC);
+        let c = capture_repeated_y(&code, 2).unwrap();
+        let packets: Vec<(usize, i64, i64)> = c
+            .records
+            .iter()
+            .map(|r| (r.tick, r.packet.src, r.packet.dest))
+            .collect();
+        assert_eq!(
+            vec![(1, 0, 1), (1, 1, 255), (2, 255, 0), (3, 0, 255)],
+            packets
+        );
+        assert_eq!(Ok(c.clone()), Capture::parse(&c.to_string()));
+    }
+
+    #[test]
+    fn test_capture_parse_error() {
+        assert_eq!(
+            Err("line 2: bad record: 1 2 3".to_string()),
+            Capture::parse("# comment\n1 2 3\n")
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let code = Intcode::new(NIC);
+        let c = capture_repeated_y(&code, 2).unwrap();
+        let to_nat = c.filter(|r| r.packet.dest == 255);
+        let ticks: Vec<usize> = to_nat.records.iter().map(|r| r.tick).collect();
+        assert_eq!(vec![1, 3], ticks);
+        assert_eq!(c, c.filter(|_| true));
+        assert!(c.filter(|r| r.tick > 3).records.is_empty());
+    }
+
+    #[test]
+    fn test_summary() {
+        let code = Intcode::new(NIC);
+        let s = capture_repeated_y(&code, 2).unwrap().summary();
+        assert_eq!(Some(&2), s.sent.get(&0));
+        assert_eq!(Some(&1), s.sent.get(&1));
+        assert_eq!(Some(&2), s.received.get(&255));
+        assert_eq!(1, s.nat_deliveries);
+        assert_eq!(Some(1), s.first_to_255.map(|r| r.tick));
+    }
+
+    #[test]
+    fn test_replay() {
+        let code = Intcode::new(NIC);
+        let c = capture_repeated_y(&code, 2).unwrap();
+
+        let mut nat = FirstNat::default();
+        assert_eq!(2, c.replay(2, &mut DirectRouter, &mut nat));
+        assert_eq!(first_to_nat(&code, 2), Ok(nat.first));
+
+        let mut nat = RepeatNat::default();
+        assert_eq!(4, c.replay(2, &mut DirectRouter, &mut nat));
+        assert_eq!(Some(5), nat.lasty);
+    }
+
+    struct DropAll;
+
+    impl Router for DropAll {
+        fn route(&mut self, _p: &Packet, _nodes: usize) -> Route {
+            Route::Drop
         }
-    });
-    handles.push(handle);
+    }
+
+    #[test]
+    fn test_router() {
+        let code = Intcode::new(NIC);
+        let mut nat = FirstNat::default();
+        let ticks = Network::new(&code, 2).run(&mut DropAll, &mut nat);
+        assert_eq!(None, nat.first);
+        assert_eq!(Ok(2), ticks);
 
-    for handle in handles {
-        handle.join().unwrap();
+        let p = |dest| Packet {
+            src: 0,
+            dest,
+            x: 0,
+            y: 0,
+        };
+        assert_eq!(Route::Node(1), DirectRouter.route(&p(1), 2));
+        assert_eq!(Route::Nat, DirectRouter.route(&p(255), 2));
+        assert_eq!(Route::Drop, DirectRouter.route(&p(2), 2));
     }
 }
@@ -1,3 +1,8 @@
+#![allow(dead_code)]
+
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use std::collections::HashMap;
 
 // grid is an i32
@@ -8,26 +13,33 @@ use std::collections::HashMap;
 // 15 16 17 18 19
 // 20 21 22 23 24
 
-fn parse(s: &str) -> i32 {
-    let mut v = 0;
-    for (i, c) in s.chars().enumerate() {
-        let b = match c {
-            '.' => 0,
-            '#' => 1,
-            _ => panic!("bad char"),
-        };
-        v |= b << i;
+// a 5x5 scan of '#' bugs and '.' space
+fn parse(s: &str) -> Result<i32, ParseError> {
+    let grid = input::grid(s, |c| match c {
+        '.' => Some(0),
+        '#' => Some(1),
+        _ => None,
+    })?;
+    for (l, row) in input::lines(s).zip(grid.iter()) {
+        if row.len() != 5 {
+            return Err(l.trim().error("expected 5 cells"));
+        }
     }
-    v
+    if grid.len() != 5 {
+        let message = format!("expected 5 rows, found {}", grid.len());
+        let at = input::lines(s).nth(5).unwrap_or_else(|| input::end(s));
+        return Err(at.error(&message));
+    }
+    Ok(grid
+        .iter()
+        .flatten()
+        .enumerate()
+        .fold(0, |v, (i, b)| v | b << i))
 }
 
-pub fn part1() {
-    // real
-    // let grid = "...#.#.##.#..###.#####...";
-    let grid = "....##..#.#..##..#..#....";
-
-    let mut v = parse(&grid);
-
+// the first layout to appear twice, which as bits is also its
+// biodiversity rating
+fn first_repeat(mut v: i32) -> i32 {
     let mut m = HashMap::new();
     m.insert(v, 0);
 
@@ -35,10 +47,7 @@ pub fn part1() {
         v = minute(v);
         match m.get(&v) {
             None => m.insert(v, 0),
-            Some(_) => {
-                println!("double: {}", v);
-                break;
-            }
+            Some(_) => return v,
         };
     }
 }
@@ -241,18 +250,47 @@ impl InfiniteBoard {
     }
 }
 
-pub fn part2() {
-    // let grid = "....##..#.#..##..#..#....";
-    let grid = "...#.#.##.#..###.#####...";
-    let b = parse(&grid);
-
+// the bugs after 200 minutes on the recursive grids
+fn recursive_bugs(b: i32) -> i32 {
     let mut ib = InfiniteBoard::new(b);
-    // ib.print();
-
     for _ in 0..200 {
         ib = ib.minute();
     }
-    // ib.print();
+    ib.count()
+}
 
-    println!("sum: {}", ib.count());
+// the scan of the area
+pub struct Day24 {
+    grid: i32,
+}
+
+impl Solution for Day24 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self { grid: parse(data)? })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(first_repeat(self.grid) as i64))
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(recursive_bugs(self.grid) as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(0b11), parse("##...\n.....\n.....\n.....\n....."));
+        let e = parse("....\n").unwrap_err();
+        assert_eq!((1, 1, "...."), (e.line, e.column, e.text.as_str()));
+        let e = parse(".....\n.....\n").unwrap_err();
+        assert_eq!(
+            "line 2, column 6: expected 5 rows, found 2 \"\"",
+            e.to_string()
+        );
+    }
 }
@@ -1,10 +1,14 @@
+#![allow(dead_code)]
+
+use crate::device::Device;
+use crate::input::ParseError;
 use crate::intcode::ExitMode;
 use crate::intcode::Intcode;
+use crate::record;
+use crate::record::{Log, Recorder};
+use crate::solution::{Answer, Solution};
 use std::collections::HashMap;
-use std::fs::File;
 use std::io;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(Debug)]
 struct Room {
@@ -24,10 +28,14 @@ struct Game {
     rooms: HashMap<String, Room>,
     commands: Vec<&'static str>,
     command_index: usize,
+    // read more commands from stdin once the scripted ones run out
+    stdin: bool,
+    // the text shown since the last clear
+    text: String,
 }
 
 impl Game {
-    fn new(code: &Intcode) -> Self {
+    fn new(code: &Intcode, stdin: bool) -> Self {
         Self {
             code: code.clone(),
             input: vec![],
@@ -62,10 +70,17 @@ impl Game {
                 "west",
             ],
             command_index: 0,
+            stdin,
+            text: String::new(),
         }
     }
 
-    fn get_input(&mut self) -> i64 {
+    fn send(&mut self, command: &str) {
+        self.input.extend(command.chars().map(|x| x as i64));
+        self.input.push('\n' as i64);
+    }
+
+    fn get_input(&mut self) -> Option<i64> {
         if self.input_index >= self.input.len() {
             if self.command_index < self.commands.len() {
                 self.input = self.commands[self.command_index]
@@ -73,36 +88,72 @@ impl Game {
                     .map(|x| x as i64)
                     .collect();
                 self.input.push('\n' as i64);
-                println!("** {} **", self.commands[self.command_index]);
+                if self.stdin {
+                    println!("** {} **", self.commands[self.command_index]);
+                }
                 self.input_index = 0;
                 self.command_index += 1;
-            } else {
+            } else if self.stdin {
                 let mut guess = String::new();
-                io::stdin()
+                let n = io::stdin()
                     .read_line(&mut guess)
                     .expect("Failed to read line");
+                if n == 0 {
+                    return None;
+                }
                 self.input = guess.chars().map(|x| x as i64).collect();
                 self.input_index = 0;
+            } else {
+                return None;
             }
         }
         let i = self.input[self.input_index];
         self.input_index += 1;
-        i
+        Some(i)
+    }
+
+    fn run(
+        &mut self,
+        code: &mut Intcode,
+        recorder: Option<&mut Recorder>,
+    ) -> Result<ExitMode, String> {
+        record::run(code, self, recorder).map_err(|e| e.to_string())
+    }
+
+    // the items picked up by the script
+    fn items(&self) -> Vec<&'static str> {
+        self.commands
+            .iter()
+            .filter_map(|c| c.strip_prefix("take "))
+            .collect()
     }
 
-    fn run(&mut self) {
+    // play the script to the security checkpoint, then try carrying each
+    // set of items onto the pressure-sensitive floor to the west until one
+    // weighs right. Returns the password for the airlock.
+    fn solve(&mut self, mut recorder: Option<&mut Recorder>) -> Result<Option<i64>, String> {
         let mut code = self.code.clone();
-        loop {
-            match code.run(&mut || self.get_input()) {
-                ExitMode::Halt => {
-                    println!("halt");
-                    break;
+        self.run(&mut code, recorder.as_deref_mut())?;
+        let items = self.items();
+        for set in 0..1 << items.len() {
+            for (i, item) in items.iter().enumerate() {
+                if set & 1 << i != 0 {
+                    self.send(&format!("take {}", item));
+                } else {
+                    self.send(&format!("drop {}", item));
                 }
-                ExitMode::Output(x) => {
-                    print!("{}", x as u8 as char);
-                }
-            };
+            }
+            self.send("west");
+            self.text.clear();
+            let exit = self.run(&mut code, recorder.as_deref_mut())?;
+            if !self.text.contains("Alert!") {
+                return Ok(password(&self.text));
+            }
+            if exit == ExitMode::Halt {
+                break;
+            }
         }
+        Ok(None)
     }
 
     // fn explore(&self) {
@@ -121,16 +172,96 @@ impl Game {
     // }
 }
 
-pub fn part1() {
-    let f = File::open("day25.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Device for Game {
+    fn input(&mut self) -> Option<i64> {
+        self.get_input()
+    }
 
-    let code = Intcode::new(&data);
+    fn output(&mut self, value: i64) {
+        if self.stdin {
+            print!("{}", value as u8 as char);
+        }
+        self.text.push(value as u8 as char);
+    }
+}
 
-    let mut g = Game::new(&code);
-    g.run();
+// the number in "... by typing 2622472 on the keypad ..."
+fn password(text: &str) -> Option<i64> {
+    let at = text.find("typing ")? + "typing ".len();
+    text[at..].split_whitespace().next()?.parse().ok()
 }
 
-pub fn part2() {}
+// play along from the end of the script, saving the session to path
+// if given
+pub fn play(data: &str, path: Option<&str>) -> Result<(), String> {
+    let mut code = Intcode::parse(data).map_err(|e| e.to_string())?;
+    let mut g = Game::new(&code, true);
+    let mut recorder = path.map(|_| Recorder::new());
+    match g.run(&mut code, recorder.as_mut())? {
+        ExitMode::Halt => println!("halt"),
+        _ => println!("end of input"),
+    }
+    if let (Some(path), Some(r)) = (path, recorder) {
+        r.log.save(path).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+// the adventure game
+pub struct Day25 {
+    code: Intcode,
+}
+
+impl Solution for Day25 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: Intcode::parse(data)?,
+        })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        let mut g = Game::new(&self.code, false);
+        match g.solve(None)? {
+            Some(p) => Ok(Answer::Int(p)),
+            None => Err(format!("no weight got through:\n{}", g.text)),
+        }
+    }
+}
+
+// the session of part 1, for a bug report
+pub fn record(data: &str) -> Result<Log, String> {
+    let code = Intcode::parse(data).map_err(|e| e.to_string())?;
+    let mut recorder = Recorder::new();
+    Game::new(&code, false).solve(Some(&mut recorder))?;
+    Ok(recorder.log)
+}
+
+// check a session from record still plays out the same
+pub fn replay(data: &str, log: &Log) -> Result<(), String> {
+    let mut code = Intcode::parse(data).map_err(|e| e.to_string())?;
+    record::replay(&mut code, log).map_err(|d| d.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // echoes every character typed
+    const ECHO: &str = "3,100,4,100,1105,1,0";
+
+    #[test]
+    fn test_replay_session() {
+        let code = Intcode::new(ECHO);
+        let mut g = Game::new(&code, false);
+        let mut recorder = Recorder::new();
+        let exit = g.run(&mut code.clone(), Some(&mut recorder));
+        assert_eq!(Ok(ExitMode::AwaitingInput), exit);
+        assert!(g.text.starts_with("south\ntake fixed point\n"));
+
+        let log = Log::parse(&recorder.log.to_text()).unwrap();
+        assert_eq!(Ok(()), record::replay(&mut code.clone(), &log));
+        // a different program does not play out the same
+        let twice = Intcode::new("3,100,4,100,4,100,1105,1,0");
+        assert!(record::replay(&mut twice.clone(), &log).is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use crate::input;
+use crate::input::{ParseError, Span};
+use crate::solution::{Answer, Solution};
 
 #[derive(Clone, Copy)]
 struct Point {
@@ -76,20 +76,35 @@ impl Line {
     }
 }
 
-pub fn part1() {
-    let f = File::open("day3.txt").expect("file not found");
-    let f = BufReader::new(f);
+pub struct Day3 {
+    wires: Vec<Vec<Line>>,
+}
 
-    let lines: Vec<String> = f.lines().map(|x| x.unwrap()).collect();
+impl Solution for Day3 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let wires: Vec<Vec<Line>> = input::lines(data)
+            .map(process_line)
+            .collect::<Result<_, _>>()?;
+        if wires.len() != 2 {
+            let message = format!("expected 2 wires, found {}", wires.len());
+            let at = input::lines(data)
+                .nth(2)
+                .unwrap_or_else(|| input::end(data));
+            return Err(at.error(&message));
+        }
+        Ok(Self { wires })
+    }
 
-    let dist = dist(&lines[0], &lines[1]);
-    println!("dist: {}", dist);
-}
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(dist(&self.wires[0], &self.wires[1]).into())
+    }
 
-fn dist(l1: &str, l2: &str) -> i32 {
-    let l1 = process_line(l1);
-    let l2 = process_line(l2);
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(steps(&self.wires[0], &self.wires[1]).into())
+    }
+}
 
+fn dist(l1: &[Line], l2: &[Line]) -> i32 {
     let mut min_dist = 10_000_000;
     let origin = Point { x: 0, y: 0 };
 
@@ -108,10 +123,7 @@ fn dist(l1: &str, l2: &str) -> i32 {
     min_dist
 }
 
-fn steps(l1: &str, l2: &str) -> i32 {
-    let l1 = process_line(l1);
-    let l2 = process_line(l2);
-
+fn steps(l1: &[Line], l2: &[Line]) -> i32 {
     let mut min_steps = 10_000_000;
 
     let mut s1_steps = 0;
@@ -140,78 +152,96 @@ fn steps(l1: &str, l2: &str) -> i32 {
     min_steps
 }
 
-fn process_line(line: &str) -> Vec<Line> {
+// a wire's path, e.g. R8,U5,L5,D3
+fn process_line(line: Span) -> Result<Vec<Line>, ParseError> {
     let mut ls: Vec<Line> = Vec::new();
     let mut p = Point { x: 0, y: 0 };
 
-    for op in line.split(',') {
-        let dir = &op[0..1];
-        let d: i32 = op[1..].parse().unwrap();
-        // println!("dir: {}, dist: {}", dir, d);
+    for op in line.trim().split(",") {
+        let (dir, d) = op.split_first().ok_or_else(|| op.error("empty move"))?;
+        let d: i32 = d.parse()?;
 
-        let e = match dir {
+        let e = match dir.text {
             "U" => Point { y: p.y + d, x: p.x },
             "D" => Point { y: p.y - d, x: p.x },
             "L" => Point { y: p.y, x: p.x - d },
             "R" => Point { y: p.y, x: p.x + d },
-            c => panic!("invalid dir: {}", c),
+            _ => return Err(dir.error("invalid direction")),
         };
 
         ls.push(Line { a: p, b: e });
 
         p = e;
     }
-    ls
-}
-
-pub fn part2() {
-    let f = File::open("day3.txt").expect("file not found");
-    let f = BufReader::new(f);
-
-    let lines: Vec<String> = f.lines().map(|x| x.unwrap()).collect();
-
-    let dist = steps(&lines[0], &lines[1]);
-    println!("steps: {}", dist);
+    Ok(ls)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn wire(s: &str) -> Vec<Line> {
+        process_line(input::lines(s).next().unwrap()).unwrap()
+    }
+
     #[test]
     fn test_part1() {
-        assert_eq!(6, dist("R8,U5,L5,D3", "U7,R6,D4,L4"));
+        assert_eq!(6, dist(&wire("R8,U5,L5,D3"), &wire("U7,R6,D4,L4")));
         assert_eq!(
             159,
             dist(
-                "R75,D30,R83,U83,L12,D49,R71,U7,L72",
-                "U62,R66,U55,R34,D71,R55,D58,R83"
+                &wire("R75,D30,R83,U83,L12,D49,R71,U7,L72"),
+                &wire("U62,R66,U55,R34,D71,R55,D58,R83")
             )
         );
         assert_eq!(
             135,
             dist(
-                "R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51",
-                "U98,R91,D20,R16,D67,R40,U7,R15,U6,R7"
+                &wire("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51"),
+                &wire("U98,R91,D20,R16,D67,R40,U7,R15,U6,R7")
             )
         );
     }
 
+    #[test]
+    fn test_process_line() {
+        let e = process_line(input::lines("R8,U5,X5").next().unwrap()).err();
+        assert_eq!(
+            Some("line 1, column 7: invalid direction \"X\"".to_string()),
+            e.map(|e| e.to_string())
+        );
+        let e = Day3::parse("R8,U5\nU7,R\n").err();
+        assert_eq!(
+            Some("line 2, column 5: expected i32 \"\"".to_string()),
+            e.map(|e| e.to_string())
+        );
+        let e = Day3::parse("R8,U5\n\nU7\nL2\n").err();
+        assert_eq!(
+            Some((4, 1, "L2".to_string())),
+            e.map(|e| (e.line, e.column, e.text))
+        );
+        let e = Day3::parse("R8,U5\n").err();
+        assert_eq!(
+            Some("line 1, column 6: expected 2 wires, found 1 \"\"".to_string()),
+            e.map(|e| e.to_string())
+        );
+    }
+
     #[test]
     fn test_part2() {
-        assert_eq!(30, steps("R8,U5,L5,D3", "U7,R6,D4,L4"));
+        assert_eq!(30, steps(&wire("R8,U5,L5,D3"), &wire("U7,R6,D4,L4")));
         assert_eq!(
             610,
             steps(
-                "R75,D30,R83,U83,L12,D49,R71,U7,L72",
-                "U62,R66,U55,R34,D71,R55,D58,R83"
+                &wire("R75,D30,R83,U83,L12,D49,R71,U7,L72"),
+                &wire("U62,R66,U55,R34,D71,R55,D58,R83")
             )
         );
         assert_eq!(
             410,
             steps(
-                "R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51",
-                "U98,R91,D20,R16,D67,R40,U7,R15,U6,R7"
+                &wire("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51"),
+                &wire("U98,R91,D20,R16,D67,R40,U7,R15,U6,R7")
             )
         );
     }
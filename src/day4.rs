@@ -1,13 +1,50 @@
-pub fn part1() {
-    let mut count = 0;
-    for x in 278_384..824_795 {
-        let digits = split(x);
-        if conseq(&digits) && incr(&digits) {
-            // println!("x: {}", x);
-            count += 1;
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
+
+// the range of passwords, e.g. 278384-824795
+pub struct Day4 {
+    from: i32,
+    to: i32,
+}
+
+impl Solution for Day4 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let line = input::lines(data)
+            .next()
+            .ok_or_else(|| input::end(data).error("expected a range"))?;
+        match line.trim().split("-")[..] {
+            [from, to] => Ok(Self {
+                from: from.parse()?,
+                to: to.parse()?,
+            }),
+            _ => Err(line.error("expected a range")),
+        }
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        let mut count = 0;
+        for x in self.from..self.to {
+            let digits = split(x);
+            if conseq(&digits) && incr(&digits) {
+                // println!("x: {}", x);
+                count += 1;
+            }
         }
+        Ok(count.into())
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        let mut count = 0;
+        for x in self.from..self.to {
+            let digits = split(x);
+            if conseq_only2(&digits) && incr(&digits) {
+                // println!("x: {}", x);
+                count += 1;
+            }
+        }
+        Ok(count.into())
     }
-    println!("count: {}", count);
 }
 
 fn conseq_only2(v: &[i32]) -> bool {
@@ -58,7 +95,7 @@ fn split(x: i32) -> Vec<i32> {
     let mut v: Vec<i32> = Vec::new();
     let mut x = x;
     for i in (0..6).rev() {
-        let pow = (10 as i32).pow(i);
+        let pow = 10_i32.pow(i);
         let a = x / pow;
         x -= a * pow;
         v.push(a);
@@ -67,23 +104,12 @@ fn split(x: i32) -> Vec<i32> {
     v
 }
 
-pub fn part2() {
-    let mut count = 0;
-    for x in 278_384..824_795 {
-        let digits = split(x);
-        if conseq_only2(&digits) && incr(&digits) {
-            // println!("x: {}", x);
-            count += 1;
-        }
-    }
-    println!("count: {}", count);
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_part1() {
         assert_eq!(true, conseq(&[0, 0]));
         assert_eq!(true, conseq(&[1, 0, 6, 6, 7]));
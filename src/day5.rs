@@ -1,7 +1,10 @@
+use crate::fuzz;
+use crate::fuzz::Outcome;
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
+use crate::unwind;
 use std::fmt;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum ParamMode {
@@ -84,6 +87,7 @@ impl Op {
 struct Intcode {
     input: i32,
     v: Vec<i32>,
+    outputs: Vec<i32>,
 }
 
 impl fmt::Display for Intcode {
@@ -98,14 +102,12 @@ impl Intcode {
         Intcode {
             input,
             v: Self::string_to_vec(s),
+            outputs: Vec::new(),
         }
     }
 
     fn string_to_vec(s: &str) -> Vec<i32> {
-        s.trim()
-            .split(',')
-            .map(|x| x.parse().expect("failed to parse to int"))
-            .collect()
+        input::list(s, ",").unwrap_or_else(|e| panic!("{}", e))
     }
 
     fn get(&self, i: usize, m: ParamMode) -> i32 {
@@ -148,12 +150,12 @@ impl Intcode {
                     i += 4;
                 }
                 Op::Input(im) => {
-                    println!("Step: {}, inserting input: {}", i, self.input);
                     self.set(i + 1, im, self.input);
                     i += 2;
                 }
                 Op::Output(om) => {
-                    println!("output: {}", self.get(i + 1, om));
+                    let output = self.get(i + 1, om);
+                    self.outputs.push(output);
                     i += 2;
                 }
                 Op::JumpTrue(m1, m2) => match self.get(i + 1, m1) {
@@ -188,24 +190,49 @@ impl Intcode {
     }
 }
 
-pub fn part1() {
-    // let f = File::open("day5.txt").expect("file not found");
-    // let mut f = BufReader::new(f);
-    // let mut data = String::new();
-    // f.read_to_string(&mut data).expect("failed to read string");
+pub struct Day5 {
+    program: String,
+}
 
-    // let mut i = Intcode::new(&data, 1);
-    // i.run();
+impl Day5 {
+    // the diagnostic code, output after the test results
+    fn diagnostic(&self, system: i32) -> Result<Answer, String> {
+        let mut i = Intcode::new(&self.program, system);
+        i.run();
+        match i.outputs.last() {
+            Some(&code) => Ok(code.into()),
+            None => Err("no diagnostic code".to_string()),
+        }
+    }
 }
 
-pub fn part2() {
-    let f = File::open("day5.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Solution for Day5 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        // the machine here is made from the text, so check it up front
+        input::list::<i32>(data, ",")?;
+        Ok(Self {
+            program: data.to_string(),
+        })
+    }
 
-    let mut i = Intcode::new(&data, 5);
-    i.run();
+    fn part1(&self) -> Result<Answer, String> {
+        self.diagnostic(1)
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        self.diagnostic(5)
+    }
+}
+
+// run for differential fuzzing. Every input reads the first input value.
+pub fn run_program(data: &str, input: &[i64]) -> Outcome {
+    let mut code = Intcode::new(data, input.first().copied().unwrap_or(0) as i32);
+    let error = unwind::catch(|| code.run());
+    Outcome {
+        outputs: code.outputs.iter().map(|&x| x as i64).collect(),
+        memory: fuzz::memory(&code.to_string()),
+        error,
+    }
 }
 
 #[cfg(test)]
@@ -219,7 +246,7 @@ mod tests {
         assert_eq!(ParamMode::Immediate, modes[1]);
         assert_eq!(ParamMode::Position, modes[2]);
 
-        let modes = Op::modes(110, 3);
+        let modes = Op::modes(11, 3);
         assert_eq!(ParamMode::Immediate, modes[0]);
         assert_eq!(ParamMode::Immediate, modes[1]);
         assert_eq!(ParamMode::Position, modes[2]);
@@ -1,24 +1,34 @@
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
-fn input_to_map(s: &str) -> HashMap<String, Vec<String>> {
+type Orbit = (String, String);
+
+// one "A)B" a line, B orbiting A
+fn parse_orbits(s: &str) -> Result<Vec<Orbit>, ParseError> {
+    input::lines(s)
+        .map(|l| match l.trim().split(")")[..] {
+            [a, b] if !a.text.is_empty() && !b.text.is_empty() => {
+                Ok((a.text.to_owned(), b.text.to_owned()))
+            }
+            _ => Err(l.trim().error("expected an orbit")),
+        })
+        .collect()
+}
+
+fn input_to_map(orbits: &[Orbit]) -> HashMap<String, Vec<String>> {
     let mut m: HashMap<String, Vec<String>> = HashMap::new();
-    for l in s.trim().split_whitespace() {
-        let vals: Vec<&str> = l.split(')').collect();
-        m.entry(vals[0].to_owned())
-            .or_insert(Vec::new())
-            .push(vals[1].to_owned());
+    for (a, b) in orbits.iter() {
+        m.entry(a.to_owned()).or_default().push(b.to_owned());
     }
     m
 }
 
-fn input_to_rev_map(s: &str) -> HashMap<String, String> {
+fn input_to_rev_map(orbits: &[Orbit]) -> HashMap<String, String> {
     let mut m: HashMap<String, String> = HashMap::new();
-    for l in s.trim().split_whitespace() {
-        let vals: Vec<&str> = l.split(')').collect();
-        m.insert(vals[1].to_owned(), vals[0].to_owned());
+    for (a, b) in orbits.iter() {
+        m.insert(b.to_owned(), a.to_owned());
     }
     m
 }
@@ -49,18 +59,6 @@ fn path(m: &HashMap<String, String>, x: &str) -> Vec<String> {
     v
 }
 
-pub fn part1() {
-    let f = File::open("day6.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let m = input_to_map(&data);
-    let sum = start(&m);
-
-    println!("sum: {}", sum);
-}
-
 fn min(a: usize, b: usize) -> usize {
     if a < b {
         a
@@ -83,35 +81,39 @@ fn common(v1: &[String], v2: &[String]) -> usize {
     x
 }
 
-fn hops(s: &str) -> usize {
-    let m = input_to_rev_map(&s);
+fn hops(orbits: &[Orbit]) -> usize {
+    let m = input_to_rev_map(orbits);
 
     let mut p1 = path(&m, "YOU");
     let mut p2 = path(&m, "SAN");
     p1.reverse();
     p2.reverse();
 
-    println!("p1: {:?}", p1);
-    println!("p2: {:?}", p2);
-
     let c = common(&p1, &p2);
 
-    println!("common: {}", c);
-
-    let hops = p1.len() + p2.len() - 2 * c;
-
-    println!("hops: {}", hops);
+    p1.len() + p2.len() - 2 * c
+}
 
-    hops
+// the orbit map
+pub struct Day6 {
+    orbits: Vec<Orbit>,
 }
 
-pub fn part2() {
-    let f = File::open("day6.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Solution for Day6 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            orbits: parse_orbits(data)?,
+        })
+    }
 
-    println!("hops: {}", hops(&data));
+    fn part1(&self) -> Result<Answer, String> {
+        let m = input_to_map(&self.orbits);
+        Ok(start(&m).into())
+    }
+
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(hops(&self.orbits).into())
+    }
 }
 
 #[cfg(test)]
@@ -131,8 +133,13 @@ mod tests {
         E)J
         J)K
         K)L";
-        let m = input_to_map(input);
+        let m = input_to_map(&parse_orbits(input).unwrap());
         assert_eq!(42, start(&m));
+
+        assert_eq!(
+            Err("line 2, column 9: expected an orbit \"B)\"".to_string()),
+            parse_orbits("COM)B\n        B)\n").map_err(|e| e.to_string())
+        );
     }
     #[test]
     fn test_part2() {
@@ -149,6 +156,6 @@ mod tests {
         K)L
         K)YOU
         I)SAN";
-        assert_eq!(4, hops(&input));
+        assert_eq!(4, hops(&parse_orbits(input).unwrap()));
     }
 }
@@ -1,8 +1,14 @@
-use permutohedron::Heap;
+use crate::fuzz;
+use crate::fuzz::Outcome;
+use crate::input;
+use crate::input::ParseError;
+use crate::intcode;
+use crate::pipeline::Pipeline;
+use crate::solution::{Answer, Solution};
+use crate::unwind;
+use std::collections::VecDeque;
 use std::fmt;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use std::thread;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum ParamMode {
@@ -90,6 +96,8 @@ enum ExitMode {
 struct Intcode {
     v: Vec<i32>,
     i: usize,
+    // inputs read over every run
+    inputs_read: usize,
 }
 
 impl fmt::Display for Intcode {
@@ -104,14 +112,12 @@ impl Intcode {
         Intcode {
             v: Self::string_to_vec(s),
             i: 0,
+            inputs_read: 0,
         }
     }
 
     fn string_to_vec(s: &str) -> Vec<i32> {
-        s.trim()
-            .split(',')
-            .map(|x| x.parse().expect("failed to parse to int"))
-            .collect()
+        input::list(s, ",").unwrap_or_else(|e| panic!("{}", e))
     }
 
     fn get(&self, i: usize, m: ParamMode) -> i32 {
@@ -165,6 +171,7 @@ impl Intcode {
                     // println!("Step: {}, inserting input: {}", i, input);
                     self.set(self.i + 1, im, input[input_index]);
                     input_index += 1;
+                    self.inputs_read += 1;
                     self.i += 2;
                 }
                 Op::Output(om) => {
@@ -205,130 +212,234 @@ impl Intcode {
     }
 }
 
-fn amplify(data: &str, phases: &[i32], input: i32) -> i32 {
+// run the amplifiers in a chain, each passing its output to the next
+fn amplify(code: &intcode::Intcode, phases: &[i32], input: i32) -> i32 {
     let mut input = input;
     for p in phases.iter() {
-        let mut code = Intcode::new(data);
-        loop {
-            match code.run(&[*p, input]) {
-                ExitMode::Halt => break,
-                ExitMode::Output(x) => input = x,
-            }
+        let mut code = code.clone();
+        let mut queue = VecDeque::from(vec![*p as i64, input as i64]);
+        while let intcode::ExitMode::Output(x) = code.run_queue(&mut queue) {
+            input = x as i32;
         }
     }
 
     input
 }
 
-fn amplify2(data: &str, phases: &[i32], input: i32) -> i32 {
-    let mut a = Intcode::new(data);
-    let mut b = Intcode::new(data);
-    let mut c = Intcode::new(data);
-    let mut d = Intcode::new(data);
-    let mut e = Intcode::new(data);
-
-    let mut signal = input;
-    // initial loop
-    match a.run(&[phases[0], signal]) {
-        ExitMode::Halt => panic!("unexpected halt"),
-        ExitMode::Output(x) => signal = x,
-    }
-    match b.run(&[phases[1], signal]) {
-        ExitMode::Halt => panic!("unexpected halt"),
-        ExitMode::Output(x) => signal = x,
+// Run the amplifiers in a feedback loop until they halt. The thrusters
+// get the last amplifier's final output, None if the loop stalls first.
+fn amplify2(code: &intcode::Intcode, phases: &[i32], input: i32) -> Option<i32> {
+    // no amplifiers pass the signal straight through, as in a chain
+    if phases.is_empty() {
+        return Some(input);
     }
-    match c.run(&[phases[2], signal]) {
-        ExitMode::Halt => panic!("unexpected halt"),
-        ExitMode::Output(x) => signal = x,
-    }
-    match d.run(&[phases[3], signal]) {
-        ExitMode::Halt => panic!("unexpected halt"),
-        ExitMode::Output(x) => signal = x,
+    let mut p = Pipeline::new();
+    let ids: Vec<usize> = phases
+        .iter()
+        .map(|&phase| {
+            let id = p.add(code);
+            p.seed(id, &[phase as i64]);
+            id
+        })
+        .collect();
+
+    for (i, &id) in ids.iter().enumerate() {
+        p.connect(id, ids[(i + 1) % ids.len()]);
     }
-    match e.run(&[phases[4], signal]) {
-        ExitMode::Halt => panic!("unexpected halt"),
-        ExitMode::Output(x) => signal = x,
+    let thrusters = p.sink(ids[ids.len() - 1]);
+
+    p.seed(ids[0], &[input as i64]);
+    p.run();
+    if !p.halted() {
+        return None;
     }
+    p.values(thrusters).last().map(|&x| x as i32)
+}
 
-    // continuous loop
-    loop {
-        match a.run(&[signal]) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => signal = x,
-        }
-        match b.run(&[signal]) {
+// run for differential fuzzing, taking input from one stream across runs
+pub fn run_program(data: &str, input: &[i64]) -> Outcome {
+    let input: Vec<i32> = input.iter().map(|&x| x as i32).collect();
+    let mut code = Intcode::new(data);
+    let mut outputs = Vec::new();
+    let error = unwind::catch(|| loop {
+        let read = code.inputs_read;
+        match code.run(&input[read..]) {
             ExitMode::Halt => break,
-            ExitMode::Output(x) => signal = x,
-        }
-        match c.run(&[signal]) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => signal = x,
-        }
-        match d.run(&[signal]) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => signal = x,
-        }
-        match e.run(&[signal]) {
-            ExitMode::Halt => break,
-            ExitMode::Output(x) => signal = x,
+            ExitMode::Output(x) => outputs.push(x as i64),
         }
+    });
+    Outcome {
+        outputs,
+        memory: fuzz::memory(&code.to_string()),
+        error,
     }
+}
 
-    signal
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Circuit {
+    Chain,
+    Feedback,
 }
 
-fn best(data: &str, mut phases: Vec<i32>, input: i32) -> i32 {
-    let heap = Heap::new(&mut phases);
-    let mut b = 0;
-    for ps in heap {
-        let output = amplify(data, &ps, input);
-        if output > b {
-            b = output;
+impl Circuit {
+    // the phases the puzzle gives for the circuit
+    pub fn phases(self) -> Vec<i32> {
+        match self {
+            Circuit::Chain => vec![0, 1, 2, 3, 4],
+            Circuit::Feedback => vec![5, 6, 7, 8, 9],
         }
     }
-    b
 }
 
-fn best2(data: &str, mut phases: Vec<i32>, input: i32) -> i32 {
-    let heap = Heap::new(&mut phases);
-    let mut b = 0;
-    for ps in heap {
-        let output = amplify2(data, &ps, input);
-        if output > b {
-            b = output;
+// Searches every phase setting for a circuit of amplifiers, each phase
+// used at most once unless repeat is set. Chains share the work for
+// common prefixes, since the signal after the first few amplifiers only
+// depends on their phases.
+pub struct Optimizer<'a> {
+    code: &'a intcode::Intcode,
+    alphabet: Vec<i32>,
+    amps: usize,
+    repeat: bool,
+    circuit: Circuit,
+}
+
+impl<'a> Optimizer<'a> {
+    pub fn new(
+        code: &'a intcode::Intcode,
+        alphabet: &[i32],
+        amps: usize,
+        repeat: bool,
+        circuit: Circuit,
+    ) -> Self {
+        Self {
+            code,
+            alphabet: alphabet.to_vec(),
+            amps,
+            repeat,
+            circuit,
+        }
+    }
+
+    fn search(
+        &self,
+        prefix: &mut Vec<i32>,
+        signal: i32,
+        input: i32,
+        results: &mut Vec<(Vec<i32>, i32)>,
+    ) {
+        if prefix.len() == self.amps {
+            let output = match self.circuit {
+                Circuit::Chain => Some(signal),
+                Circuit::Feedback => amplify2(self.code, prefix, input),
+            };
+            // a stalled loop never reaches the thrusters
+            if let Some(output) = output {
+                results.push((prefix.clone(), output));
+            }
+            return;
+        }
+
+        for &phase in self.alphabet.iter() {
+            if !self.repeat && prefix.contains(&phase) {
+                continue;
+            }
+            let next = match self.circuit {
+                Circuit::Chain => amplify(self.code, &[phase], signal),
+                Circuit::Feedback => signal,
+            };
+            prefix.push(phase);
+            self.search(prefix, next, input, results);
+            prefix.pop();
         }
     }
-    b
+
+    // every phase setting with its output, best first. Each choice of
+    // first phase is searched on its own thread.
+    pub fn ranked(&self, input: i32) -> Vec<(Vec<i32>, i32)> {
+        let mut results = Vec::new();
+        if self.amps == 0 {
+            self.search(&mut Vec::new(), input, input, &mut results);
+            return results;
+        }
+
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .alphabet
+                .iter()
+                .map(|&phase| {
+                    s.spawn(move || {
+                        let mut results = Vec::new();
+                        let signal = match self.circuit {
+                            Circuit::Chain => amplify(self.code, &[phase], input),
+                            Circuit::Feedback => input,
+                        };
+                        self.search(&mut vec![phase], signal, input, &mut results);
+                        results
+                    })
+                })
+                .collect();
+            for h in handles {
+                results.extend(h.join().unwrap());
+            }
+        });
+
+        results.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results
+    }
+
+    // the phase setting with the highest output, and the output, if
+    // there are any phase settings to try
+    pub fn best(&self, input: i32) -> Option<(Vec<i32>, i32)> {
+        self.ranked(input).into_iter().next()
+    }
+}
+
+fn best(code: &intcode::Intcode, phases: Vec<i32>, input: i32) -> Option<(Vec<i32>, i32)> {
+    Optimizer::new(code, &phases, phases.len(), false, Circuit::Chain).best(input)
 }
 
-pub fn part1() {
-    let f = File::open("day7.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+fn best2(code: &intcode::Intcode, phases: Vec<i32>, input: i32) -> Option<(Vec<i32>, i32)> {
+    Optimizer::new(code, &phases, phases.len(), false, Circuit::Feedback).best(input)
+}
 
-    println!("best: {}", best(&data, vec![0, 1, 2, 3, 4], 0));
+pub struct Day7 {
+    code: intcode::Intcode,
 }
 
-pub fn part2() {
-    let f = File::open("day7.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Solution for Day7 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            code: intcode::Intcode::parse(data)?,
+        })
+    }
+
+    fn part1(&self) -> Result<Answer, String> {
+        best(&self.code, Circuit::Chain.phases(), 0)
+            .map(|(_, output)| Answer::from(output))
+            .ok_or_else(|| "no phase settings".to_string())
+    }
 
-    println!("best: {}", best2(&data, vec![9, 8, 7, 6, 5], 0));
+    fn part2(&self) -> Result<Answer, String> {
+        best2(&self.code, Circuit::Feedback.phases(), 0)
+            .map(|(_, output)| Answer::from(output))
+            .ok_or_else(|| "no phase settings".to_string())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn code(s: &str) -> intcode::Intcode {
+        intcode::Intcode::new(s)
+    }
+
     #[test]
     fn test_amplify() {
         assert_eq!(
-            43210,
+            Some((vec![4, 3, 2, 1, 0], 43210)),
             best(
-                "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0",
+                &code("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0"),
                 vec!(0, 1, 2, 3, 4),
                 0
             )
@@ -338,12 +449,64 @@ mod tests {
     #[test]
     fn test_amplify2() {
         assert_eq!(
-            139629729,
+            Some(139629729),
             amplify2(
-                "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+                &code("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"),
                 &[9,8,7,6,5],
                 0
             )
         );
+        // waits on a third input that never comes
+        assert_eq!(None, amplify2(&code("3,0,3,0,3,0,99"), &[1], 0));
+    }
+
+    #[test]
+    fn test_best2() {
+        assert_eq!(
+            Some((vec![9, 8, 7, 6, 5], 139629729)),
+            best2(
+                &code("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"),
+                vec![5, 6, 7, 8, 9],
+                0
+            )
+        );
+    }
+
+    // outputs -(phase + input)
+    const NEGATE: &str = "3,15,3,16,1,15,16,17,1002,17,-1,17,4,17,99,0,0,0";
+
+    #[test]
+    fn test_best_negative() {
+        let negate = code(NEGATE);
+        let o = Optimizer::new(&negate, &[1, 2], 1, false, Circuit::Chain);
+        assert_eq!(vec![(vec![1], -1), (vec![2], -2)], o.ranked(0));
+        assert_eq!(Some((vec![1], -1)), best(&negate, vec![1], 0));
+    }
+
+    #[test]
+    fn test_too_few_phases() {
+        let negate = code(NEGATE);
+        // three amplifiers can't each take a different one of two phases
+        for &circuit in [Circuit::Chain, Circuit::Feedback].iter() {
+            let o = Optimizer::new(&negate, &[1, 2], 3, false, circuit);
+            assert_eq!(None, o.best(0));
+        }
+        let o = Optimizer::new(&negate, &[], 0, false, Circuit::Feedback);
+        assert_eq!(Some((vec![], 7)), o.best(7));
+    }
+
+    #[test]
+    fn test_ranked_repeat() {
+        let negate = code(NEGATE);
+        let o = Optimizer::new(&negate, &[1, 2], 2, true, Circuit::Chain);
+        assert_eq!(
+            vec![
+                (vec![2, 1], 1),
+                (vec![1, 1], 0),
+                (vec![2, 2], 0),
+                (vec![1, 2], -1)
+            ],
+            o.ranked(0)
+        );
     }
 }
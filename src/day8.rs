@@ -1,69 +1,79 @@
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
-pub fn part1() {
-    let f = File::open("day8.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+const LAYER_SIZE: usize = 25 * 6;
 
-    let layer_size = 25 * 6;
-    let layers = data.trim().len() / layer_size;
-
-    let mut blah = Vec::new();
+// the image, one digit a pixel
+pub struct Day8 {
+    data: String,
+}
 
-    for i in 0..layers {
-        let sums = data[i * layer_size..(i + 1) * layer_size].chars().fold(
-            HashMap::new(),
-            |mut acc, x| {
-                *acc.entry(x).or_insert(0) += 1;
-                acc
-            },
-        );
-        blah.push(sums);
+impl Solution for Day8 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        input::digits(data)?;
+        let data = data.trim().to_string();
+        if data.is_empty() || data.len() % LAYER_SIZE != 0 {
+            let message = format!("{} pixels is not a whole number of layers", data.len());
+            return Err(input::end(&data).error(&message));
+        }
+        Ok(Self { data })
     }
 
-    let min = blah
-        .iter()
-        .min_by(|a, b| a.get(&'0').unwrap().cmp(b.get(&'0').unwrap()));
-    let result = min.unwrap().get(&'1').unwrap() * min.unwrap().get(&'2').unwrap();
+    fn part1(&self) -> Result<Answer, String> {
+        let data = &self.data;
+        let layers = data.len() / LAYER_SIZE;
 
-    println!("min: {:?}", min);
-    println!("result: {:?}", result);
-}
+        let mut blah = Vec::new();
 
-pub fn part2() {
-    let f = File::open("day8.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+        for i in 0..layers {
+            let sums = data[i * LAYER_SIZE..(i + 1) * LAYER_SIZE].chars().fold(
+                HashMap::new(),
+                |mut acc, x| {
+                    *acc.entry(x).or_insert(0) += 1;
+                    acc
+                },
+            );
+            blah.push(sums);
+        }
 
-    let layer_size = 25 * 6;
-    let mut layers: Vec<Vec<char>> = Vec::new();
+        let min = blah
+            .iter()
+            .min_by(|a, b| a.get(&'0').unwrap().cmp(b.get(&'0').unwrap()));
+        let result = min.unwrap().get(&'1').unwrap() * min.unwrap().get(&'2').unwrap();
 
-    for i in 0..100 {
-        layers.push(data[i * layer_size..(i + 1) * layer_size].chars().collect());
+        Ok(Answer::Int(result))
     }
 
-    for row in 0..6 {
-        for col in 0..25 {
-            let x = row * 25 + col;
-            for layer in layers.iter() {
-                match layer[x] {
-                    '0' => {
-                        print!(" ");
-                        break;
-                    }
-                    '1' => {
-                        print!("#");
-                        break;
+    fn part2(&self) -> Result<Answer, String> {
+        let data = &self.data;
+        let mut layers: Vec<Vec<char>> = Vec::new();
+
+        for i in 0..data.len() / LAYER_SIZE {
+            layers.push(data[i * LAYER_SIZE..(i + 1) * LAYER_SIZE].chars().collect());
+        }
+
+        let mut image = String::new();
+        for row in 0..6 {
+            for col in 0..25 {
+                let x = row * 25 + col;
+                for layer in layers.iter() {
+                    match layer[x] {
+                        '0' => {
+                            image.push(' ');
+                            break;
+                        }
+                        '1' => {
+                            image.push('#');
+                            break;
+                        }
+                        _ => (),
                     }
-                    _ => (),
                 }
             }
+            image.push('\n');
         }
-        println!();
+        Ok(Answer::Art(image))
     }
 }
@@ -1,8 +1,11 @@
+use crate::fuzz;
+use crate::fuzz::Outcome;
+use crate::input;
+use crate::input::ParseError;
+use crate::solution::{Answer, Solution};
+use crate::unwind;
 use std::collections::HashMap;
 use std::fmt;
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum ParamMode {
@@ -97,6 +100,8 @@ enum ExitMode {
 struct Intcode {
     v: Vec<i64>,
     i: i64,
+    // inputs read over every run
+    inputs_read: usize,
     relative_base: i64,
     memory: HashMap<i64, i64>,
 }
@@ -113,16 +118,14 @@ impl Intcode {
         Intcode {
             v: Self::string_to_vec(s),
             i: 0,
+            inputs_read: 0,
             relative_base: 0,
             memory: HashMap::new(),
         }
     }
 
     fn string_to_vec(s: &str) -> Vec<i64> {
-        s.trim()
-            .split(',')
-            .map(|x| x.parse().expect("failed to parse to int"))
-            .collect()
+        input::list(s, ",").unwrap_or_else(|e| panic!("{}", e))
     }
 
     fn get_mem(&self, i: i64) -> i64 {
@@ -192,6 +195,7 @@ impl Intcode {
                     // println!("Step: {}, inserting input: {}", i, input);
                     self.set(self.i + 1, im, input[input_index]);
                     input_index += 1;
+                    self.inputs_read += 1;
                     self.i += 2;
                 }
                 Op::Output(om) => {
@@ -237,28 +241,55 @@ impl Intcode {
     }
 }
 
-pub fn part1() {
-    let f = File::open("day9.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
-
-    let mut code = Intcode::new(&data);
+// the BOOST program
+pub struct Day9 {
+    program: String,
+}
 
-    while let ExitMode::Output(x) = code.run(&[1]) {
-        println!("Output: {}", x);
+impl Day9 {
+    // the last thing BOOST outputs, after any failing opcodes
+    fn boost(&self, mode: i64) -> i64 {
+        let mut code = Intcode::new(&self.program);
+        let mut last = None;
+        while let ExitMode::Output(x) = code.run(&[mode]) {
+            last = Some(x);
+        }
+        last.expect("no output")
     }
 }
 
-pub fn part2() {
-    let f = File::open("day9.txt").expect("file not found");
-    let mut f = BufReader::new(f);
-    let mut data = String::new();
-    f.read_to_string(&mut data).expect("failed to read string");
+impl Solution for Day9 {
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        // the machine here is made from the text, so check it up front
+        input::list::<i64>(data, ",")?;
+        Ok(Self {
+            program: data.to_string(),
+        })
+    }
 
-    let mut code = Intcode::new(&data);
+    fn part1(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(self.boost(1)))
+    }
 
-    while let ExitMode::Output(x) = code.run(&[2]) {
-        println!("Output: {}", x);
+    fn part2(&self) -> Result<Answer, String> {
+        Ok(Answer::Int(self.boost(2)))
+    }
+}
+
+// run for differential fuzzing, taking input from one stream across runs
+pub fn run_program(data: &str, input: &[i64]) -> Outcome {
+    let mut code = Intcode::new(data);
+    let mut outputs = Vec::new();
+    let error = unwind::catch(|| loop {
+        let read = code.inputs_read;
+        match code.run(&input[read..]) {
+            ExitMode::Halt => break,
+            ExitMode::Output(x) => outputs.push(x),
+        }
+    });
+    Outcome {
+        outputs,
+        memory: fuzz::memory(&code.to_string()),
+        error,
     }
 }
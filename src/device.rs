@@ -0,0 +1,457 @@
+use crate::intcode::{Error, ExitMode, Extension, Intcode, Param, Truncated};
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+// Emulated hardware attached to an Intcode machine, either mapped to a
+// range of addresses or on the input and output streams. Mapped addresses
+// are relative to the start of the range.
+pub trait Device {
+    fn read(&mut self, _addr: i64) -> i64 {
+        0
+    }
+    fn write(&mut self, _addr: i64, _value: i64) {}
+    // the next input value, None if the device has none
+    fn input(&mut self) -> Option<i64> {
+        None
+    }
+    fn output(&mut self, _value: i64) {}
+}
+
+struct Mapped<'a> {
+    devices: Vec<(Range<i64>, &'a mut dyn Device)>,
+}
+
+impl<'a> Extension for Mapped<'a> {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+
+    fn read(&mut self, addr: i64) -> Option<i64> {
+        self.devices
+            .iter_mut()
+            .find(|(r, _)| r.contains(&addr))
+            .map(|(r, d)| d.read(addr - r.start))
+    }
+
+    fn write(&mut self, addr: i64, value: i64) -> bool {
+        match self.devices.iter_mut().find(|(r, _)| r.contains(&addr)) {
+            Some((r, d)) => {
+                d.write(addr - r.start, value);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Routes memory accesses and IO for a machine to devices. The devices are
+// borrowed, so their state can be inspected once the bus is dropped.
+pub struct Bus<'a> {
+    mapped: Mapped<'a>,
+    stream: Option<&'a mut dyn Device>,
+}
+
+impl<'a> Default for Bus<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Bus<'a> {
+    pub fn new() -> Self {
+        Self {
+            mapped: Mapped {
+                devices: Vec::new(),
+            },
+            stream: None,
+        }
+    }
+
+    // map a device to a range of addresses. The first mapping containing
+    // an address wins.
+    pub fn map(&mut self, range: Range<i64>, device: &'a mut dyn Device) {
+        self.mapped.devices.push((range, device));
+    }
+
+    // the device that takes the machine's output and gives its input
+    pub fn attach(&mut self, device: &'a mut dyn Device) {
+        self.stream = Some(device);
+    }
+
+    // Run until the machine halts or needs input the stream device can't
+    // give. Without a stream device output is returned as usual.
+    pub fn run(&mut self, code: &mut Intcode) -> Result<ExitMode, Error> {
+        loop {
+            let stream = &mut self.stream;
+            let exit = code.run_ext(&mut self.mapped, || match stream {
+                Some(d) => d.input(),
+                None => None,
+            })?;
+            match (exit, &mut self.stream) {
+                (ExitMode::Output(x), Some(d)) => d.output(x),
+                (exit, _) => return Ok(exit),
+            }
+        }
+    }
+}
+
+// input on one device, output on another
+pub struct Split<I: Device, O: Device> {
+    pub input: I,
+    pub output: O,
+}
+
+impl<I: Device, O: Device> Device for Split<I, O> {
+    fn input(&mut self) -> Option<i64> {
+        self.input.input()
+    }
+
+    fn output(&mut self, value: i64) {
+        self.output.output(value)
+    }
+}
+
+// Gathers a stream device's output into tuples of N values, e.g. the
+// (x, y, value) triples drawn on a frame buffer
+#[derive(Debug)]
+pub struct Tuples<const N: usize> {
+    pending: Vec<i64>,
+}
+
+impl<const N: usize> Default for Tuples<N> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<const N: usize> Tuples<N> {
+    // add a value, returning the tuple it completes
+    pub fn push(&mut self, value: i64) -> Option<[i64; N]> {
+        self.pending.push(value);
+        if self.pending.len() < N {
+            return None;
+        }
+        let mut t = [0; N];
+        t.copy_from_slice(&self.pending);
+        self.pending.clear();
+        Some(t)
+    }
+
+    // once the machine stops, an error if it stopped part way through a
+    // tuple
+    pub fn finish(&self, exit: ExitMode) -> Result<ExitMode, Truncated> {
+        if self.pending.is_empty() {
+            return Ok(exit);
+        }
+        Err(Truncated {
+            outputs: self.pending.clone(),
+            expected: N,
+            exit,
+        })
+    }
+}
+
+// Pixels set by (x, y, value) triples on the output stream, or mapped
+// with one address per pixel in rows of width.
+#[derive(Debug)]
+pub struct FrameBuffer {
+    pub width: i64,
+    pub pixels: HashMap<(i64, i64), i64>,
+    // the triple being drawn, to check once the machine stops
+    pub triples: Tuples<3>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: i64) -> Self {
+        assert!(width > 0, "frame buffer width {}", width);
+        Self {
+            width,
+            pixels: HashMap::new(),
+            triples: Tuples::default(),
+        }
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> i64 {
+        *self.pixels.get(&(x, y)).unwrap_or(&0)
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, value: i64) {
+        self.pixels.insert((x, y), value);
+    }
+
+    pub fn count(&self, value: i64) -> usize {
+        self.pixels.values().filter(|&&v| v == value).count()
+    }
+
+    fn point(&self, addr: i64) -> (i64, i64) {
+        (addr % self.width, addr / self.width)
+    }
+}
+
+impl Device for FrameBuffer {
+    fn read(&mut self, addr: i64) -> i64 {
+        let (x, y) = self.point(addr);
+        self.get(x, y)
+    }
+
+    fn write(&mut self, addr: i64, value: i64) {
+        let (x, y) = self.point(addr);
+        self.set(x, y, value);
+    }
+
+    fn output(&mut self, value: i64) {
+        if let Some([x, y, value]) = self.triples.push(value) {
+            self.set(x, y, value);
+        }
+    }
+}
+
+// ASCII output as text, with any value past ASCII kept aside, e.g. a
+// number at the end of a report
+#[derive(Debug, Default)]
+pub struct Terminal {
+    pub text: String,
+    pub values: Vec<i64>,
+}
+
+impl Device for Terminal {
+    fn output(&mut self, value: i64) {
+        match value {
+            0..=127 => self.text.push(value as u8 as char),
+            _ => self.values.push(value),
+        }
+    }
+}
+
+// Typed ASCII input. Mapped, address 0 reads the next key (-1 when empty)
+// and address 1 the number of keys waiting.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    keys: VecDeque<i64>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn type_str(&mut self, s: &str) {
+        self.keys.extend(s.chars().map(|c| c as i64));
+    }
+}
+
+impl Device for Keyboard {
+    fn read(&mut self, addr: i64) -> i64 {
+        match addr {
+            0 => self.keys.pop_front().unwrap_or(-1),
+            _ => self.keys.len() as i64,
+        }
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        self.keys.pop_front()
+    }
+}
+
+// counts up by one on every read, writing sets the count
+#[derive(Debug, Default)]
+pub struct Clock {
+    pub ticks: i64,
+}
+
+impl Device for Clock {
+    fn read(&mut self, _addr: i64) -> i64 {
+        let t = self.ticks;
+        self.ticks += 1;
+        t
+    }
+
+    fn write(&mut self, _addr: i64, value: i64) {
+        self.ticks = value;
+    }
+}
+
+// xorshift random numbers, non-negative. Writing reseeds.
+#[derive(Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    fn next(&mut self) -> i64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 1) as i64
+    }
+}
+
+impl Device for Rng {
+    fn read(&mut self, _addr: i64) -> i64 {
+        self.next()
+    }
+
+    fn write(&mut self, _addr: i64, value: i64) {
+        self.state = (value as u64).max(1);
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        Some(self.next())
+    }
+}
+
+// where console maps the clock and random numbers, far past the memory
+// of any puzzle program
+pub const CLOCK: i64 = 1 << 32;
+pub const RNG: i64 = CLOCK + 1;
+
+// Run a program at a console: keys typed on the keyboard, output on a
+// terminal, and the clock and random numbers mapped at CLOCK and RNG.
+// Returns the terminal once the program halts or runs out of keys.
+pub fn console(code: &mut Intcode, keys: &str, seed: u64) -> Result<(Terminal, ExitMode), Error> {
+    let mut clock = Clock::default();
+    let mut rng = Rng::new(seed);
+    let mut dev = Split {
+        input: Keyboard::new(),
+        output: Terminal::default(),
+    };
+    dev.input.type_str(keys);
+    let mut bus = Bus::new();
+    bus.map(CLOCK..CLOCK + 1, &mut clock);
+    bus.map(RNG..RNG + 1, &mut rng);
+    bus.attach(&mut dev);
+    let exit = bus.run(code)?;
+    drop(bus);
+    Ok((dev.output, exit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped() {
+        // add clock ticks to the screen twice, then a key plus the number
+        // of keys left, then output the number of keys left
+        let mut code = Intcode::new("1,1000,1000,2001,1,1000,1000,2002,1,1100,1101,2003,4,1101,99");
+        let mut clock = Clock { ticks: 10 };
+        let mut keys = Keyboard::new();
+        keys.type_str("ab");
+        let mut screen = FrameBuffer::new(2);
+        {
+            let mut bus = Bus::new();
+            bus.map(1000..1001, &mut clock);
+            bus.map(1100..1102, &mut keys);
+            bus.map(2000..2004, &mut screen);
+            assert_eq!(Ok(ExitMode::Output(1)), bus.run(&mut code));
+            assert_eq!(Ok(ExitMode::Halt), bus.run(&mut code));
+        }
+        assert_eq!(14, clock.ticks);
+        assert_eq!(10 + 11, screen.get(1, 0));
+        assert_eq!(12 + 13, screen.get(0, 1));
+        assert_eq!('a' as i64 + 1, screen.get(1, 1));
+    }
+
+    #[test]
+    fn test_stream() {
+        // read two values and output them as a pixel with value 7
+        let mut code = Intcode::new("3,100,3,101,4,100,4,101,104,7,99");
+        let mut dev = Split {
+            input: Keyboard::new(),
+            output: FrameBuffer::new(8),
+        };
+        dev.input.type_str("\u{3}\u{4}");
+        {
+            let mut bus = Bus::new();
+            bus.attach(&mut dev);
+            assert_eq!(Ok(ExitMode::Halt), bus.run(&mut code));
+        }
+        assert_eq!(7, dev.output.get(3, 4));
+        assert_eq!(1, dev.output.count(7));
+        assert_eq!(
+            Ok(ExitMode::Halt),
+            dev.output.triples.finish(ExitMode::Halt)
+        );
+    }
+
+    #[test]
+    fn test_tuples() {
+        let mut t = Tuples::<2>::default();
+        assert_eq!(None, t.push(1));
+        assert_eq!(Some([1, 2]), t.push(2));
+        assert_eq!(None, t.push(3));
+        assert_eq!(
+            Err(Truncated {
+                outputs: vec![3],
+                expected: 2,
+                exit: ExitMode::Halt
+            }),
+            t.finish(ExitMode::Halt)
+        );
+    }
+
+    #[test]
+    fn test_terminal() {
+        let mut code = Intcode::new("104,104,104,105,104,10,104,1000,99");
+        let mut terminal = Terminal::default();
+        let mut bus = Bus::new();
+        bus.attach(&mut terminal);
+        assert_eq!(Ok(ExitMode::Halt), bus.run(&mut code));
+        drop(bus);
+        assert_eq!("hi\n", terminal.text);
+        assert_eq!(vec![1000], terminal.values);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame buffer width 0")]
+    fn test_zero_width() {
+        FrameBuffer::new(0);
+    }
+
+    #[test]
+    fn test_awaiting_input() {
+        let mut code = Intcode::new("3,100,99");
+        let mut keys = Keyboard::new();
+        let mut bus = Bus::new();
+        bus.attach(&mut keys);
+        assert_eq!(Ok(ExitMode::AwaitingInput), bus.run(&mut code));
+    }
+
+    #[test]
+    fn test_console() {
+        // echo a key, then output '0' plus the clock twice and whether a
+        // random number is negative
+        let mut code = Intcode::new(
+            "3,100,4,100,1001,4294967296,48,101,4,101,1001,4294967296,48,101,4,101,\
+             1007,4294967297,0,102,1001,102,10,102,4,102,99",
+        );
+        let (terminal, exit) = console(&mut code, "a", 1).unwrap();
+        assert_eq!(ExitMode::Halt, exit);
+        assert_eq!("a01\n", terminal.text);
+
+        let (terminal, exit) = console(&mut Intcode::new("3,100,4,100,3,100,99"), "b", 1).unwrap();
+        assert_eq!(ExitMode::AwaitingInput, exit);
+        assert_eq!("b", terminal.text);
+    }
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let x: Vec<i64> = (0..5).map(|_| a.read(0)).collect();
+        let y: Vec<i64> = (0..5).map(|_| b.input().unwrap()).collect();
+        assert_eq!(x, y);
+        assert!(x.iter().all(|&v| v >= 0));
+    }
+}
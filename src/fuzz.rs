@@ -0,0 +1,588 @@
+use crate::intcode;
+use crate::intcode::ExitMode;
+use crate::unwind;
+use crate::unwind::catch;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+use std::fmt;
+
+// what a run of a program did
+#[derive(PartialEq, Debug, Clone)]
+pub struct Outcome {
+    pub outputs: Vec<i64>,
+    pub memory: Vec<i64>,
+    // the panic message, if the run panicked
+    pub error: Option<String>,
+}
+
+// parse the comma separated memory printed by an Intcode's Display
+pub fn memory(s: &str) -> Vec<i64> {
+    s.split(',').filter_map(|x| x.parse().ok()).collect()
+}
+
+fn run_intcode(data: &str, input: &[i64]) -> Outcome {
+    let mut code = intcode::Intcode::new(data);
+    let mut queue: VecDeque<i64> = input.iter().copied().collect();
+    let mut outputs = Vec::new();
+    let error = catch(|| loop {
+        match code.run_queue(&mut queue) {
+            ExitMode::Halt => break,
+            ExitMode::Output(x) => outputs.push(x),
+            ExitMode::AwaitingInput => panic!("not enough inputs"),
+        }
+    });
+    Outcome {
+        outputs,
+        memory: memory(&code.to_string()),
+        error,
+    }
+}
+
+pub struct Implementation {
+    pub name: &'static str,
+    pub run: fn(&str, &[i64]) -> Outcome,
+    // reads the same input every time, so it can only be compared when
+    // all the inputs are the same
+    pub single_input: bool,
+    // has relative mode and opcode 9
+    pub relative: bool,
+}
+
+pub fn implementations() -> Vec<Implementation> {
+    vec![
+        Implementation {
+            name: "intcode",
+            run: run_intcode,
+            single_input: false,
+            relative: true,
+        },
+        Implementation {
+            name: "day5",
+            run: crate::day5::run_program,
+            single_input: true,
+            relative: false,
+        },
+        Implementation {
+            name: "day7",
+            run: crate::day7::run_program,
+            single_input: false,
+            relative: false,
+        },
+        Implementation {
+            name: "day9",
+            run: crate::day9::run_program,
+            single_input: false,
+            relative: true,
+        },
+    ]
+}
+
+// the most an adjustment moves the relative base
+const MAX_ADJUST: i64 = 3;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Arg {
+    Imm(i64),
+    Data(usize),
+    // a data cell addressed from the relative base, so past it by however
+    // far the base has moved
+    Rel(usize),
+}
+
+// Instructions are generated with data cells instead of addresses and
+// jump targets as the index of a later instruction, so cases can be
+// shrunk and reassembled. Writes are never immediate. The relative base
+// only moves forward, into spare cells after the data, so nothing writes
+// to code, and every jump goes forward, so every program terminates.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Instr {
+    Add(Arg, Arg, Arg),
+    Mul(Arg, Arg, Arg),
+    Input(Arg),
+    Output(Arg),
+    JumpTrue(Arg, usize),
+    JumpFalse(Arg, usize),
+    LessThan(Arg, Arg, Arg),
+    Equals(Arg, Arg, Arg),
+    // by 0 to MAX_ADJUST
+    AdjustBase(i64),
+}
+
+impl Instr {
+    fn len(&self) -> usize {
+        match self {
+            Instr::Input(_) | Instr::Output(_) | Instr::AdjustBase(_) => 2,
+            Instr::JumpTrue(_, _) | Instr::JumpFalse(_, _) => 3,
+            _ => 4,
+        }
+    }
+
+    fn args(&self) -> Vec<Arg> {
+        match *self {
+            Instr::Add(x, y, r)
+            | Instr::Mul(x, y, r)
+            | Instr::LessThan(x, y, r)
+            | Instr::Equals(x, y, r) => vec![x, y, r],
+            Instr::Input(x) | Instr::Output(x) | Instr::JumpTrue(x, _) | Instr::JumpFalse(x, _) => {
+                vec![x]
+            }
+            Instr::AdjustBase(_) => vec![],
+        }
+    }
+
+    // uses relative mode or opcode 9
+    fn relative(&self) -> bool {
+        matches!(self, Instr::AdjustBase(_)) || self.args().iter().any(|a| matches!(a, Arg::Rel(_)))
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Case {
+    pub instrs: Vec<Instr>,
+    pub data: Vec<i64>,
+    pub inputs: Vec<i64>,
+}
+
+impl Case {
+    // the program as comma separated text: the instructions, a halt, the
+    // data cells and then a zero for every cell the relative base can
+    // move past them
+    pub fn program(&self) -> String {
+        let mut addrs = Vec::new();
+        let mut a = 0;
+        for i in self.instrs.iter() {
+            addrs.push(a);
+            a += i.len();
+        }
+        // jumps to the end go to the halt
+        addrs.push(a);
+        let data = a + 1;
+
+        let arg = |x: &Arg| match *x {
+            Arg::Imm(v) => (1, v),
+            Arg::Data(d) => (0, (data + d) as i64),
+            Arg::Rel(d) => (2, (data + d) as i64),
+        };
+
+        let mut v: Vec<i64> = Vec::new();
+        for i in self.instrs.iter() {
+            match i {
+                Instr::Add(x, y, r)
+                | Instr::Mul(x, y, r)
+                | Instr::LessThan(x, y, r)
+                | Instr::Equals(x, y, r) => {
+                    let code = match i {
+                        Instr::Add(..) => 1,
+                        Instr::Mul(..) => 2,
+                        Instr::LessThan(..) => 7,
+                        _ => 8,
+                    };
+                    let (mx, x) = arg(x);
+                    let (my, y) = arg(y);
+                    let (mr, r) = arg(r);
+                    v.extend(&[code + 100 * mx + 1000 * my + 10000 * mr, x, y, r]);
+                }
+                Instr::Input(r) => {
+                    let (m, r) = arg(r);
+                    v.extend(&[3 + 100 * m, r]);
+                }
+                Instr::AdjustBase(k) => v.extend(&[109, *k]),
+                Instr::Output(x) => {
+                    let (m, x) = arg(x);
+                    v.extend(&[4 + 100 * m, x]);
+                }
+                Instr::JumpTrue(c, t) | Instr::JumpFalse(c, t) => {
+                    let code = match i {
+                        Instr::JumpTrue(..) => 5,
+                        _ => 6,
+                    };
+                    let (m, c) = arg(c);
+                    v.extend(&[code + 100 * m + 1000, c, addrs[*t] as i64]);
+                }
+            }
+        }
+        v.push(99);
+        v.extend(&self.data);
+        let adjusts = self
+            .instrs
+            .iter()
+            .filter(|i| matches!(i, Instr::AdjustBase(_)))
+            .count();
+        v.extend(vec![0; adjusts * MAX_ADJUST as usize]);
+
+        let s: Vec<String> = v.iter().map(|x| x.to_string()).collect();
+        s.join(",")
+    }
+
+    fn same_inputs(&self) -> bool {
+        self.inputs.windows(2).all(|w| w[0] == w[1])
+    }
+
+    fn relative(&self) -> bool {
+        self.instrs.iter().any(|i| i.relative())
+    }
+
+    // remove an instruction, moving jumps past it back by one
+    fn without(&self, n: usize) -> Self {
+        let fix = |t: usize| if t > n { t - 1 } else { t };
+        let instrs = self
+            .instrs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != n)
+            .map(|(_, i)| match *i {
+                Instr::JumpTrue(c, t) => Instr::JumpTrue(c, fix(t)),
+                Instr::JumpFalse(c, t) => Instr::JumpFalse(c, fix(t)),
+                i => i,
+            })
+            .collect();
+        Self {
+            instrs,
+            data: self.data.clone(),
+            inputs: self.inputs.clone(),
+        }
+    }
+
+    // every case with one immediate value moved towards 0
+    fn smaller_constants(&self) -> Vec<Self> {
+        let shrink = |v: i64| if v.abs() > 1 { vec![0, v / 2] } else { vec![0] };
+        let mut cases = Vec::new();
+        for n in 0..self.instrs.len() {
+            let args: Vec<(usize, i64)> = match self.instrs[n] {
+                Instr::Add(x, y, _)
+                | Instr::Mul(x, y, _)
+                | Instr::LessThan(x, y, _)
+                | Instr::Equals(x, y, _) => vec![(0, x), (1, y)],
+                Instr::Output(x) | Instr::JumpTrue(x, _) | Instr::JumpFalse(x, _) => vec![(0, x)],
+                Instr::AdjustBase(k) => vec![(0, Arg::Imm(k))],
+                Instr::Input(_) => vec![],
+            }
+            .into_iter()
+            .filter_map(|(k, a)| match a {
+                Arg::Imm(v) if v != 0 => Some((k, v)),
+                _ => None,
+            })
+            .collect();
+
+            for (k, v) in args {
+                for s in shrink(v) {
+                    let mut c = self.clone();
+                    let a = Arg::Imm(s);
+                    c.instrs[n] = match (c.instrs[n], k) {
+                        (Instr::Add(_, y, r), 0) => Instr::Add(a, y, r),
+                        (Instr::Add(x, _, r), _) => Instr::Add(x, a, r),
+                        (Instr::Mul(_, y, r), 0) => Instr::Mul(a, y, r),
+                        (Instr::Mul(x, _, r), _) => Instr::Mul(x, a, r),
+                        (Instr::LessThan(_, y, r), 0) => Instr::LessThan(a, y, r),
+                        (Instr::LessThan(x, _, r), _) => Instr::LessThan(x, a, r),
+                        (Instr::Equals(_, y, r), 0) => Instr::Equals(a, y, r),
+                        (Instr::Equals(x, _, r), _) => Instr::Equals(x, a, r),
+                        (Instr::Output(_), _) => Instr::Output(a),
+                        (Instr::JumpTrue(_, t), _) => Instr::JumpTrue(a, t),
+                        (Instr::JumpFalse(_, t), _) => Instr::JumpFalse(a, t),
+                        (Instr::AdjustBase(_), _) => Instr::AdjustBase(s),
+                        (i, _) => i,
+                    };
+                    cases.push(c);
+                }
+            }
+        }
+        for n in 0..self.data.len() {
+            if self.data[n] != 0 {
+                for s in shrink(self.data[n]) {
+                    let mut c = self.clone();
+                    c.data[n] = s;
+                    cases.push(c);
+                }
+            }
+        }
+        cases
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub case: Case,
+    pub a: &'static str,
+    pub b: &'static str,
+    pub what: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} and {} differ: {}", self.a, self.b, self.what)?;
+        writeln!(f, "program: {}", self.case.program())?;
+        write!(f, "inputs: {:?}", self.case.inputs)
+    }
+}
+
+// how two outcomes first differ, if they do
+fn compare(a: &Outcome, b: &Outcome) -> Option<String> {
+    if let Some(i) =
+        (0..a.outputs.len().max(b.outputs.len())).find(|&i| a.outputs.get(i) != b.outputs.get(i))
+    {
+        return Some(format!(
+            "output {}: {:?} vs {:?}",
+            i,
+            a.outputs.get(i),
+            b.outputs.get(i)
+        ));
+    }
+    if let Some(i) =
+        (0..a.memory.len().max(b.memory.len())).find(|&i| a.memory.get(i) != b.memory.get(i))
+    {
+        return Some(format!(
+            "memory {}: {:?} vs {:?}",
+            i,
+            a.memory.get(i),
+            b.memory.get(i)
+        ));
+    }
+    if a.error.is_some() != b.error.is_some() {
+        return Some(format!("panic: {:?} vs {:?}", a.error, b.error));
+    }
+    None
+}
+
+// Generates random programs and runs them on every implementation,
+// comparing each against the first.
+pub struct Fuzzer {
+    rng: StdRng,
+    impls: Vec<Implementation>,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u64, impls: Vec<Implementation>) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            impls,
+        }
+    }
+
+    // a random value in lo..=hi
+    fn value(&mut self, lo: i64, hi: i64) -> i64 {
+        lo + (self.rng.gen::<u64>() % (hi - lo + 1) as u64) as i64
+    }
+
+    fn index(&mut self, n: usize) -> usize {
+        self.rng.gen::<u64>() as usize % n
+    }
+
+    fn arg(&mut self, data: usize, relative: bool) -> Arg {
+        if self.rng.gen::<bool>() {
+            Arg::Imm(self.value(-20, 20))
+        } else {
+            self.dest(data, relative)
+        }
+    }
+
+    // where to write
+    fn dest(&mut self, data: usize, relative: bool) -> Arg {
+        if relative && self.rng.gen::<bool>() {
+            Arg::Rel(self.index(data))
+        } else {
+            Arg::Data(self.index(data))
+        }
+    }
+
+    // Values stay small enough for the 32 bit implementations: at most
+    // two multiplies, each by a single digit. Half the cases use relative
+    // mode, which only some implementations have.
+    pub fn generate(&mut self) -> Case {
+        let data = 1 + self.index(4);
+        let len = 1 + self.index(12);
+        let rel = self.rng.gen::<bool>();
+        let mut instrs = Vec::new();
+        let mut muls = 0;
+        for n in 0..len {
+            let target = n + 1 + self.index(len - n);
+            let i = match self.index(9) {
+                0 => Instr::Add(
+                    self.arg(data, rel),
+                    self.arg(data, rel),
+                    self.dest(data, rel),
+                ),
+                1 if muls < 2 => {
+                    muls += 1;
+                    let x = self.arg(data, rel);
+                    Instr::Mul(x, Arg::Imm(self.value(-9, 9)), self.dest(data, rel))
+                }
+                2 => Instr::Input(self.dest(data, rel)),
+                3 => Instr::JumpTrue(self.arg(data, rel), target),
+                4 => Instr::JumpFalse(self.arg(data, rel), target),
+                5 => Instr::LessThan(
+                    self.arg(data, rel),
+                    self.arg(data, rel),
+                    self.dest(data, rel),
+                ),
+                6 => Instr::Equals(
+                    self.arg(data, rel),
+                    self.arg(data, rel),
+                    self.dest(data, rel),
+                ),
+                7 if rel => Instr::AdjustBase(self.value(0, MAX_ADJUST)),
+                _ => Instr::Output(self.arg(data, rel)),
+            };
+            instrs.push(i);
+        }
+
+        let reads = instrs
+            .iter()
+            .filter(|i| matches!(i, Instr::Input(_)))
+            .count();
+        let inputs = if self.rng.gen::<bool>() {
+            vec![self.value(-50, 50); reads]
+        } else {
+            (0..reads).map(|_| self.value(-50, 50)).collect()
+        };
+        Case {
+            instrs,
+            data: (0..data).map(|_| self.value(-20, 20)).collect(),
+            inputs,
+        }
+    }
+
+    // the first implementation that differs from the first one, which
+    // should have relative mode. Panics are expected, so they are kept
+    // quiet.
+    pub fn check(&self, case: &Case) -> Option<Divergence> {
+        unwind::quietly(|| self.compare_all(case))
+    }
+
+    fn compare_all(&self, case: &Case) -> Option<Divergence> {
+        let program = case.program();
+        let reference = &self.impls[0];
+        let expected = (reference.run)(&program, &case.inputs);
+        for imp in self.impls.iter().skip(1) {
+            if imp.single_input && !case.same_inputs() || case.relative() && !imp.relative {
+                continue;
+            }
+            let got = (imp.run)(&program, &case.inputs);
+            if let Some(what) = compare(&expected, &got) {
+                return Some(Divergence {
+                    case: case.clone(),
+                    a: reference.name,
+                    b: imp.name,
+                    what,
+                });
+            }
+        }
+        None
+    }
+
+    // Shrink a divergence by removing instructions and moving constants
+    // towards 0 for as long as the same two implementations still differ.
+    pub fn minimize(&self, d: Divergence) -> Divergence {
+        let mut d = d;
+        loop {
+            let case = &d.case;
+            let candidates = (0..case.instrs.len())
+                .map(|n| case.without(n))
+                .chain(case.smaller_constants());
+            let smaller = candidates
+                .filter_map(|c| self.check(&c))
+                .find(|n| n.a == d.a && n.b == d.b);
+            match smaller {
+                Some(n) => d = n,
+                None => return d,
+            }
+        }
+    }
+
+    // run up to count random cases, returning the first divergence found,
+    // minimized
+    pub fn run(&mut self, count: usize) -> Option<Divergence> {
+        for _ in 0..count {
+            let case = self.generate();
+            if let Some(d) = self.check(&case) {
+                return Some(self.minimize(d));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program() {
+        let case = Case {
+            instrs: vec![
+                Instr::Input(Arg::Data(0)),
+                Instr::JumpFalse(Arg::Data(0), 3),
+                Instr::Output(Arg::Imm(-1)),
+                Instr::Mul(Arg::Data(0), Arg::Imm(3), Arg::Data(1)),
+                Instr::Output(Arg::Data(1)),
+            ],
+            data: vec![0, 0],
+            inputs: vec![2],
+        };
+        assert_eq!(
+            "3,14,1006,14,7,104,-1,1002,14,3,15,4,15,99,0,0",
+            case.program()
+        );
+        let o = run_intcode(&case.program(), &case.inputs);
+        assert_eq!(vec![-1, 6], o.outputs);
+        assert_eq!(None, o.error);
+    }
+
+    #[test]
+    fn test_relative() {
+        // read into the spare cell after the data and output it
+        let case = Case {
+            instrs: vec![
+                Instr::AdjustBase(2),
+                Instr::Input(Arg::Rel(0)),
+                Instr::Output(Arg::Rel(0)),
+                Instr::Output(Arg::Data(0)),
+            ],
+            data: vec![0, 0],
+            inputs: vec![5],
+        };
+        assert!(case.relative());
+        assert_eq!("109,2,203,9,204,9,4,9,99,0,0,0,0,0", case.program());
+        let o = run_intcode(&case.program(), &case.inputs);
+        assert_eq!(vec![5, 0], o.outputs);
+        assert_eq!(5, o.memory[11]);
+        assert!(Fuzzer::new(0, implementations()).check(&case).is_none());
+
+        let mut f = Fuzzer::new(2019, implementations());
+        let cases: Vec<Case> = (0..100).map(|_| f.generate()).collect();
+        assert!(cases.iter().any(|c| c.relative()));
+        assert!(cases.iter().any(|c| !c.relative()));
+    }
+
+    #[test]
+    fn test_implementations_agree() {
+        let mut f = Fuzzer::new(2019, implementations());
+        if let Some(d) = f.run(300) {
+            panic!("{}", d);
+        }
+    }
+
+    // a broken implementation that loses the sign of negative outputs
+    fn abs_outputs(data: &str, input: &[i64]) -> Outcome {
+        let mut o = run_intcode(data, input);
+        o.outputs = o.outputs.iter().map(|x| x.abs()).collect();
+        o
+    }
+
+    #[test]
+    fn test_divergence() {
+        let mut impls = implementations();
+        impls.push(Implementation {
+            name: "abs",
+            run: abs_outputs,
+            single_input: false,
+            relative: true,
+        });
+        let mut f = Fuzzer::new(7, impls);
+        let d = f.run(300).expect("no divergence found");
+        assert_eq!(("intcode", "abs"), (d.a, d.b));
+        assert_eq!(1, d.case.instrs.len());
+        assert!(matches!(d.case.instrs[0], Instr::Output(_)));
+    }
+}
@@ -0,0 +1,552 @@
+use crate::intcode::{ExitMode, Intcode, NoExtension};
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// GDB remote serial protocol stub. Memory is byte addressed, as gdb
+// expects: Intcode address n is the 8 bytes at 8n, little endian. There
+// are two 64-bit registers, described to gdb in TARGET_XML: 0 is the PC,
+// as a byte address like memory, and 1 the relative base, as it is.
+//
+// Input comes from a queue given up front, more can be added with
+// "monitor input 1,2,3". Outputs are sent to the console as they happen.
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// bytes in an Intcode word
+const WORD: i64 = 8;
+
+// the most bytes m or M may cover, to fit in a packet
+const MAX_BYTES: i64 = 0x4000 / 2;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.intcode.core">
+    <reg name="pc" bitsize="64" type="code_ptr" regnum="0"/>
+    <reg name="rb" bitsize="64" type="int64" regnum="1"/>
+  </feature>
+</target>
+"#;
+
+// how many instructions to run between checks for an interrupt
+const POLL_STEPS: usize = 10_000;
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b))
+}
+
+fn packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data))
+}
+
+fn word_hex(x: i64) -> String {
+    x.to_le_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn text_hex(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_text(s: &str) -> Result<String, String> {
+    if !s.is_ascii() {
+        return Err(format!("bad hex {:?}", s));
+    }
+    let b = (0..s.len() / 2)
+        .map(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(b).map_err(|e| e.to_string())
+}
+
+// a hex address, register or length, which can't be negative
+fn number(s: &str) -> Result<i64, String> {
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("bad number {:?}", s));
+    }
+    let x = u64::from_str_radix(s, 16).map_err(|e| format!("{}: {:?}", e, s))?;
+    if x > i64::MAX as u64 {
+        return Err(format!("number too big {:?}", s));
+    }
+    Ok(x as i64)
+}
+
+// "addr,len" as used by m and M, both in hex bytes
+fn range(s: &str) -> Result<(i64, i64), String> {
+    let mut parts = s.split(',');
+    let (addr, len) = match (parts.next(), parts.next()) {
+        (Some(a), Some(n)) => (number(a)?, number(n)?),
+        _ => return Err(format!("bad range {:?}", s)),
+    };
+    if len > MAX_BYTES || addr.checked_add(len).is_none() {
+        return Err(format!("bad range {:?}", s));
+    }
+    Ok((addr, len))
+}
+
+fn byte(reader: &mut BufReader<TcpStream>) -> Option<io::Result<u8>> {
+    let mut b = [0u8; 1];
+    match reader.read(&mut b) {
+        Ok(0) => None,
+        Ok(_) => Some(Ok(b[0])),
+        Err(e) => Some(Err(e)),
+    }
+}
+
+// whether an interrupt (0x03) has been sent, without blocking. Anything
+// else sent while the program runs is dropped.
+fn interrupt(reader: &mut BufReader<TcpStream>) -> bool {
+    if reader.get_ref().set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut found = false;
+    while let Ok(b) = reader.fill_buf() {
+        if b.is_empty() {
+            break;
+        }
+        found |= b.contains(&0x03);
+        let n = b.len();
+        reader.consume(n);
+    }
+    reader.get_ref().set_nonblocking(false).is_ok() && found
+}
+
+pub struct Stub {
+    code: Intcode,
+    input: VecDeque<i64>,
+    // by Intcode address
+    breakpoints: BTreeSet<i64>,
+    // set once the debugger kills or detaches
+    done: bool,
+}
+
+impl Stub {
+    pub fn new(code: &Intcode, input: &[i64]) -> Self {
+        Self {
+            code: code.clone(),
+            input: input.iter().copied().collect(),
+            breakpoints: BTreeSet::new(),
+            done: false,
+        }
+    }
+
+    // the PC as a byte address
+    fn pc(&self) -> i64 {
+        self.code.pc().saturating_mul(WORD)
+    }
+
+    fn peek_byte(&self, addr: i64) -> u8 {
+        self.code.peek(addr / WORD).to_le_bytes()[(addr % WORD) as usize]
+    }
+
+    fn poke_byte(&mut self, addr: i64, x: u8) {
+        let mut b = self.code.peek(addr / WORD).to_le_bytes();
+        b[(addr % WORD) as usize] = x;
+        self.code.set_imm(addr / WORD, i64::from_le_bytes(b));
+    }
+
+    // "annex:offset,length" of qXfer:features:read, giving the chunk of
+    // the target description with 'm' if there is more or 'l' if not
+    fn features(&self, args: &str) -> Result<String, String> {
+        let (annex, range) = args
+            .split_once(':')
+            .ok_or_else(|| format!("bad qXfer {:?}", args))?;
+        if annex != "target.xml" {
+            return Ok("E00".to_string());
+        }
+        let (offset, len) = range
+            .split_once(',')
+            .ok_or_else(|| format!("bad qXfer {:?}", args))?;
+        let size = TARGET_XML.len() as i64;
+        let start = number(offset)?.min(size);
+        let end = start.saturating_add(number(len)?).min(size);
+        let more = if end < size { 'm' } else { 'l' };
+        Ok(format!(
+            "{}{}",
+            more,
+            &TARGET_XML[start as usize..end as usize]
+        ))
+    }
+
+    // Run one instruction, or until a breakpoint or interrupt when
+    // continuing. Returns the packets to send: console output then the
+    // stop reply.
+    fn resume(&mut self, single: bool, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        let mut replies = Vec::new();
+        let input = &mut self.input;
+        let mut steps = 0;
+        loop {
+            match self
+                .code
+                .step_ext(&mut NoExtension, &mut || input.pop_front())
+            {
+                Err(e) => {
+                    replies.push(format!("O{}", text_hex(&format!("{}\n", e))));
+                    replies.push(format!("S{:02x}", SIGILL));
+                    return replies;
+                }
+                Ok(Some(ExitMode::Halt)) => {
+                    replies.push("W00".to_string());
+                    return replies;
+                }
+                Ok(Some(ExitMode::AwaitingInput)) => {
+                    replies.push(format!("O{}", text_hex("awaiting input\n")));
+                    replies.push(format!("S{:02x}", SIGTRAP));
+                    return replies;
+                }
+                Ok(Some(ExitMode::Output(x))) => {
+                    replies.push(format!("O{}", text_hex(&format!("{}\n", x))));
+                }
+                Ok(None) => (),
+            }
+            if single || self.breakpoints.contains(&self.code.pc()) {
+                replies.push(format!("S{:02x}", SIGTRAP));
+                return replies;
+            }
+            steps += 1;
+            if steps % POLL_STEPS == 0 && interrupted() {
+                replies.push(format!("S{:02x}", SIGINT));
+                return replies;
+            }
+        }
+    }
+
+    fn monitor(&mut self, cmd: &str) -> Result<String, String> {
+        let mut words = cmd.splitn(2, ' ');
+        match (words.next(), words.next()) {
+            (Some("input"), Some(values)) => {
+                for v in values.split(|c: char| c == ',' || c.is_whitespace()) {
+                    if !v.is_empty() {
+                        let v = v.parse().map_err(|_| format!("bad integer {:?}", v))?;
+                        self.input.push_back(v);
+                    }
+                }
+                Ok("OK".to_string())
+            }
+            _ => Err(format!("unknown command {:?}", cmd)),
+        }
+    }
+
+    fn command(
+        &mut self,
+        p: &str,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Result<Vec<String>, String> {
+        let reply = match p.chars().next() {
+            Some('?') => format!("S{:02x}", SIGTRAP),
+            Some('g') => word_hex(self.pc()) + &word_hex(self.code.relative_base()),
+            Some('p') => match number(&p[1..])? {
+                0 => word_hex(self.pc()),
+                1 => word_hex(self.code.relative_base()),
+                _ => "E00".to_string(),
+            },
+            Some('m') => {
+                let (addr, len) = range(&p[1..])?;
+                (addr..addr + len)
+                    .map(|a| format!("{:02x}", self.peek_byte(a)))
+                    .collect()
+            }
+            Some('M') => {
+                let mut parts = p[1..].splitn(2, ':');
+                let (addr, len) = range(parts.next().unwrap_or(""))?;
+                let data = parts.next().unwrap_or("");
+                if data.len() != len as usize * 2 || !data.is_ascii() {
+                    return Err(format!("expected {} bytes", len));
+                }
+                for n in 0..len {
+                    let i = n as usize * 2;
+                    let x = u8::from_str_radix(&data[i..i + 2], 16).map_err(|e| e.to_string())?;
+                    self.poke_byte(addr + n, x);
+                }
+                "OK".to_string()
+            }
+            Some('c') => return Ok(self.resume(false, interrupted)),
+            Some('s') => return Ok(self.resume(true, interrupted)),
+            Some('Z') | Some('z') if p[1..].starts_with("0,") => {
+                let addr = number(p[3..].split(',').next().unwrap_or(""))?;
+                if addr % WORD != 0 {
+                    return Err(format!("unaligned breakpoint {:x}", addr));
+                }
+                if p.starts_with('Z') {
+                    self.breakpoints.insert(addr / WORD);
+                } else {
+                    self.breakpoints.remove(&(addr / WORD));
+                }
+                "OK".to_string()
+            }
+            Some('k') => {
+                self.done = true;
+                return Ok(vec![]);
+            }
+            Some('D') => {
+                self.done = true;
+                "OK".to_string()
+            }
+            _ if p.starts_with("qSupported") => "PacketSize=4000;qXfer:features:read+".to_string(),
+            _ if p.starts_with("qXfer:features:read:") => self.features(&p[20..])?,
+            _ if p == "qAttached" => "1".to_string(),
+            _ if p.starts_with("qRcmd,") => {
+                let cmd = hex_text(&p[6..])?;
+                match self.monitor(&cmd) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let e = format!("O{}", text_hex(&format!("{}\n", e)));
+                        return Ok(vec![e, "E01".to_string()]);
+                    }
+                }
+            }
+            // anything else is unsupported
+            _ => String::new(),
+        };
+        Ok(vec![reply])
+    }
+
+    // the replies to one packet's data, checking interrupted now and then
+    // while continuing
+    fn reply(&mut self, p: &str, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        match self.command(p, interrupted) {
+            Ok(replies) => replies,
+            Err(_) => vec!["E01".to_string()],
+        }
+    }
+
+    // the replies to one packet's data, with no interrupts
+    #[cfg(test)]
+    pub fn handle(&mut self, p: &str) -> Vec<String> {
+        self.reply(p, &mut || false)
+    }
+
+    // Serve one debugger connection until it kills, detaches or hangs up.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut out = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        while !self.done {
+            // skip acks and interrupts until the start of a packet
+            let b = match byte(&mut reader) {
+                None => return Ok(()),
+                Some(b) => b?,
+            };
+            if b != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            loop {
+                match byte(&mut reader) {
+                    None => return Ok(()),
+                    Some(b) => match b? {
+                        b'#' => break,
+                        b => data.push(b),
+                    },
+                }
+            }
+            let mut sum = String::new();
+            for _ in 0..2 {
+                match byte(&mut reader) {
+                    None => return Ok(()),
+                    Some(b) => sum.push(b? as char),
+                }
+            }
+            let data = String::from_utf8_lossy(&data).to_string();
+            if u8::from_str_radix(&sum, 16).ok() != Some(checksum(&data)) {
+                out.write_all(b"-")?;
+                continue;
+            }
+            out.write_all(b"+")?;
+            let mut interrupted = || interrupt(&mut reader);
+            for reply in self.reply(&data, &mut interrupted) {
+                out.write_all(packet(&reply).as_bytes())?;
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    // e.g. TcpListener::bind("127.0.0.1:1234"), then "target remote :1234"
+    pub fn listen(&mut self, listener: TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // read x, output x * 2, halt
+    const DOUBLE: &str = "3,9,1002,9,2,10,4,10,99,0,0";
+
+    #[test]
+    fn test_packet() {
+        assert_eq!("$OK#9a", packet("OK"));
+        assert_eq!("$#00", packet(""));
+        assert_eq!("0100000000000000", word_hex(1));
+        assert_eq!(Ok("input 5".to_string()), hex_text(&text_hex("input 5")));
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut stub = Stub::new(&Intcode::new(DOUBLE), &[]);
+        assert_eq!(vec![word_hex(0) + &word_hex(0)], stub.handle("g"));
+        assert_eq!(
+            vec![word_hex(3) + &word_hex(9) + &word_hex(1002)],
+            stub.handle("m0,18")
+        );
+        // part of a word, 1002 is 0x3ea
+        assert_eq!(vec!["ea03"], stub.handle("m10,2"));
+        assert_eq!(vec!["E01"], stub.handle("mzz,1"));
+        assert_eq!(vec!["E01"], stub.handle("m-1,1"));
+        assert_eq!(vec!["E01"], stub.handle(&format!("M-1,1:{}", word_hex(7))));
+        assert_eq!(vec!["E01"], stub.handle("m0,7fffffffffffffff"));
+        assert_eq!(vec!["E01"], stub.handle("m7fffffffffffffff,2"));
+        assert_eq!(vec!["E01"], stub.handle("M0,-1:"));
+        assert_eq!(vec!["E01"], stub.handle("M0,2:\u{e9}\u{e9}"));
+        assert_eq!(vec!["E01"], stub.handle("Z0,31,8"));
+        assert_eq!(vec!["OK"], stub.handle("Z0,30,8"));
+
+        // nothing to read yet
+        assert_eq!(
+            vec![
+                format!("O{}", text_hex("awaiting input\n")),
+                "S05".to_string()
+            ],
+            stub.handle("c")
+        );
+        let cmd = text_hex("input 21");
+        assert_eq!(vec!["OK"], stub.handle(&format!("qRcmd,{}", cmd)));
+
+        // stops at the breakpoint on the output
+        assert_eq!(vec!["S05"], stub.handle("c"));
+        assert_eq!(vec![word_hex(48)], stub.handle("p0"));
+        assert_eq!(vec![word_hex(42)], stub.handle("m50,8"));
+
+        // patch the low byte of the result before it is output
+        assert_eq!(vec!["OK"], stub.handle("M50,1:07"));
+        assert_eq!(
+            vec![format!("O{}", text_hex("7\n")), "S05".to_string()],
+            stub.handle("s")
+        );
+        assert_eq!(vec!["W00"], stub.handle("c"));
+    }
+
+    #[test]
+    fn test_features() {
+        let mut stub = Stub::new(&Intcode::new(DOUBLE), &[]);
+        let read = |offset: usize, len: usize| {
+            format!("qXfer:features:read:target.xml:{:x},{:x}", offset, len)
+        };
+        assert_eq!(
+            vec![format!("l{}", TARGET_XML)],
+            stub.handle(&read(0, 0x1000))
+        );
+        assert_eq!(
+            vec![format!("m{}", &TARGET_XML[..16])],
+            stub.handle(&read(0, 16))
+        );
+        assert_eq!(
+            vec![format!("l{}", &TARGET_XML[16..])],
+            stub.handle(&read(16, 0x1000))
+        );
+        assert_eq!(vec!["l"], stub.handle(&read(0x1000, 16)));
+        assert_eq!(
+            vec!["E00"],
+            stub.handle("qXfer:features:read:other.xml:0,10")
+        );
+        assert_eq!(vec!["E01"], stub.handle("qXfer:features:read:target.xml"));
+        assert!(TARGET_XML.contains(r#"<reg name="pc" bitsize="64""#));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        // loop forever
+        let mut stub = Stub::new(&Intcode::new("1105,1,0"), &[]);
+        let mut polls = 0;
+        let mut interrupted = || {
+            polls += 1;
+            polls == 3
+        };
+        assert_eq!(vec!["S02"], stub.reply("c", &mut interrupted));
+    }
+
+    // send a packet and read replies up to and including a stop reply or
+    // plain response, skipping acks
+    fn exchange(stream: &mut TcpStream, data: &str, replies: usize) -> Vec<String> {
+        stream.write_all(packet(data).as_bytes()).unwrap();
+        receive(stream, replies)
+    }
+
+    fn receive(stream: &mut TcpStream, replies: usize) -> Vec<String> {
+        let mut got = Vec::new();
+        let mut b = [0u8; 1];
+        while got.len() < replies {
+            stream.read_exact(&mut b).unwrap();
+            if b[0] != b'$' {
+                continue;
+            }
+            let mut s = String::new();
+            loop {
+                stream.read_exact(&mut b).unwrap();
+                if b[0] == b'#' {
+                    break;
+                }
+                s.push(b[0] as char);
+            }
+            let mut sum = [0u8; 2];
+            stream.read_exact(&mut sum).unwrap();
+            assert_eq!(format!("{:02x}", checksum(&s)).as_bytes(), &sum);
+            got.push(s);
+        }
+        got
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut stub = Stub::new(&Intcode::new(DOUBLE), &[5]);
+            stub.listen(listener).unwrap();
+        });
+
+        let mut s = TcpStream::connect(addr).unwrap();
+        s.write_all(b"+").unwrap();
+        assert_eq!(
+            vec!["PacketSize=4000;qXfer:features:read+"],
+            exchange(&mut s, "qSupported:swbreak+", 1)
+        );
+        assert_eq!(vec!["S05"], exchange(&mut s, "?", 1));
+        assert_eq!(vec!["S05"], exchange(&mut s, "s", 1));
+        assert_eq!(vec![word_hex(16)], exchange(&mut s, "p0", 1));
+        assert_eq!(
+            vec![format!("O{}", text_hex("10\n")), "W00".to_string()],
+            exchange(&mut s, "c", 2)
+        );
+        assert_eq!(vec![""], exchange(&mut s, "vMustReplyEmpty", 1));
+        assert_eq!(vec!["OK"], exchange(&mut s, "D", 1));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_interrupt() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut stub = Stub::new(&Intcode::new("1105,1,0"), &[]);
+            stub.listen(listener).unwrap();
+        });
+
+        let mut s = TcpStream::connect(addr).unwrap();
+        s.write_all(packet("c").as_bytes()).unwrap();
+        s.write_all(&[0x03]).unwrap();
+        assert_eq!(vec!["S02"], receive(&mut s, 1));
+        assert_eq!(vec!["OK"], exchange(&mut s, "D", 1));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::str::FromStr;
+
+// Where a puzzle input comes from
+#[derive(PartialEq, Debug, Clone)]
+pub enum Source {
+    File(String),
+    Stdin,
+    // compiled in, e.g. an example from the puzzle
+    Embedded(&'static str),
+}
+
+impl Source {
+    pub fn read(&self) -> Result<String, String> {
+        match self {
+            Source::File(path) => fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e)),
+            Source::Stdin => {
+                let mut data = String::new();
+                io::stdin()
+                    .read_to_string(&mut data)
+                    .map_err(|e| format!("stdin: {}", e))?;
+                Ok(data)
+            }
+            Source::Embedded(s) => Ok(s.to_string()),
+        }
+    }
+}
+
+// for errors, e.g. day22.txt: line 2, column 1: ...
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "{}", path),
+            Source::Stdin => write!(f, "stdin"),
+            Source::Embedded(_) => write!(f, "embedded input"),
+        }
+    }
+}
+
+// What was wrong with the input and where, lines and columns counting
+// from 1
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    // the offending text
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {} {:?}",
+            self.line, self.column, self.message, self.text
+        )
+    }
+}
+
+// A piece of the input and where it starts
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Span<'a> {
+    pub line: usize,
+    pub column: usize,
+    pub text: &'a str,
+}
+
+impl<'a> Span<'a> {
+    pub fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            text: self.text.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn parse<T: FromStr>(&self) -> Result<T, ParseError> {
+        self.text
+            .parse()
+            .map_err(|_| self.error(&format!("expected {}", std::any::type_name::<T>())))
+    }
+
+    // the part from byte offset i
+    fn from(&self, i: usize) -> Self {
+        Self {
+            line: self.line,
+            column: self.column + self.text[..i].chars().count(),
+            text: &self.text[i..],
+        }
+    }
+
+    fn to(&self, i: usize) -> Self {
+        Self {
+            text: &self.text[..i],
+            ..*self
+        }
+    }
+
+    pub fn trim(&self) -> Self {
+        let start = self.text.len() - self.text.trim_start().len();
+        let s = self.from(start);
+        s.to(s.text.trim_end().len())
+    }
+
+    pub fn split(&self, sep: &str) -> Vec<Self> {
+        let mut at = 0;
+        self.text
+            .split(sep)
+            .map(|piece| {
+                let s = self.from(at).to(piece.len());
+                at += piece.len() + sep.len();
+                s
+            })
+            .collect()
+    }
+
+    pub fn strip_prefix(&self, prefix: &str) -> Option<Self> {
+        if self.text.starts_with(prefix) {
+            Some(self.from(prefix.len()))
+        } else {
+            None
+        }
+    }
+
+    pub fn strip_suffix(&self, suffix: &str) -> Option<Self> {
+        self.text
+            .strip_suffix(suffix)
+            .map(|rest| self.to(rest.len()))
+    }
+
+    // the first char and the rest, e.g. "R75" to "R" and "75"
+    pub fn split_first(&self) -> Option<(Self, Self)> {
+        let c = self.text.chars().next()?;
+        Some((self.to(c.len_utf8()), self.from(c.len_utf8())))
+    }
+
+    // one span per char
+    pub fn cells(&self) -> Vec<Self> {
+        self.text
+            .char_indices()
+            .map(|(i, c)| self.from(i).to(c.len_utf8()))
+            .collect()
+    }
+}
+
+// the lines that aren't blank
+pub fn lines(s: &str) -> impl Iterator<Item = Span<'_>> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, text)| Span {
+            line: i + 1,
+            column: 1,
+            text,
+        })
+}
+
+// the empty span just after the last line that isn't blank, for errors
+// about something missing from the input
+pub fn end(s: &str) -> Span<'_> {
+    match lines(s).last() {
+        Some(l) => l.from(l.text.len()),
+        None => Span {
+            line: 1,
+            column: 1,
+            text: "",
+        },
+    }
+}
+
+// separated values, e.g. an Intcode program
+pub fn list<T: FromStr>(s: &str, sep: &str) -> Result<Vec<T>, ParseError> {
+    let mut v = Vec::new();
+    for l in lines(s) {
+        for f in l.trim().split(sep) {
+            v.push(f.trim().parse()?);
+        }
+    }
+    Ok(v)
+}
+
+// a grid of cells, one line a row, with cell mapping each char
+pub fn grid<T, F>(s: &str, cell: F) -> Result<Vec<Vec<T>>, ParseError>
+where
+    F: Fn(char) -> Option<T>,
+{
+    lines(s)
+        .map(|l| {
+            l.trim()
+                .cells()
+                .iter()
+                .map(|c| {
+                    c.text
+                        .chars()
+                        .next()
+                        .and_then(&cell)
+                        .ok_or_else(|| c.error("unexpected"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn digits(s: &str) -> Result<Vec<Vec<u32>>, ParseError> {
+    grid(s, |c| c.to_digit(10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let l = lines("\n  R75, U8 \n").next().unwrap();
+        assert_eq!(2, l.line);
+        let fields: Vec<(usize, &str)> = l
+            .split(",")
+            .iter()
+            .map(|f| f.trim())
+            .map(|f| (f.column, f.text))
+            .collect();
+        assert_eq!(vec![(3, "R75"), (8, "U8")], fields);
+
+        let (dir, n) = l.trim().split_first().unwrap();
+        assert_eq!(("R", 4), (dir.text, n.column));
+        assert_eq!(
+            "line 2, column 4: expected i32 \"75, U8\"",
+            n.parse::<i32>().unwrap_err().to_string()
+        );
+        let x = l.trim().strip_prefix("R7").unwrap();
+        assert_eq!((5, "5, U8"), (x.column, x.text));
+        assert_eq!("R75, U", l.trim().strip_suffix("8").unwrap().text);
+
+        let e = end("R75\nU8\n\n").error("expected more");
+        assert_eq!("line 2, column 3: expected more \"\"", e.to_string());
+        assert_eq!((1, 1), (end("").line, end("").column));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(Ok(vec![1, -2, 3]), list::<i64>("1,-2, 3\n", ","));
+        let e = list::<i64>("1,2\n\n4,x5,6", ",").unwrap_err();
+        assert_eq!((3, 3, "x5"), (e.line, e.column, e.text.as_str()));
+        // a trailing separator is an empty value
+        assert_eq!(5, list::<i64>("1,2,", ",").unwrap_err().column);
+    }
+
+    #[test]
+    fn test_grid() {
+        let g = grid(".#\n#.\n", |c| match c {
+            '.' => Some(false),
+            '#' => Some(true),
+            _ => None,
+        });
+        assert_eq!(Ok(vec![vec![false, true], vec![true, false]]), g);
+        assert_eq!(
+            "line 1, column 3: unexpected \"x\"",
+            digits("12x4").unwrap_err().to_string()
+        );
+        assert_eq!(
+            Ok("embedded".to_string()),
+            Source::Embedded("embedded").read()
+        );
+    }
+}
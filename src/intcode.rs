@@ -1,4 +1,9 @@
+use crate::input;
+use crate::input::ParseError;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::TryInto;
 use std::fmt;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -19,55 +24,228 @@ enum Op {
     LessThan(ParamMode, ParamMode, ParamMode),
     Equals(ParamMode, ParamMode, ParamMode),
     AdjustRelativeBase(ParamMode),
+    Extension(i64, Vec<(Param, ParamMode)>),
+}
+
+// how an extension opcode uses each of its parameters
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Param {
+    Read,
+    Write,
+}
+
+// the parameters of a standard opcode, None for anything else
+pub fn params(code: i64) -> Option<&'static [Param]> {
+    use Param::{Read, Write};
+    match code {
+        1 | 2 | 7 | 8 => Some(&[Read, Read, Write]),
+        3 => Some(&[Write]),
+        4 | 9 => Some(&[Read]),
+        5 | 6 => Some(&[Read, Read]),
+        99 => Some(&[]),
+        _ => None,
+    }
+}
+
+// Extra opcodes on top of the standard set, and hooks for memory mapped
+// devices. Parameter modes are decoded the same way as for the standard
+// opcodes, so extension parameters can be position, immediate or relative.
+pub trait Extension {
+    // the parameters for an opcode, or None if it isn't handled
+    fn params(&self, code: i64) -> Option<Vec<Param>>;
+    // execute an opcode given the values of its Read parameters in order,
+    // returning the values for its Write parameters in order
+    fn exec(&mut self, code: i64, args: &[i64]) -> Vec<i64>;
+    // memory mapped reads and writes. Returning None or false leaves the
+    // access to normal memory. Parameters themselves are always read from
+    // normal memory.
+    fn read(&mut self, _addr: i64) -> Option<i64> {
+        None
+    }
+    fn write(&mut self, _addr: i64, _value: i64) -> bool {
+        false
+    }
+    // called before each instruction is executed
+    fn step(&mut self, _code: &Intcode) {}
+}
+
+// the standard opcodes only
+pub struct NoExtension;
+
+impl Extension for NoExtension {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+}
+
+// Holds back each write so the machine can tell whether it changed memory.
+#[derive(Default)]
+struct Deferred {
+    writes: Vec<(i64, i64)>,
+}
+
+impl Extension for Deferred {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+
+    fn write(&mut self, addr: i64, value: i64) -> bool {
+        self.writes.push((addr, value));
+        true
+    }
+}
+
+// Dynamic taint tracking. Each input is labelled with its index, and the
+// labels follow values through Add, Mul, LessThan, Equals and memory.
+// Only data flow is tracked: a value chosen by a jump on an input is not
+// tainted by it, nor is an address computed from one.
+#[derive(Debug, Default, Clone)]
+pub struct Taint {
+    memory: HashMap<i64, BTreeSet<usize>>,
+    // the instruction being executed and the labels it has read
+    code: i64,
+    reads: BTreeSet<usize>,
+    inputs: usize,
+    // for each output so far, the inputs it depends on
+    pub outputs: Vec<BTreeSet<usize>>,
+}
+
+impl Taint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Extension for Taint {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+
+    fn read(&mut self, addr: i64) -> Option<i64> {
+        if let Some(labels) = self.memory.get(&addr) {
+            self.reads.extend(labels);
+        }
+        if self.code == 4 {
+            self.outputs.push(std::mem::take(&mut self.reads));
+        }
+        None
+    }
+
+    fn write(&mut self, addr: i64, _value: i64) -> bool {
+        let labels = match self.code {
+            3 => {
+                self.inputs += 1;
+                [self.inputs - 1].iter().copied().collect()
+            }
+            _ => std::mem::take(&mut self.reads),
+        };
+        if labels.is_empty() {
+            self.memory.remove(&addr);
+        } else {
+            self.memory.insert(addr, labels);
+        }
+        false
+    }
+
+    fn step(&mut self, code: &Intcode) {
+        self.code = code.peek(code.pc()) % 100;
+        self.reads.clear();
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Error {
+    InvalidCode(i64),
+    InvalidMode(i64),
+    ExtensionWrites {
+        code: i64,
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidCode(c) => write!(f, "invalid code {}", c),
+            Error::InvalidMode(m) => write!(f, "invalid mode {}", m),
+            Error::ExtensionWrites {
+                code,
+                expected,
+                got,
+            } => write!(
+                f,
+                "code {} returned {} values for {} write params",
+                code, got, expected
+            ),
+        }
+    }
 }
 
 impl Op {
-    fn new(i: i64) -> Self {
+    fn new<E: Extension + ?Sized>(i: i64, ext: &E) -> Result<Self, Error> {
         let mode = i / 100;
         let code = i - mode * 100;
-        match code {
+        let op = match code {
             99 => Op::Halt,
             1 => {
-                let m = Self::modes(mode, 3);
+                let m = Self::modes(mode, 3)?;
                 Op::Add(m[0], m[1], m[2])
             }
             2 => {
-                let m = Self::modes(mode, 3);
+                let m = Self::modes(mode, 3)?;
                 Op::Mul(m[0], m[1], m[2])
             }
             3 => {
-                let m = Self::modes(mode, 1);
+                let m = Self::modes(mode, 1)?;
                 Op::Input(m[0])
             }
             4 => {
-                let m = Self::modes(mode, 1);
+                let m = Self::modes(mode, 1)?;
                 Op::Output(m[0])
             }
             5 => {
-                let m = Self::modes(mode, 2);
+                let m = Self::modes(mode, 2)?;
                 Op::JumpTrue(m[0], m[1])
             }
             6 => {
-                let m = Self::modes(mode, 2);
+                let m = Self::modes(mode, 2)?;
                 Op::JumpFalse(m[0], m[1])
             }
             7 => {
-                let m = Self::modes(mode, 3);
+                let m = Self::modes(mode, 3)?;
                 Op::LessThan(m[0], m[1], m[2])
             }
             8 => {
-                let m = Self::modes(mode, 3);
+                let m = Self::modes(mode, 3)?;
                 Op::Equals(m[0], m[1], m[2])
             }
             9 => {
-                let m = Self::modes(mode, 1);
+                let m = Self::modes(mode, 1)?;
                 Op::AdjustRelativeBase(m[0])
             }
-            c => panic!("invalid code {}", c),
-        }
+            _ => {
+                let params = ext.params(code).ok_or(Error::InvalidCode(code))?;
+                let m = Self::modes(mode, params.len())?;
+                Op::Extension(code, params.into_iter().zip(m).collect())
+            }
+        };
+        Ok(op)
     }
 
-    fn modes(m: i64, count: usize) -> Vec<ParamMode> {
+    fn modes(m: i64, count: usize) -> Result<Vec<ParamMode>, Error> {
         let mut v: Vec<ParamMode> = Vec::new();
         let mut m = m;
         for _ in 0..count {
@@ -76,22 +254,138 @@ impl Op {
                 0 => ParamMode::Position,
                 1 => ParamMode::Immediate,
                 2 => ParamMode::Relative,
-                c => panic!("invalid mode {}", c),
+                c => return Err(Error::InvalidMode(c)),
             };
             v.push(pm);
             m = rem;
         }
 
-        v
+        Ok(v)
     }
 }
 
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ExitMode {
     Halt,
     Output(i64),
+    // the machine wants input but none is available. The input
+    // instruction has not been executed, so running again retries it.
+    AwaitingInput,
+}
+
+// how long run_until_idle lets a polling machine run while its memory
+// keeps changing
+pub const SPIN_STEPS: usize = 100_000;
+
+// how run_until_idle stopped
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Status {
+    Halt,
+    Output(i64),
+    // read the sentinel the limit number of times in a row without an
+    // output or a change to memory in between
+    Idle,
 }
 
-#[derive(Debug, Clone)]
+// the result of running for a batch of outputs
+#[derive(PartialEq, Debug, Clone)]
+pub enum Batch {
+    Outputs(Vec<i64>),
+    Halt,
+    AwaitingInput,
+}
+
+// the machine stopped partway through a batch
+#[derive(PartialEq, Debug, Clone)]
+pub struct Truncated {
+    pub outputs: Vec<i64>,
+    pub expected: usize,
+    pub exit: ExitMode,
+}
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stop = match self.exit {
+            ExitMode::Halt => "halted",
+            _ => "waiting for input",
+        };
+        write!(
+            f,
+            "{} after {} of {} outputs",
+            stop,
+            self.outputs.len(),
+            self.expected
+        )
+    }
+}
+
+// Iterates over the outputs of a machine N at a time, see records.
+pub struct Records<'a, const N: usize> {
+    code: &'a mut Intcode,
+    input_fn: Box<dyn FnMut() -> Option<i64> + 'a>,
+    exit: Option<ExitMode>,
+}
+
+impl<const N: usize> Records<'_, N> {
+    // why the machine stopped, once the iterator is done
+    pub fn exit(&self) -> Option<ExitMode> {
+        self.exit
+    }
+}
+
+impl<const N: usize> Iterator for Records<'_, N> {
+    type Item = Result<[i64; N], Truncated>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exit.is_some() {
+            return None;
+        }
+        match self.code.run_n_outputs(N, &mut self.input_fn) {
+            Ok(Batch::Outputs(v)) => Some(Ok(v.try_into().expect("a full batch"))),
+            Ok(Batch::Halt) => {
+                self.exit = Some(ExitMode::Halt);
+                None
+            }
+            Ok(Batch::AwaitingInput) => {
+                self.exit = Some(ExitMode::AwaitingInput);
+                None
+            }
+            Err(t) => {
+                self.exit = Some(t.exit);
+                Some(Err(t))
+            }
+        }
+    }
+}
+
+// collect n outputs from run, see run_n_outputs
+fn batch<F>(n: usize, mut run: F) -> Result<Batch, Truncated>
+where
+    F: FnMut() -> ExitMode,
+{
+    let mut outputs = Vec::with_capacity(n);
+    while outputs.len() < n {
+        match run() {
+            ExitMode::Output(x) => outputs.push(x),
+            exit if outputs.is_empty() => {
+                return Ok(match exit {
+                    ExitMode::Halt => Batch::Halt,
+                    _ => Batch::AwaitingInput,
+                })
+            }
+            exit => {
+                return Err(Truncated {
+                    outputs,
+                    expected: n,
+                    exit,
+                })
+            }
+        }
+    }
+    Ok(Batch::Outputs(outputs))
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct Intcode {
     v: Vec<i64>,
     i: i64,
@@ -99,6 +393,7 @@ pub struct Intcode {
     memory: HashMap<i64, i64>,
 }
 
+// the program only, see dump for the full state
 impl fmt::Display for Intcode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s: Vec<String> = self.v.iter().map(|x| x.to_string()).collect();
@@ -106,21 +401,197 @@ impl fmt::Display for Intcode {
     }
 }
 
+// write cells from addr on, 16 runs to a line, each line starting with the
+// address of its first cell and runs of 3 or more as value*count
+fn dump_cells(s: &mut String, mut addr: i64, values: &[i64]) {
+    let mut runs: Vec<(i64, usize)> = Vec::new();
+    for &x in values {
+        match runs.last_mut() {
+            Some((y, n)) if *y == x => *n += 1,
+            _ => runs.push((x, 1)),
+        }
+    }
+    for line in runs.chunks(16) {
+        let cells: Vec<String> = line
+            .iter()
+            .map(|&(x, n)| {
+                if n > 2 {
+                    format!("{}*{}", x, n)
+                } else {
+                    vec![x.to_string(); n].join(",")
+                }
+            })
+            .collect();
+        s.push_str(&format!("{}: {}\n", addr, cells.join(",")));
+        // only wraps past the last chunk, when it ends at i64::MAX
+        addr = addr.wrapping_add(line.iter().map(|r| r.1 as i64).sum::<i64>());
+    }
+}
+
+// the most cells a dump may declare, so a bad one can't run out of memory
+const MAX_DUMP_CELLS: usize = 1 << 24;
+
+// the cells of a line, no more than limit of them
+fn load_cells(s: &str, limit: usize) -> Result<Vec<i64>, String> {
+    let mut values = Vec::new();
+    for cell in s.split(',') {
+        let cell = cell.trim();
+        let bad = || format!("bad cell {:?}", cell);
+        let (x, n) = match cell.split_once('*') {
+            Some((x, n)) => (x, n.parse().map_err(|_| bad())?),
+            None => (cell, 1),
+        };
+        let x: i64 = x.parse().map_err(|_| bad())?;
+        if n > limit - values.len() {
+            return Err("more cells than declared".to_string());
+        }
+        values.extend(std::iter::repeat(x).take(n));
+    }
+    Ok(values)
+}
+
 impl Intcode {
     pub fn new(s: &str) -> Self {
+        Self::parse(s).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // a program of comma separated values
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        Ok(Self::from_vec(input::list(s, ",")?))
+    }
+
+    pub fn from_vec(v: Vec<i64>) -> Self {
         Intcode {
-            v: Self::string_to_vec(s),
+            v,
             i: 0,
             relative_base: 0,
             memory: HashMap::new(),
         }
     }
 
-    fn string_to_vec(s: &str) -> Vec<i64> {
-        s.trim()
-            .split(',')
-            .map(|x| x.parse().expect("failed to parse to int"))
-            .collect()
+    // The full state of the machine: the registers, the program region and
+    // the cells written past it, in address ranges.
+    //
+    //   pc 4
+    //   rb 0
+    //   program 5
+    //   0: 1101,2,3,1000,99
+    //   memory 1
+    //   1000: 5
+    pub fn dump(&self) -> String {
+        let mut s = format!(
+            "pc {}\nrb {}\nprogram {}\n",
+            self.i,
+            self.relative_base,
+            self.v.len()
+        );
+        dump_cells(&mut s, 0, &self.v);
+        let mut cells: Vec<(i64, i64)> = self.memory.iter().map(|(&a, &x)| (a, x)).collect();
+        cells.sort();
+        s.push_str(&format!("memory {}\n", cells.len()));
+        let mut start = 0;
+        for i in 1..=cells.len() {
+            if i == cells.len() || cells[i].0 != cells[i - 1].0 + 1 {
+                let values: Vec<i64> = cells[start..i].iter().map(|c| c.1).collect();
+                dump_cells(&mut s, cells[start].0, &values);
+                start = i;
+            }
+        }
+        s
+    }
+
+    // read back a dump
+    pub fn load_dump(s: &str) -> Result<Self, String> {
+        let mut code = Self::from_vec(Vec::new());
+        let mut program = None;
+        let mut memory = None;
+        // where the next memory range may start
+        let mut next = 0;
+        for (n, line) in s.lines().enumerate() {
+            let err = |e: &str| format!("line {}: {}", n + 1, e);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((addr, cells)) = line.split_once(':') {
+                let addr: i64 = addr.parse().map_err(|_| err("bad address"))?;
+                let len = program.ok_or_else(|| err("cells before program"))?;
+                let limit = match memory {
+                    None => len - code.v.len(),
+                    Some(m) => m - code.memory.len(),
+                };
+                let values = load_cells(cells, limit).map_err(|e| err(&e))?;
+                if memory.is_none() {
+                    if addr != code.v.len() as i64 {
+                        return Err(err("program cells out of order"));
+                    }
+                    code.v.extend(values);
+                } else {
+                    if addr < next {
+                        return Err(err("memory cells out of order"));
+                    }
+                    // a cell at i64::MAX is fine, but nothing can follow it
+                    let last = addr
+                        .checked_add(values.len() as i64 - 1)
+                        .ok_or_else(|| err("memory cells past the last address"))?;
+                    for (k, &x) in values.iter().enumerate() {
+                        code.memory.insert(addr + k as i64, x);
+                    }
+                    next = last.saturating_add(1);
+                }
+                continue;
+            }
+            let (key, value) = line.split_once(' ').ok_or_else(|| err("bad line"))?;
+            let value: i64 = value.trim().parse().map_err(|_| err("bad value"))?;
+            let count = || {
+                if (0..=MAX_DUMP_CELLS as i64).contains(&value) {
+                    Ok(value as usize)
+                } else {
+                    Err(err("bad count"))
+                }
+            };
+            match key {
+                "pc" if value < 0 => return Err(err("pc outside memory")),
+                "pc" => code.i = value,
+                "rb" => code.relative_base = value,
+                "program" => program = Some(count()?),
+                "memory" => {
+                    if program != Some(code.v.len()) {
+                        return Err(err("program shorter than declared"));
+                    }
+                    next = code.v.len() as i64;
+                    memory = Some(count()?);
+                }
+                _ => return Err(err(&format!("unknown register {:?}", key))),
+            }
+        }
+        match memory {
+            None => Err("missing memory".to_string()),
+            Some(m) if m != code.memory.len() => Err(format!(
+                "expected {} memory cells, found {}",
+                m,
+                code.memory.len()
+            )),
+            _ => Ok(code),
+        }
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.i
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    // the length of the loaded program, memory past it starts as 0
+    pub fn program_len(&self) -> usize {
+        self.v.len()
+    }
+
+    // read memory without going through any extension
+    pub fn peek(&self, i: i64) -> i64 {
+        self.get_mem(i)
     }
 
     fn get_mem(&self, i: i64) -> i64 {
@@ -142,8 +613,9 @@ impl Intcode {
         }
     }
 
-    fn get(&self, i: i64, m: ParamMode) -> i64 {
-        self.get_mem(self.index(i, m))
+    fn get<E: Extension + ?Sized>(&self, ext: &mut E, i: i64, m: ParamMode) -> i64 {
+        let at = self.index(i, m);
+        ext.read(at).unwrap_or_else(|| self.get_mem(at))
     }
 
     fn set_mem(&mut self, i: i64, value: i64) {
@@ -159,74 +631,493 @@ impl Intcode {
         self.set_mem(self.index(i, ParamMode::Immediate), value);
     }
 
-    fn set(&mut self, i: i64, m: ParamMode, value: i64) {
-        self.set_mem(self.index(i, m), value);
+    fn set<E: Extension + ?Sized>(&mut self, ext: &mut E, i: i64, m: ParamMode, value: i64) {
+        let at = self.index(i, m);
+        if !ext.write(at, value) {
+            self.set_mem(at, value);
+        }
     }
 
     pub fn run<F>(&mut self, mut input_fn: F) -> ExitMode
     where
         F: FnMut() -> i64,
     {
+        self.run_until_input(|| Some(input_fn()))
+    }
+
+    // Run until n outputs, as run_until_input. Stopping between batches is
+    // fine, stopping partway through one is an error.
+    pub fn run_n_outputs<F>(&mut self, n: usize, mut input_fn: F) -> Result<Batch, Truncated>
+    where
+        F: FnMut() -> Option<i64>,
+    {
+        batch(n, || self.run_until_input(&mut input_fn))
+    }
+
+    // Run until n outputs, as run_until_idle. Going idle is reported as
+    // waiting for input, between batches or partway through one.
+    pub fn run_n_until_idle(
+        &mut self,
+        n: usize,
+        input: &mut VecDeque<i64>,
+        sentinel: i64,
+        limit: usize,
+    ) -> Result<Batch, Truncated> {
+        batch(n, || match self.run_until_idle(input, sentinel, limit) {
+            Status::Halt => ExitMode::Halt,
+            Status::Output(x) => ExitMode::Output(x),
+            Status::Idle => ExitMode::AwaitingInput,
+        })
+    }
+
+    // the outputs N at a time until the machine halts or waits for input,
+    // e.g. records::<3>(|| None) for (x, y, tile) triples
+    pub fn records<'a, const N: usize>(
+        &'a mut self,
+        input_fn: impl FnMut() -> Option<i64> + 'a,
+    ) -> Records<'a, N> {
+        Records {
+            code: self,
+            input_fn: Box::new(input_fn),
+            exit: None,
+        }
+    }
+
+    // run, taking input from the front of the queue and returning
+    // AwaitingInput when the queue is empty
+    pub fn run_queue(&mut self, input: &mut VecDeque<i64>) -> ExitMode {
+        self.run_until_input(|| input.pop_front())
+    }
+
+    // As run_queue, but reading sentinel when the queue is empty instead of
+    // waiting. A machine polling for input like this spins, so it is taken
+    // to be idle once it has read the sentinel limit times in a row with no
+    // output, other input or change to memory in between. One that keeps
+    // changing memory, e.g. counting its polls, is idle once it has run
+    // SPIN_STEPS instructions since it first read the sentinel without an
+    // output or other input.
+    pub fn run_until_idle(
+        &mut self,
+        input: &mut VecDeque<i64>,
+        sentinel: i64,
+        limit: usize,
+    ) -> Status {
+        let mut deferred = Deferred::default();
+        let mut polls = 0;
+        let mut spinning = None;
         loop {
-            let op = Op::new(self.v[self.i as usize]);
-            match op {
-                Op::Halt => return ExitMode::Halt,
-                Op::Add(am, bm, rm) => {
-                    let a = self.get(self.i + 1, am);
-                    let b = self.get(self.i + 2, bm);
-                    self.set(self.i + 3, rm, a + b);
-                    self.i += 4;
+            let mut read = None;
+            let exit = self.step_ext(&mut deferred, &mut || {
+                read = Some(input.pop_front());
+                Some(read.unwrap().unwrap_or(sentinel))
+            });
+            let exit = match exit {
+                Ok(exit) => exit,
+                Err(e) => panic!("{}", e),
+            };
+            for (at, value) in deferred.writes.drain(..) {
+                if self.get_mem(at) != value {
+                    self.set_mem(at, value);
+                    polls = 0;
                 }
-                Op::Mul(am, bm, rm) => {
-                    let a = self.get(self.i + 1, am);
-                    let b = self.get(self.i + 2, bm);
-                    self.set(self.i + 3, rm, a * b);
-                    self.i += 4;
+            }
+            match read {
+                Some(None) => {
+                    polls += 1;
+                    spinning = spinning.or(Some(0));
                 }
-                Op::Input(im) => {
-                    let input = input_fn();
-                    self.set(self.i + 1, im, input);
-                    self.i += 2;
+                Some(Some(_)) => {
+                    polls = 0;
+                    spinning = None;
                 }
-                Op::Output(om) => {
-                    // println!("output: {}", self.get(i + 1, om));
-                    let output = self.get(self.i + 1, om);
-                    self.i += 2;
-                    return ExitMode::Output(output);
+                None => (),
+            }
+            match exit {
+                Some(ExitMode::Halt) => return Status::Halt,
+                Some(ExitMode::Output(x)) => return Status::Output(x),
+                _ => (),
+            }
+            if let Some(steps) = &mut spinning {
+                *steps += 1;
+            }
+            if polls >= limit || spinning >= Some(SPIN_STEPS) {
+                return Status::Idle;
+            }
+        }
+    }
+
+    // as run_queue, tracking which inputs each value depends on
+    pub fn run_taint(&mut self, taint: &mut Taint, input: &mut VecDeque<i64>) -> ExitMode {
+        match self.run_ext(taint, || input.pop_front()) {
+            Ok(exit) => exit,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // run until halt or output, or until input_fn has no input to give
+    pub fn run_until_input<F>(&mut self, input_fn: F) -> ExitMode
+    where
+        F: FnMut() -> Option<i64>,
+    {
+        match self.run_ext(&mut NoExtension, input_fn) {
+            Ok(exit) => exit,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // as run_until_input, with extra opcodes handled by ext
+    pub fn run_ext<E, F>(&mut self, ext: &mut E, mut input_fn: F) -> Result<ExitMode, Error>
+    where
+        E: Extension + ?Sized,
+        F: FnMut() -> Option<i64>,
+    {
+        loop {
+            if let Some(exit) = self.step_ext(ext, &mut input_fn)? {
+                return Ok(exit);
+            }
+        }
+    }
+
+    // Execute a single instruction. Returns the exit if it halted, output
+    // or is waiting for input, None otherwise. A halt is not stepped past.
+    pub fn step_ext<E, F>(
+        &mut self,
+        ext: &mut E,
+        input_fn: &mut F,
+    ) -> Result<Option<ExitMode>, Error>
+    where
+        E: Extension + ?Sized,
+        F: FnMut() -> Option<i64>,
+    {
+        let op = Op::new(self.v[self.i as usize], ext)?;
+        // an input with nothing to read doesn't count as a step, it
+        // will be retried on the next run
+        let mut input = None;
+        if let Op::Input(_) = op {
+            input = input_fn();
+            if input.is_none() {
+                return Ok(Some(ExitMode::AwaitingInput));
+            }
+        }
+        ext.step(self);
+        match op {
+            Op::Halt => return Ok(Some(ExitMode::Halt)),
+            Op::Add(am, bm, rm) => {
+                let a = self.get(ext, self.i + 1, am);
+                let b = self.get(ext, self.i + 2, bm);
+                self.set(ext, self.i + 3, rm, a + b);
+                self.i += 4;
+            }
+            Op::Mul(am, bm, rm) => {
+                let a = self.get(ext, self.i + 1, am);
+                let b = self.get(ext, self.i + 2, bm);
+                self.set(ext, self.i + 3, rm, a * b);
+                self.i += 4;
+            }
+            Op::Input(im) => {
+                let input = input.expect("input checked above");
+                self.set(ext, self.i + 1, im, input);
+                self.i += 2;
+            }
+            Op::Output(om) => {
+                // println!("output: {}", self.get(i + 1, om));
+                let output = self.get(ext, self.i + 1, om);
+                self.i += 2;
+                return Ok(Some(ExitMode::Output(output)));
+            }
+            Op::JumpTrue(m1, m2) => match self.get(ext, self.i + 1, m1) {
+                0 => self.i += 3,
+                _ => self.i = self.get(ext, self.i + 2, m2),
+            },
+            Op::JumpFalse(m1, m2) => match self.get(ext, self.i + 1, m1) {
+                0 => self.i = self.get(ext, self.i + 2, m2),
+                _ => self.i += 3,
+            },
+            Op::LessThan(m1, m2, m3) => {
+                let val = if self.get(ext, self.i + 1, m1) < self.get(ext, self.i + 2, m2) {
+                    1
+                } else {
+                    0
+                };
+                self.set(ext, self.i + 3, m3, val);
+                self.i += 4;
+            }
+            Op::Equals(m1, m2, m3) => {
+                let val = if self.get(ext, self.i + 1, m1) == self.get(ext, self.i + 2, m2) {
+                    1
+                } else {
+                    0
+                };
+                self.set(ext, self.i + 3, m3, val);
+                self.i += 4;
+            }
+            Op::AdjustRelativeBase(m1) => {
+                let val = self.get(ext, self.i + 1, m1);
+                self.relative_base += val;
+                self.i += 2;
+            }
+            Op::Extension(code, params) => {
+                let mut args = Vec::new();
+                let mut writes = Vec::new();
+                for (n, (p, m)) in params.iter().enumerate() {
+                    let at = self.i + 1 + n as i64;
+                    match p {
+                        Param::Read => args.push(self.get(ext, at, *m)),
+                        Param::Write => writes.push((at, *m)),
+                    }
+                }
+                let values = ext.exec(code, &args);
+                if values.len() != writes.len() {
+                    return Err(Error::ExtensionWrites {
+                        code,
+                        expected: writes.len(),
+                        got: values.len(),
+                    });
                 }
-                Op::JumpTrue(m1, m2) => match self.get(self.i + 1, m1) {
-                    0 => self.i += 3,
-                    _ => self.i = self.get(self.i + 2, m2),
-                },
-                Op::JumpFalse(m1, m2) => match self.get(self.i + 1, m1) {
-                    0 => self.i = self.get(self.i + 2, m2),
-                    _ => self.i += 3,
-                },
-                Op::LessThan(m1, m2, m3) => {
-                    let val = if self.get(self.i + 1, m1) < self.get(self.i + 2, m2) {
-                        1
-                    } else {
-                        0
-                    };
-                    self.set(self.i + 3, m3, val);
-                    self.i += 4;
+                for ((at, m), value) in writes.into_iter().zip(values) {
+                    self.set(ext, at, m, value);
                 }
-                Op::Equals(m1, m2, m3) => {
-                    let val = if self.get(self.i + 1, m1) == self.get(self.i + 2, m2) {
-                        1
-                    } else {
-                        0
-                    };
-                    self.set(self.i + 3, m3, val);
-                    self.i += 4;
+                self.i += 1 + params.len() as i64;
+            }
+        };
+        // println!("-- {} => {} --\n{}", ori, i, self.to_string());
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10: print a value, 11: write a "random" number, 12: a^b
+    #[derive(Default)]
+    struct Host {
+        printed: Vec<i64>,
+        seed: i64,
+    }
+
+    impl Extension for Host {
+        fn params(&self, code: i64) -> Option<Vec<Param>> {
+            match code {
+                10 => Some(vec![Param::Read]),
+                11 => Some(vec![Param::Write]),
+                12 => Some(vec![Param::Read, Param::Read, Param::Write]),
+                _ => None,
+            }
+        }
+
+        fn exec(&mut self, code: i64, args: &[i64]) -> Vec<i64> {
+            match code {
+                10 => {
+                    self.printed.push(args[0]);
+                    vec![]
                 }
-                Op::AdjustRelativeBase(m1) => {
-                    let val = self.get(self.i + 1, m1);
-                    self.relative_base += val;
-                    self.i += 2;
+                11 => {
+                    self.seed = (self.seed * 31 + 7) % 101;
+                    vec![self.seed]
                 }
-            };
-            // println!("-- {} => {} --\n{}", ori, i, self.to_string());
+                12 => vec![args[0].pow(args[1] as u32)],
+                c => panic!("invalid code {}", c),
+            }
+        }
+    }
+
+    #[test]
+    fn test_extension() {
+        // print 5, rand into [20], 2^[20] into [21] (relative), output [21]
+        let mut code = Intcode::new("110,5,11,20,109,1,22112,2,19,20,4,21,99");
+        let mut host = Host::default();
+        let exit = code.run_ext(&mut host, || None);
+        assert_eq!(Ok(ExitMode::Output(1 << 7)), exit);
+        assert_eq!(vec![5], host.printed);
+        assert_eq!(Ok(ExitMode::Halt), code.run_ext(&mut host, || None));
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        let mut code = Intcode::new("1,0,0,0,42,99");
+        assert_eq!(
+            Err(Error::InvalidCode(42)),
+            code.run_ext(&mut Host::default(), || None)
+        );
+        let mut code = Intcode::new("1,0,0,0,42,99");
+        assert_eq!(
+            Err(Error::InvalidCode(42)),
+            code.run_ext(&mut NoExtension, || None)
+        );
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        let mut code = Intcode::new("310,0,99");
+        assert_eq!(
+            Err(Error::InvalidMode(3)),
+            code.run_ext(&mut Host::default(), || None)
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        // write 0 to 1000, 1001 and 1002 and 7 to 2000, set the relative
+        // base, then wait for input
+        let mut code =
+            Intcode::new("1101,0,0,1000,1101,0,0,1001,1101,0,0,1002,1101,3,4,2000,109,7,3,0,99");
+        let mut input = VecDeque::new();
+        code.run_queue(&mut input);
+        let dump = code.dump();
+        let expected = "pc 18
+rb 7
+program 21
+0: 1101,0,0,1000,1101,0,0,1001,1101,0,0,1002,1101,3,4,2000,109,7,3
+19: 0,99
+memory 4
+1000: 0*3
+2000: 7
+";
+        assert_eq!(expected, dump);
+        assert_eq!(Ok(code.clone()), Intcode::load_dump(&dump));
+
+        assert_eq!(
+            Err("line 4: bad cell \"x\"".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram 2\n0: 1,x\nmemory 0\n")
+        );
+        assert_eq!(
+            Err("line 4: program shorter than declared".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram 3\nmemory 0\n")
+        );
+        assert_eq!(
+            Err("expected 2 memory cells, found 1".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram 0\nmemory 2\n9: 5\n")
+        );
+        // runs can't expand past the declared cells
+        assert_eq!(
+            Err("line 5: more cells than declared".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram 0\nmemory 2\n9: 5*99999999999999\n")
+        );
+        assert_eq!(
+            Err("line 4: more cells than declared".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram 2\n0: 1,2,3\nmemory 0\n")
+        );
+        assert_eq!(
+            Err("line 3: bad count".to_string()),
+            Intcode::load_dump("pc 0\nrb 0\nprogram -1\n")
+        );
+        assert_eq!(
+            Err("line 1: pc outside memory".to_string()),
+            Intcode::load_dump("pc -1\nrb 0\nprogram 0\nmemory 0\n")
+        );
+        assert_eq!(
+            Err("line 5: memory cells past the last address".to_string()),
+            Intcode::load_dump(&format!(
+                "pc 0\nrb 0\nprogram 0\nmemory 2\n{}: 1,2\n",
+                i64::MAX
+            ))
+        );
+        let mut code = Intcode::from_vec(vec![]);
+        code.set_imm(i64::MAX, 1);
+        assert_eq!(Ok(code.clone()), Intcode::load_dump(&code.dump()));
+    }
+
+    #[test]
+    fn test_batches() {
+        // output the input doubled, then 1, 2, 3, 4 and 5
+        let src = "3,100,102,2,100,100,4,100,104,1,104,2,104,3,104,4,104,5,99";
+        let mut code = Intcode::new(src);
+        let mut input = VecDeque::new();
+        assert_eq!(
+            Ok(Batch::AwaitingInput),
+            code.run_n_outputs(2, || input.pop_front())
+        );
+        input.push_back(21);
+        assert_eq!(
+            Ok(Batch::Outputs(vec![42, 1])),
+            code.run_n_outputs(2, || input.pop_front())
+        );
+        assert_eq!(
+            Ok(Batch::Outputs(vec![2, 3, 4, 5])),
+            code.run_n_outputs(4, || None)
+        );
+        assert_eq!(Ok(Batch::Halt), code.run_n_outputs(4, || None));
+
+        let mut code = Intcode::new(src);
+        let mut records = code.records::<3>(|| Some(1));
+        assert_eq!(Some(Ok([2, 1, 2])), records.next());
+        assert_eq!(Some(Ok([3, 4, 5])), records.next());
+        assert_eq!(None, records.next());
+        assert_eq!(Some(ExitMode::Halt), records.exit());
+
+        let mut code = Intcode::new(src);
+        let mut records = code.records::<4>(|| Some(1));
+        assert_eq!(Some(Ok([2, 1, 2, 3])), records.next());
+        let t = records.next().unwrap().unwrap_err();
+        assert_eq!(vec![4, 5], t.outputs);
+        assert_eq!("halted after 2 of 4 outputs", t.to_string());
+        assert_eq!(None, records.next());
+    }
+
+    #[test]
+    fn test_idle() {
+        // poll for input, echoing anything but -1
+        let mut code = Intcode::new("3,100,1008,100,-1,101,1005,101,0,4,100,1105,1,0");
+        let mut input = VecDeque::from(vec![7]);
+        assert_eq!(Status::Output(7), code.run_until_idle(&mut input, -1, 2));
+        assert_eq!(Status::Idle, code.run_until_idle(&mut input, -1, 2));
+        assert_eq!(Status::Idle, code.run_until_idle(&mut input, -1, 5));
+        input.push_back(9);
+        assert_eq!(Status::Output(9), code.run_until_idle(&mut input, -1, 2));
+
+        // count the polls, which never goes idle, until 3
+        let mut code = Intcode::new("3,100,101,1,101,101,1007,101,3,102,1005,102,0,104,1,99");
+        assert_eq!(Status::Output(1), code.run_until_idle(&mut input, -1, 2));
+        assert_eq!(3, code.peek(101));
+        assert_eq!(Status::Halt, code.run_until_idle(&mut input, -1, 2));
+
+        // count the polls forever
+        let mut code = Intcode::new("3,100,101,1,101,101,1105,1,0");
+        assert_eq!(Status::Idle, code.run_until_idle(&mut input, -1, 2));
+        assert_eq!(SPIN_STEPS as i64 / 3, code.peek(101));
+        input.push_back(5);
+        assert_eq!(Status::Idle, code.run_until_idle(&mut input, -1, 2));
+        assert!(input.is_empty());
+
+        // in batches
+        let mut code = Intcode::new("3,100,1008,100,-1,101,1005,101,0,4,100,4,100,1105,1,0");
+        input.push_back(4);
+        assert_eq!(
+            Ok(Batch::Outputs(vec![4, 4])),
+            code.run_n_until_idle(2, &mut input, -1, 2)
+        );
+        input.push_back(6);
+        let t = code.run_n_until_idle(3, &mut input, -1, 2).unwrap_err();
+        assert_eq!((vec![6, 6], ExitMode::AwaitingInput), (t.outputs, t.exit));
+    }
+
+    #[test]
+    fn test_taint() {
+        // read a, b and c, then output a + b, c * 2, 5, a < c, and 7
+        // after overwriting a
+        let mut code = Intcode::new(
+            "3,100,3,101,3,102,1,100,101,103,4,103,1002,102,2,104,4,104,104,5,7,100,102,105,4,105,1101,7,0,100,4,100,99",
+        );
+        let mut taint = Taint::new();
+        let mut input: VecDeque<i64> = vec![1, 2, 3].into_iter().collect();
+        let mut outputs = Vec::new();
+        while let ExitMode::Output(x) = code.run_taint(&mut taint, &mut input) {
+            outputs.push(x);
         }
+        assert_eq!(vec![3, 6, 5, 1, 7], outputs);
+        let labels: Vec<Vec<usize>> = taint
+            .outputs
+            .iter()
+            .map(|l| l.iter().copied().collect())
+            .collect();
+        assert_eq!(
+            vec![vec![0, 1], vec![2], vec![], vec![0, 2], vec![]],
+            labels
+        );
+        assert_eq!(
+            vec![2],
+            taint.memory[&104].iter().copied().collect::<Vec<_>>()
+        );
     }
 }
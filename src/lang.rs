@@ -0,0 +1,1200 @@
+use crate::dap::SourceMap;
+use std::collections::HashMap;
+
+// A small language that compiles to Intcode.
+//
+//   fn fact(n) {
+//       if (n < 2) { return 1; }
+//       return n * fact(n - 1);
+//   }
+//   var a[3];
+//   var i = 0;
+//   while (i < 3) { a[i] = input(); i = i + 1; }
+//   output(fact(a[2]));
+//
+// Values are integers. There are + - * and the comparisons, ! and unary
+// -, and && and || which always evaluate both sides. Variables declared at
+// the top level are globals, anything else lives on the stack, as do
+// function arguments.
+//
+// The relative base is the stack pointer and always points just past the
+// current frame, so slot k of a frame of size n is at rb + k - n. A call
+// writes the return address to [rb] and the arguments after it, then
+// jumps to the function, which moves the relative base up by its frame
+// size. Results come back in a global. Array elements are reached by
+// writing the address into the instruction that uses it.
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Sym(&'static str),
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";", "=", "+", "-", "*",
+    "<", ">", "!",
+];
+
+fn lex(s: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    for (n, line) in s.lines().enumerate() {
+        let line_no = n + 1;
+        let line = match line.find("//") {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let x = rest[..len]
+                    .parse()
+                    .map_err(|_| format!("line {}: number too large", line_no))?;
+                tokens.push((line_no, Token::Num(x)));
+                len
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((line_no, Token::Ident(rest[..len].to_string())));
+                len
+            } else {
+                match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                    Some(s) => {
+                        tokens.push((line_no, Token::Sym(s)));
+                        s.len()
+                    }
+                    None => return Err(format!("line {}: unexpected {:?}", line_no, c)),
+                }
+            };
+            rest = rest[len..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Index(String, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum StmtKind {
+    Var(String, Option<Expr>),
+    Array(String, i64),
+    Assign(String, Expr),
+    AssignIndex(String, Expr, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Stmt {
+    line: usize,
+    kind: StmtKind,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some((line, _)) => *line,
+            None => 1,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        let found = match self.peek() {
+            Some(Token::Num(x)) => x.to_string(),
+            Some(Token::Ident(s)) => s.clone(),
+            Some(Token::Sym(s)) => s.to_string(),
+            None => "end of input".to_string(),
+        };
+        Err(format!(
+            "line {}: expected {} but found {}",
+            self.line(),
+            expected,
+            found
+        ))
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        match self.peek() {
+            Some(Token::Sym(s)) if *s == sym => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), String> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", sym))
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        if let Some(Token::Ident(s)) = self.peek() {
+            if s == word {
+                self.pos += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        if let Some(Token::Ident(s)) = self.peek() {
+            let s = s.clone();
+            self.pos += 1;
+            Ok(s)
+        } else {
+            self.error("a name")
+        }
+    }
+
+    fn program(&mut self) -> Result<(Vec<Function>, Vec<Stmt>), String> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        while self.peek().is_some() {
+            if self.keyword("fn") {
+                let name = self.ident()?;
+                self.expect("(")?;
+                let mut params = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        params.push(self.ident()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                let body = self.block()?;
+                functions.push(Function { name, params, body });
+            } else {
+                main.push(self.statement()?);
+            }
+        }
+        Ok((functions, main))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.expect("{")?;
+        let mut v = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return self.error("'}'");
+            }
+            v.push(self.statement()?);
+        }
+        Ok(v)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        let line = self.line();
+        let kind = if self.keyword("var") {
+            let name = self.ident()?;
+            if self.eat("[") {
+                let n = match self.peek() {
+                    Some(&Token::Num(n)) if n > 0 => n,
+                    _ => return self.error("an array size"),
+                };
+                self.pos += 1;
+                self.expect("]")?;
+                self.expect(";")?;
+                StmtKind::Array(name, n)
+            } else {
+                let init = if self.eat("=") {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                self.expect(";")?;
+                StmtKind::Var(name, init)
+            }
+        } else if self.keyword("if") {
+            self.expect("(")?;
+            let cond = self.expr()?;
+            self.expect(")")?;
+            let then = self.block()?;
+            let otherwise = if self.keyword("else") {
+                if let Some(Token::Ident(s)) = self.peek() {
+                    if s == "if" {
+                        vec![self.statement()?]
+                    } else {
+                        self.block()?
+                    }
+                } else {
+                    self.block()?
+                }
+            } else {
+                vec![]
+            };
+            StmtKind::If(cond, then, otherwise)
+        } else if self.keyword("while") {
+            self.expect("(")?;
+            let cond = self.expr()?;
+            self.expect(")")?;
+            StmtKind::While(cond, self.block()?)
+        } else if self.keyword("return") {
+            let value = if self.eat(";") {
+                None
+            } else {
+                let e = self.expr()?;
+                self.expect(";")?;
+                Some(e)
+            };
+            StmtKind::Return(value)
+        } else {
+            let e = self.expr()?;
+            let kind = if self.eat("=") {
+                let value = self.expr()?;
+                match e {
+                    Expr::Var(name) => StmtKind::Assign(name, value),
+                    Expr::Index(name, i) => StmtKind::AssignIndex(name, *i, value),
+                    _ => {
+                        return Err(format!(
+                            "line {}: can only assign to variables and array elements",
+                            line
+                        ))
+                    }
+                }
+            } else {
+                StmtKind::Expr(e)
+            };
+            self.expect(";")?;
+            kind
+        };
+        Ok(Stmt { line, kind })
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    // operators by precedence, lowest first
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[(&str, BinOp)]; 5] = [
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[
+                ("==", BinOp::Eq),
+                ("!=", BinOp::Ne),
+                ("<=", BinOp::Le),
+                (">=", BinOp::Ge),
+                ("<", BinOp::Lt),
+                (">", BinOp::Gt),
+            ],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut e = self.binary(level + 1)?;
+        'outer: loop {
+            for &(sym, op) in LEVELS[level].iter() {
+                if self.eat(sym) {
+                    let rhs = self.binary(level + 1)?;
+                    e = Expr::Binary(op, Box::new(e), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(e);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Num(x)) => {
+                self.pos += 1;
+                Ok(Expr::Num(x))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if self.eat("(") {
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    Ok(Expr::Call(name, args))
+                } else if self.eat("[") {
+                    let i = self.expr()?;
+                    self.expect("]")?;
+                    Ok(Expr::Index(name, Box::new(i)))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            _ if self.eat("(") => {
+                let e = self.expr()?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
+
+// what a word of an instruction holds, resolved once addresses are known
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Val {
+    Lit(i64),
+    Label(usize),
+    // address of a global
+    Global(usize),
+    // relative offset of a stack slot in the current function
+    Slot(usize),
+    // the current function's frame size, times the sign
+    Frame(i64),
+    // the relative base on entry to main
+    StackTop,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Operand {
+    mode: i64,
+    val: Val,
+}
+
+fn imm(x: i64) -> Operand {
+    Operand {
+        mode: 1,
+        val: Val::Lit(x),
+    }
+}
+
+fn literal(o: Operand) -> Option<i64> {
+    match o {
+        Operand {
+            mode: 1,
+            val: Val::Lit(x),
+        } => Some(x),
+        _ => None,
+    }
+}
+
+// the global holding the result of the last call
+const RESULT: Operand = Operand {
+    mode: 0,
+    val: Val::Global(0),
+};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Storage {
+    Global(usize),
+    Slot(usize),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Variable {
+    at: Storage,
+    array: bool,
+}
+
+#[derive(Default)]
+struct Compiler {
+    words: Vec<i64>,
+    fixups: Vec<(usize, Val)>,
+    labels: Vec<Option<i64>>,
+    globals: HashMap<String, Variable>,
+    global_size: usize,
+    // name to label and number of parameters
+    functions: HashMap<String, (usize, usize)>,
+    lines: Vec<(i64, i64)>,
+    main_size: i64,
+
+    // the function being compiled
+    in_function: bool,
+    locals: HashMap<String, Variable>,
+    slot_fixups: Vec<(usize, Val)>,
+    locals_end: usize,
+    next_temp: usize,
+    frame_size: usize,
+}
+
+impl Compiler {
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, l: usize) {
+        self.place_at(l, 0);
+    }
+
+    // point a label offset words past the next instruction
+    fn place_at(&mut self, l: usize, offset: i64) {
+        self.labels[l] = Some(self.words.len() as i64 + offset);
+    }
+
+    fn emit(&mut self, op: i64, args: &[Operand]) {
+        let mut code = op;
+        let mut m = 100;
+        for a in args {
+            code += a.mode * m;
+            m *= 10;
+        }
+        self.words.push(code);
+        for a in args {
+            let pos = self.words.len();
+            match a.val {
+                Val::Lit(x) => self.words.push(x),
+                Val::Slot(_) | Val::Frame(_) => {
+                    self.slot_fixups.push((pos, a.val));
+                    self.words.push(0);
+                }
+                v => {
+                    self.fixups.push((pos, v));
+                    self.words.push(0);
+                }
+            }
+        }
+    }
+
+    fn temp(&mut self) -> Operand {
+        let k = self.next_temp;
+        self.next_temp += 1;
+        self.frame_size = self.frame_size.max(self.next_temp);
+        Operand {
+            mode: 2,
+            val: Val::Slot(k),
+        }
+    }
+
+    fn alloc(&mut self, n: usize) -> usize {
+        let k = self.locals_end;
+        self.locals_end += n;
+        self.frame_size = self.frame_size.max(self.locals_end);
+        k
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Variable, String> {
+        self.locals
+            .get(name)
+            .or_else(|| self.globals.get(name))
+            .copied()
+            .ok_or_else(|| format!("line {}: unknown variable {}", line, name))
+    }
+
+    fn scalar(&self, name: &str, line: usize) -> Result<Operand, String> {
+        let v = self.lookup(name, line)?;
+        if v.array {
+            return Err(format!("line {}: {} is an array", line, name));
+        }
+        Ok(match v.at {
+            Storage::Global(g) => Operand {
+                mode: 0,
+                val: Val::Global(g),
+            },
+            Storage::Slot(k) => Operand {
+                mode: 2,
+                val: Val::Slot(k),
+            },
+        })
+    }
+
+    // the operand for an element, writing the address into the operand
+    // `slot` of the instruction emitted next if it isn't constant
+    fn element(
+        &mut self,
+        name: &str,
+        i: Operand,
+        slot: i64,
+        line: usize,
+    ) -> Result<Operand, String> {
+        let v = self.lookup(name, line)?;
+        if !v.array {
+            return Err(format!("line {}: {} is not an array", line, name));
+        }
+        let (mode, base) = match v.at {
+            Storage::Global(g) => (0, Val::Global(g)),
+            Storage::Slot(k) => (2, Val::Slot(k)),
+        };
+        if let Some(x) = literal(i) {
+            let val = match v.at {
+                Storage::Global(g) => Val::Global((g as i64 + x) as usize),
+                Storage::Slot(k) => Val::Slot((k as i64 + x) as usize),
+            };
+            return Ok(Operand { mode, val });
+        }
+        let l = self.label();
+        let at = Operand {
+            mode: 0,
+            val: Val::Label(l),
+        };
+        self.emit(1, &[i, Operand { mode: 1, val: base }, at]);
+        self.place_at(l, slot);
+        Ok(Operand {
+            mode,
+            val: Val::Lit(0),
+        })
+    }
+
+    fn binary(&mut self, op: BinOp, a: Operand, b: Operand) -> Operand {
+        if let (Some(x), Some(y)) = (literal(a), literal(b)) {
+            return imm(match op {
+                BinOp::Add => x + y,
+                BinOp::Sub => x - y,
+                BinOp::Mul => x * y,
+                BinOp::Lt => (x < y) as i64,
+                BinOp::Gt => (x > y) as i64,
+                BinOp::Le => (x <= y) as i64,
+                BinOp::Ge => (x >= y) as i64,
+                BinOp::Eq => (x == y) as i64,
+                BinOp::Ne => (x != y) as i64,
+                BinOp::And => (x != 0 && y != 0) as i64,
+                BinOp::Or => (x != 0 || y != 0) as i64,
+            });
+        }
+        let t = self.temp();
+        match op {
+            BinOp::Add => self.emit(1, &[a, b, t]),
+            BinOp::Sub => match literal(b) {
+                Some(y) => self.emit(1, &[a, imm(-y), t]),
+                None => {
+                    self.emit(2, &[b, imm(-1), t]);
+                    self.emit(1, &[a, t, t]);
+                }
+            },
+            BinOp::Mul => self.emit(2, &[a, b, t]),
+            BinOp::Lt => self.emit(7, &[a, b, t]),
+            BinOp::Gt => self.emit(7, &[b, a, t]),
+            BinOp::Eq => self.emit(8, &[a, b, t]),
+            BinOp::Ne | BinOp::Le | BinOp::Ge => {
+                match op {
+                    BinOp::Ne => self.emit(8, &[a, b, t]),
+                    BinOp::Le => self.emit(7, &[b, a, t]),
+                    _ => self.emit(7, &[a, b, t]),
+                }
+                self.emit(8, &[t, imm(0), t]);
+            }
+            BinOp::And | BinOp::Or => {
+                // count the zeros
+                let z = self.temp();
+                self.emit(8, &[a, imm(0), t]);
+                self.emit(8, &[b, imm(0), z]);
+                self.emit(1, &[t, z, t]);
+                match op {
+                    BinOp::And => self.emit(8, &[t, imm(0), t]),
+                    _ => self.emit(7, &[t, imm(2), t]),
+                }
+            }
+        }
+        t
+    }
+
+    fn expr(&mut self, e: &Expr, line: usize) -> Result<Operand, String> {
+        Ok(match e {
+            Expr::Num(x) => imm(*x),
+            Expr::Var(name) => self.scalar(name, line)?,
+            Expr::Index(name, i) => {
+                let i = self.expr(i, line)?;
+                let el = self.element(name, i, 1, line)?;
+                let t = self.temp();
+                self.emit(1, &[el, imm(0), t]);
+                t
+            }
+            Expr::Neg(a) => {
+                let a = self.expr(a, line)?;
+                match literal(a) {
+                    Some(x) => imm(-x),
+                    None => {
+                        let t = self.temp();
+                        self.emit(2, &[a, imm(-1), t]);
+                        t
+                    }
+                }
+            }
+            Expr::Not(a) => {
+                let a = self.expr(a, line)?;
+                match literal(a) {
+                    Some(x) => imm((x == 0) as i64),
+                    None => {
+                        let t = self.temp();
+                        self.emit(8, &[a, imm(0), t]);
+                        t
+                    }
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.expr(a, line)?;
+                let b = self.expr(b, line)?;
+                self.binary(*op, a, b)
+            }
+            Expr::Call(name, args) => self.call(name, args, line)?,
+        })
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Operand, String> {
+        let arity = match name {
+            "input" => 0,
+            "output" => 1,
+            _ => match self.functions.get(name) {
+                Some(&(_, n)) => n,
+                None => return Err(format!("line {}: unknown function {}", line, name)),
+            },
+        };
+        if args.len() != arity {
+            return Err(format!(
+                "line {}: {} takes {} arguments but was given {}",
+                line,
+                name,
+                arity,
+                args.len()
+            ));
+        }
+        let args = args
+            .iter()
+            .map(|a| self.expr(a, line))
+            .collect::<Result<Vec<_>, _>>()?;
+        match name {
+            "input" => {
+                let t = self.temp();
+                self.emit(3, &[t]);
+                Ok(t)
+            }
+            "output" => {
+                self.emit(4, &[args[0]]);
+                Ok(imm(0))
+            }
+            _ => {
+                let f = self.functions[name].0;
+                for (i, &a) in args.iter().enumerate() {
+                    let to = Operand {
+                        mode: 2,
+                        val: Val::Lit(1 + i as i64),
+                    };
+                    self.emit(1, &[a, imm(0), to]);
+                }
+                let ret = self.label();
+                let return_addr = Operand {
+                    mode: 1,
+                    val: Val::Label(ret),
+                };
+                let top = Operand {
+                    mode: 2,
+                    val: Val::Lit(0),
+                };
+                self.emit(1, &[return_addr, imm(0), top]);
+                let target = Operand {
+                    mode: 1,
+                    val: Val::Label(f),
+                };
+                self.emit(5, &[imm(1), target]);
+                self.place(ret);
+                let t = self.temp();
+                self.emit(1, &[RESULT, imm(0), t]);
+                Ok(t)
+            }
+        }
+    }
+
+    fn ret(&mut self, value: Operand) {
+        if !self.in_function {
+            self.emit(99, &[]);
+            return;
+        }
+        self.emit(1, &[value, imm(0), RESULT]);
+        let frame = Operand {
+            mode: 1,
+            val: Val::Frame(-1),
+        };
+        self.emit(9, &[frame]);
+        let top = Operand {
+            mode: 2,
+            val: Val::Lit(0),
+        };
+        self.emit(5, &[imm(1), top]);
+    }
+
+    fn block(&mut self, stmts: &[Stmt], top: bool) -> Result<(), String> {
+        for s in stmts.iter() {
+            self.statement(s, top)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, s: &Stmt, top: bool) -> Result<(), String> {
+        let line = s.line;
+        self.lines.push((self.words.len() as i64, line as i64));
+        self.next_temp = self.locals_end;
+        match &s.kind {
+            StmtKind::Var(name, init) => {
+                let value = match init {
+                    Some(e) => self.expr(e, line)?,
+                    None => imm(0),
+                };
+                if !top {
+                    let k = self.alloc(1);
+                    self.locals.insert(
+                        name.clone(),
+                        Variable {
+                            at: Storage::Slot(k),
+                            array: false,
+                        },
+                    );
+                }
+                let to = self.scalar(name, line)?;
+                self.emit(1, &[value, imm(0), to]);
+            }
+            StmtKind::Array(name, n) => {
+                if !top {
+                    let k = self.alloc(*n as usize);
+                    self.locals.insert(
+                        name.clone(),
+                        Variable {
+                            at: Storage::Slot(k),
+                            array: true,
+                        },
+                    );
+                }
+            }
+            StmtKind::Assign(name, e) => {
+                let value = self.expr(e, line)?;
+                let to = self.scalar(name, line)?;
+                self.emit(1, &[value, imm(0), to]);
+            }
+            StmtKind::AssignIndex(name, i, e) => {
+                let value = self.expr(e, line)?;
+                let i = self.expr(i, line)?;
+                let to = self.element(name, i, 3, line)?;
+                self.emit(1, &[value, imm(0), to]);
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let c = self.expr(cond, line)?;
+                let other = self.label();
+                let other_op = Operand {
+                    mode: 1,
+                    val: Val::Label(other),
+                };
+                self.emit(6, &[c, other_op]);
+                self.block(then, false)?;
+                if otherwise.is_empty() {
+                    self.place(other);
+                } else {
+                    let end = self.label();
+                    let end_op = Operand {
+                        mode: 1,
+                        val: Val::Label(end),
+                    };
+                    self.emit(5, &[imm(1), end_op]);
+                    self.place(other);
+                    self.block(otherwise, false)?;
+                    self.place(end);
+                }
+            }
+            StmtKind::While(cond, body) => {
+                let start = self.label();
+                let end = self.label();
+                self.place(start);
+                let c = self.expr(cond, line)?;
+                let end_op = Operand {
+                    mode: 1,
+                    val: Val::Label(end),
+                };
+                self.emit(6, &[c, end_op]);
+                self.block(body, false)?;
+                let start_op = Operand {
+                    mode: 1,
+                    val: Val::Label(start),
+                };
+                self.emit(5, &[imm(1), start_op]);
+                self.place(end);
+            }
+            StmtKind::Return(e) => {
+                let value = match e {
+                    Some(e) => self.expr(e, line)?,
+                    None => imm(0),
+                };
+                self.ret(value);
+            }
+            StmtKind::Expr(e) => {
+                self.expr(e, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    // resolve the slots of the function just compiled, returning its
+    // frame size
+    fn end_frame(&mut self) -> i64 {
+        let size = self.frame_size as i64;
+        for (pos, v) in self.slot_fixups.drain(..) {
+            self.words[pos] = match v {
+                Val::Slot(k) => k as i64 - size,
+                Val::Frame(sign) => sign * size,
+                _ => unreachable!(),
+            };
+        }
+        size
+    }
+
+    fn start_frame(&mut self, params: &[String]) {
+        self.locals.clear();
+        // slot 0 holds the return address
+        for (i, p) in params.iter().enumerate() {
+            let v = Variable {
+                at: Storage::Slot(i + 1),
+                array: false,
+            };
+            self.locals.insert(p.clone(), v);
+        }
+        self.locals_end = params.len() + 1;
+        self.next_temp = self.locals_end;
+        self.frame_size = self.locals_end;
+    }
+}
+
+// A compiled program, with a source map from instruction addresses to
+// the lines of the statements they came from.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Compiled {
+    pub words: Vec<i64>,
+    pub map: SourceMap,
+}
+
+pub fn compile(src: &str) -> Result<Compiled, String> {
+    let mut parser = Parser {
+        tokens: lex(src)?,
+        pos: 0,
+    };
+    let (functions, main) = parser.program()?;
+
+    let mut c = Compiler {
+        global_size: 1,
+        ..Compiler::default()
+    };
+    for s in main.iter() {
+        let (name, array, n) = match &s.kind {
+            StmtKind::Var(name, _) => (name, false, 1),
+            StmtKind::Array(name, n) => (name, true, *n as usize),
+            _ => continue,
+        };
+        let v = Variable {
+            at: Storage::Global(c.global_size),
+            array,
+        };
+        if c.globals.insert(name.clone(), v).is_some() {
+            return Err(format!("line {}: {} declared twice", s.line, name));
+        }
+        c.global_size += n;
+    }
+    for f in functions.iter() {
+        if f.name == "input" || f.name == "output" {
+            return Err(format!("{} is built in", f.name));
+        }
+        let l = c.label();
+        if c.functions
+            .insert(f.name.clone(), (l, f.params.len()))
+            .is_some()
+        {
+            return Err(format!("function {} defined twice", f.name));
+        }
+    }
+
+    c.start_frame(&[]);
+    let top = Operand {
+        mode: 1,
+        val: Val::StackTop,
+    };
+    c.emit(9, &[top]);
+    c.block(&main, true)?;
+    c.emit(99, &[]);
+    c.main_size = c.end_frame();
+
+    c.in_function = true;
+    for f in functions.iter() {
+        c.start_frame(&f.params);
+        let l = c.functions[&f.name].0;
+        c.place(l);
+        let frame = Operand {
+            mode: 1,
+            val: Val::Frame(1),
+        };
+        c.emit(9, &[frame]);
+        c.block(&f.body, false)?;
+        c.ret(imm(0));
+        c.end_frame();
+    }
+
+    let len = c.words.len() as i64;
+    for &(pos, v) in c.fixups.iter() {
+        c.words[pos] = match v {
+            Val::Label(l) => c.labels[l].expect("label placed"),
+            Val::Global(g) => len + g as i64,
+            Val::StackTop => len + c.global_size as i64 + c.main_size,
+            _ => unreachable!(),
+        };
+    }
+
+    let mut map = SourceMap::default();
+    for &(addr, line) in c.lines.iter() {
+        // a statement that emits nothing shares its address with the
+        // next one
+        map.lines.insert(addr, line);
+    }
+    Ok(Compiled {
+        words: c.words,
+        map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{ExitMode, Intcode};
+    use std::collections::VecDeque;
+
+    fn run(src: &str, input: &[i64]) -> Vec<i64> {
+        let mut code = Intcode::from_vec(compile(src).unwrap().words);
+        let mut input: VecDeque<i64> = input.iter().copied().collect();
+        let mut out = Vec::new();
+        loop {
+            match code.run_queue(&mut input) {
+                ExitMode::Output(x) => out.push(x),
+                ExitMode::Halt => return out,
+                ExitMode::AwaitingInput => panic!("out of input"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(
+            vec![7, -3, 20, 9],
+            run(
+                "output(1 + 2 * 3); output(-3); output((1 + 3) * 5); output(10 - 1);",
+                &[]
+            )
+        );
+        let src = "var a = input(); var b = input();
+            output(a + b); output(a - b); output(a * b); output(-a);
+            output(a < b); output(a > b); output(a <= b); output(a >= b);
+            output(a == b); output(a != b); output(!a);
+            output(a && b); output(a || b); output(a && 0); output(0 || b);";
+        assert_eq!(
+            vec![7, -3, 10, -2, 1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1],
+            run(src, &[2, 5])
+        );
+        assert_eq!(
+            vec![10, 0, 25, -5, 0, 0, 1, 1, 1, 0],
+            run(src, &[5, 5])[..10].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        // sum inputs until a zero, then count down from the sum by threes
+        let src = "
+            var sum = 0;
+            var x = input();
+            while (x != 0) {
+                sum = sum + x;
+                x = input();
+            }
+            if (sum > 10) {
+                output(1);
+            } else if (sum > 5) {
+                output(2);
+            } else {
+                output(3);
+            }
+            while (sum > 0) { output(sum); sum = sum - 3; }
+        ";
+        assert_eq!(vec![2, 9, 6, 3], run(src, &[4, 5, 0]));
+        assert_eq!(vec![1, 11, 8, 5, 2], run(src, &[11, 0]));
+        assert_eq!(vec![3], run(src, &[0]));
+    }
+
+    #[test]
+    fn test_arrays() {
+        // sort the inputs with a global array, then reverse them into a
+        // local one
+        let src = "
+            var n = input();
+            var a[10];
+            var i = 0;
+            while (i < n) { a[i] = input(); i = i + 1; }
+            i = 0;
+            while (i < n) {
+                var j = 0;
+                while (j < n - 1 - i) {
+                    if (a[j] > a[j + 1]) {
+                        var t = a[j];
+                        a[j] = a[j + 1];
+                        a[j + 1] = t;
+                    }
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            if (1) {
+                var b[10];
+                i = 0;
+                while (i < n) { b[n - 1 - i] = a[i]; i = i + 1; }
+                i = 0;
+                while (i < n) { output(b[i]); i = i + 1; }
+                output(a[0] + b[0]);
+            }
+        ";
+        assert_eq!(vec![9, 5, 3, 1, -2, 7], run(src, &[5, 3, 9, -2, 5, 1]));
+    }
+
+    #[test]
+    fn test_functions() {
+        let src = "
+            fn fact(n) {
+                if (n < 2) { return 1; }
+                return n * fact(n - 1);
+            }
+            fn fib(n) {
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            // locals and arrays on the stack survive calls
+            fn squares(n) {
+                var a[5];
+                var i = 0;
+                while (i < n) { a[i] = i * i; i = i + 1; }
+                var sum = 0;
+                while (i > 0) { i = i - 1; sum = sum + a[i] + fact(1); }
+                return sum;
+            }
+            fn count() { calls = calls + 1; }
+            var calls = 0;
+            output(fact(input()));
+            output(fib(10));
+            output(squares(4));
+            count(); count();
+            output(calls);
+            output(fact(3) + fib(fact(3)) * 2);
+        ";
+        assert_eq!(vec![120, 55, 18, 2, 22], run(src, &[5]));
+    }
+
+    #[test]
+    fn test_frames() {
+        // the relative base moves up by a frame on each call, as the
+        // debugger expects
+        let c = compile("fn f(x) { return x + 1; } output(f(1));").unwrap();
+        let mut code = Intcode::from_vec(c.words.clone());
+        let mut stack = crate::stack::CallStack::default();
+        let mut max = 0;
+        loop {
+            let pc = code.pc();
+            let exit = code
+                .step_ext(&mut crate::intcode::NoExtension, &mut || None)
+                .unwrap();
+            stack.step(pc, &code);
+            max = max.max(stack.depth());
+            if exit == Some(ExitMode::Halt) {
+                break;
+            }
+        }
+        assert_eq!(2, max);
+        assert_eq!(1, stack.depth());
+    }
+
+    #[test]
+    fn test_source_map() {
+        let c = compile("var x = 1;\n\nwhile (x < 3) {\n  x = x + 1;\n}\noutput(x);\n").unwrap();
+        let lines: Vec<i64> = c.map.lines.values().copied().collect();
+        assert_eq!(vec![1, 3, 4, 6], lines);
+        assert_eq!(Some(6), c.map.line(c.words.len() as i64 - 1));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err("line 2: expected ';' but found y".to_string()),
+            compile("var x = 1;\nx = 2 y")
+        );
+        assert_eq!(
+            Err("line 1: unknown variable y".to_string()),
+            compile("var x = y;")
+        );
+        assert_eq!(
+            Err("line 1: unknown function f".to_string()),
+            compile("f();")
+        );
+        assert_eq!(
+            Err("line 1: output takes 1 arguments but was given 2".to_string()),
+            compile("output(1, 2);")
+        );
+        assert_eq!(
+            Err("line 1: a is an array".to_string()),
+            compile("var a[2]; output(a);")
+        );
+        assert_eq!(
+            Err("line 3: expected '}' but found end of input".to_string()),
+            compile("if (1) {\n\noutput(1);")
+        );
+        assert_eq!(
+            Err("line 1: unexpected '%'".to_string()),
+            compile("output(1 % 2);")
+        );
+    }
+}
@@ -1,7 +1,52 @@
+mod bench;
+mod coverage;
+mod dap;
+mod day1;
+mod day10;
+mod day11;
+mod day12;
+mod day13;
+mod day14;
+mod day15;
+mod day16;
+mod day17;
+mod day19;
+mod day2;
+mod day21;
 mod day22;
-// mod intcode;
+mod day23;
+mod day24;
+mod day25;
+mod day3;
+mod day4;
+mod day5;
+mod day6;
+mod day7;
+mod day8;
+mod day9;
+mod device;
+mod fuzz;
+mod gdb;
+mod input;
+mod intcode;
+mod lang;
+mod network;
+mod optimize;
+mod pipeline;
+mod profile;
+mod program;
+mod record;
+mod runner;
+mod server;
+mod solution;
+mod stack;
+mod unwind;
+mod verify;
 
 fn main() {
-    // day22::part1();
-    day22::part2();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = runner::main(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
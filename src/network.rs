@@ -0,0 +1,366 @@
+use crate::intcode::{Batch, ExitMode, Intcode, Truncated};
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Packet {
+    pub src: i64,
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Route {
+    Node(usize),
+    Nat,
+    Drop,
+}
+
+pub trait Router {
+    fn route(&mut self, p: &Packet, nodes: usize) -> Route;
+}
+
+// sends packets to the node with the destination address, or to the nat
+// at 255. Anything else is lost.
+pub struct DirectRouter;
+
+impl Router for DirectRouter {
+    fn route(&mut self, p: &Packet, nodes: usize) -> Route {
+        if p.dest >= 0 && (p.dest as usize) < nodes {
+            Route::Node(p.dest as usize)
+        } else if p.dest == 255 {
+            Route::Nat
+        } else {
+            Route::Drop
+        }
+    }
+}
+
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+pub trait Nat {
+    // called for every packet the router sends to the nat
+    fn receive(&mut self, p: &Packet) -> Control;
+    // called when the network is idle. Returning a packet wakes the
+    // network up, returning None stops it.
+    fn idle(&mut self) -> Option<Packet>;
+}
+
+#[derive(Debug)]
+struct Node {
+    code: Intcode,
+    queue: VecDeque<i64>,
+    out: Vec<i64>,
+    halted: bool,
+}
+
+// how many -1s in a row a node must read, without sending or changing
+// memory, to count as idle
+const IDLE_POLLS: usize = 2;
+
+impl Node {
+    // run until the node is idle, polling -1 with nothing queued, or
+    // halts. Returns whether the node had any real input.
+    fn turn(&mut self, id: usize, packets: &mut Vec<Packet>) -> Result<bool, String> {
+        if self.halted {
+            return Ok(false);
+        }
+        let had_input = !self.queue.is_empty();
+        loop {
+            // a packet may be split across turns, going idle part way
+            // through
+            match self
+                .code
+                .run_n_until_idle(3 - self.out.len(), &mut self.queue, -1, IDLE_POLLS)
+            {
+                Ok(Batch::Outputs(v)) => {
+                    self.out.extend(v);
+                    packets.push(Packet {
+                        src: id as i64,
+                        dest: self.out[0],
+                        x: self.out[1],
+                        y: self.out[2],
+                    });
+                    self.out.clear();
+                }
+                Ok(Batch::Halt) if self.out.is_empty() => {
+                    self.halted = true;
+                    break;
+                }
+                Ok(Batch::AwaitingInput) => break,
+                Err(Truncated {
+                    outputs,
+                    exit: ExitMode::AwaitingInput,
+                    ..
+                }) => {
+                    self.out.extend(outputs);
+                    break;
+                }
+                Ok(Batch::Halt) | Err(_) => {
+                    return Err(format!("node {} halted part way through a packet", id))
+                }
+            }
+        }
+        Ok(had_input)
+    }
+}
+
+// A network of Intcode nodes run round-robin on a single thread. Each
+// node is booted with its address as the first input.
+#[derive(Debug)]
+pub struct Network {
+    nodes: Vec<Node>,
+    tick: usize,
+    capture: Option<Capture>,
+}
+
+impl Network {
+    pub fn new(code: &Intcode, size: usize) -> Self {
+        let nodes = (0..size)
+            .map(|i| Node {
+                code: code.clone(),
+                queue: VecDeque::from(vec![i as i64]),
+                out: Vec::new(),
+                halted: false,
+            })
+            .collect();
+        Self {
+            nodes,
+            tick: 0,
+            capture: None,
+        }
+    }
+
+    // start recording every packet delivered
+    pub fn record(&mut self) {
+        if self.capture.is_none() {
+            self.capture = Some(Capture::default());
+        }
+    }
+
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
+    // deliver a packet, returns false if the nat asked to stop
+    fn deliver<R: Router, N: Nat>(&mut self, p: &Packet, router: &mut R, nat: &mut N) -> bool {
+        if let Some(c) = &mut self.capture {
+            c.records.push(Record {
+                tick: self.tick,
+                packet: *p,
+            });
+        }
+        match router.route(p, self.nodes.len()) {
+            Route::Node(i) => {
+                let q = &mut self.nodes[i].queue;
+                q.push_back(p.x);
+                q.push_back(p.y);
+                true
+            }
+            Route::Nat => match nat.receive(p) {
+                Control::Continue => true,
+                Control::Stop => false,
+            },
+            Route::Drop => true,
+        }
+    }
+
+    // Run until the nat stops the network or every node has halted.
+    // Each tick gives every node one turn, running it until it is idle.
+    // The network is idle when a whole tick passes with every queue empty
+    // and no packets sent.
+    // Returns the number of ticks run, or an error if a node halts part
+    // way through sending a packet.
+    pub fn run<R: Router, N: Nat>(&mut self, router: &mut R, nat: &mut N) -> Result<usize, String> {
+        let mut packets = Vec::new();
+        loop {
+            self.tick += 1;
+            let mut busy = false;
+            for i in 0..self.nodes.len() {
+                busy |= self.nodes[i].turn(i, &mut packets)?;
+                busy |= !packets.is_empty() || !self.nodes[i].out.is_empty();
+                for p in packets.drain(..) {
+                    if !self.deliver(&p, router, nat) {
+                        return Ok(self.tick);
+                    }
+                }
+            }
+
+            if self.nodes.iter().all(|n| n.halted) {
+                return Ok(self.tick);
+            }
+
+            if !busy {
+                match nat.idle() {
+                    Some(p) => {
+                        if !self.deliver(&p, router, nat) {
+                            return Ok(self.tick);
+                        }
+                    }
+                    None => return Ok(self.tick),
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Record {
+    pub tick: usize,
+    pub packet: Packet,
+}
+
+// Every packet delivered by a network, in delivery order. Packets from
+// the nat have a src of 255.
+//
+// The text format is one packet per line as "tick src dest x y", with
+// blank lines and lines starting with '#' ignored.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Capture {
+    pub records: Vec<Record>,
+}
+
+const CAPTURE_HEADER: &str = "# network capture v1\n# tick src dest x y";
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", CAPTURE_HEADER)?;
+        for r in self.records.iter() {
+            let p = &r.packet;
+            writeln!(f, "{} {} {} {} {}", r.tick, p.src, p.dest, p.x, p.y)?;
+        }
+        Ok(())
+    }
+}
+
+impl Capture {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut records = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let v: Vec<i64> = line
+                .split_whitespace()
+                .map(|x| x.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("line {}: {}: {}", n + 1, e, line))?;
+            if v.len() != 5 || v[0] < 0 {
+                return Err(format!("line {}: bad record: {}", n + 1, line));
+            }
+            records.push(Record {
+                tick: v[0] as usize,
+                packet: Packet {
+                    src: v[1],
+                    dest: v[2],
+                    x: v[3],
+                    y: v[4],
+                },
+            });
+        }
+        Ok(Self { records })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&data)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn filter<P>(&self, pred: P) -> Self
+    where
+        P: Fn(&Record) -> bool,
+    {
+        Self {
+            records: self.records.iter().filter(|r| pred(r)).cloned().collect(),
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut s = Summary::default();
+        for r in self.records.iter() {
+            let p = &r.packet;
+            if p.src == 255 {
+                s.nat_deliveries += 1;
+            } else {
+                *s.sent.entry(p.src).or_default() += 1;
+            }
+            *s.received.entry(p.dest).or_default() += 1;
+            if p.dest == 255 && s.first_to_255.is_none() {
+                s.first_to_255 = Some(*r);
+            }
+        }
+        s
+    }
+
+    // Feed the captured packets through a different router and nat
+    // without running any nodes. Packets the nat sent in the capture are
+    // replaced by whatever the new nat returns from idle. Replies are
+    // routed but not acted on, so the replay is only faithful while the
+    // new logic makes the same deliveries as the original. Returns the
+    // number of records replayed.
+    pub fn replay<R: Router, N: Nat>(&self, nodes: usize, router: &mut R, nat: &mut N) -> usize {
+        for (i, r) in self.records.iter().enumerate() {
+            let p = if r.packet.src == 255 {
+                match nat.idle() {
+                    Some(p) => p,
+                    None => return i,
+                }
+            } else {
+                r.packet
+            };
+            if let Route::Nat = router.route(&p, nodes) {
+                if let Control::Stop = nat.receive(&p) {
+                    return i + 1;
+                }
+            }
+        }
+        self.records.len()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub sent: BTreeMap<i64, usize>,
+    pub received: BTreeMap<i64, usize>,
+    pub nat_deliveries: usize,
+    pub first_to_255: Option<Record>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "addr     sent  received")?;
+        let mut addrs: Vec<&i64> = self.sent.keys().chain(self.received.keys()).collect();
+        addrs.sort();
+        addrs.dedup();
+        for a in addrs {
+            writeln!(
+                f,
+                "{:>4} {:>8} {:>9}",
+                a,
+                self.sent.get(a).unwrap_or(&0),
+                self.received.get(a).unwrap_or(&0)
+            )?;
+        }
+        writeln!(f, "nat deliveries: {}", self.nat_deliveries)?;
+        match &self.first_to_255 {
+            Some(r) => write!(
+                f,
+                "first to 255: tick: {}, src: {}, x: {}, y: {}",
+                r.tick, r.packet.src, r.packet.x, r.packet.y
+            ),
+            None => write!(f, "first to 255: none"),
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use crate::intcode::{params, ExitMode, Intcode, NoExtension, Param};
+use crate::unwind;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Peephole optimizer. Rewrites are only made once the whole program has
+// been analysed: every reachable instruction decodes, every jump has an
+// immediate target, nothing writes to an instruction and relative
+// accesses provably stay past the end of the program. Anything else is
+// refused with the reason.
+
+// a range of relative bases, or of addresses; MIN and MAX are unbounded
+type Range = (i64, i64);
+
+const UNKNOWN: Range = (i64::MIN, i64::MAX);
+
+fn shift(r: Range, x: i64) -> Range {
+    let lo = if r.0 == i64::MIN { r.0 } else { r.0 + x };
+    let hi = if r.1 == i64::MAX { r.1 } else { r.1 + x };
+    (lo, hi)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Instr {
+    op: i64,
+    modes: [i64; 3],
+    len: i64,
+}
+
+fn decode(words: &[i64], addr: i64) -> Result<Instr, String> {
+    let w = words[addr as usize];
+    let op = w % 100;
+    let p = params(op).ok_or_else(|| format!("invalid code {} at {}", w, addr))?;
+    let mut modes = [0; 3];
+    let mut m = w / 100;
+    for mode in modes.iter_mut().take(p.len()) {
+        *mode = m % 10;
+        if *mode > 2 {
+            return Err(format!("invalid mode in {} at {}", w, addr));
+        }
+        m /= 10;
+    }
+    let len = 1 + p.len() as i64;
+    if addr + len > words.len() as i64 {
+        return Err(format!("instruction at {} runs past the end", addr));
+    }
+    Ok(Instr { op, modes, len })
+}
+
+fn arg(words: &[i64], addr: i64, n: usize) -> i64 {
+    words[(addr + 1) as usize + n]
+}
+
+fn is_jump(op: i64) -> bool {
+    op == 5 || op == 6
+}
+
+// whether a jump always goes to its target, None if it depends on input
+fn taken(words: &[i64], addr: i64, instr: Instr) -> Option<bool> {
+    if instr.modes[0] == 1 {
+        Some((arg(words, addr, 0) != 0) == (instr.op == 5))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+struct Analysis {
+    // reachable instructions
+    instrs: BTreeMap<i64, Instr>,
+    // relative base on reaching each instruction
+    rb: BTreeMap<i64, Range>,
+    succ: BTreeMap<i64, Vec<i64>>,
+    // program cells accessed as data
+    data: BTreeSet<i64>,
+    // addresses jumped to
+    targets: BTreeSet<i64>,
+}
+
+impl Analysis {
+    fn new(words: &[i64]) -> Result<Self, String> {
+        let len = words.len() as i64;
+        if len == 0 {
+            return Err("empty program".to_string());
+        }
+        let mut a = Self::default();
+        let mut visits: BTreeMap<i64, usize> = BTreeMap::new();
+        let mut work = vec![0];
+        a.rb.insert(0, (0, 0));
+        while let Some(addr) = work.pop() {
+            let instr = decode(words, addr)?;
+            a.instrs.insert(addr, instr);
+
+            let next = addr + instr.len;
+            let succ = match instr.op {
+                99 => vec![],
+                5 | 6 => {
+                    if instr.modes[1] != 1 {
+                        return Err(format!("indirect jump at {}", addr));
+                    }
+                    let target = arg(words, addr, 1);
+                    a.targets.insert(target);
+                    match taken(words, addr, instr) {
+                        Some(true) => vec![target],
+                        Some(false) => vec![next],
+                        None => vec![next, target],
+                    }
+                }
+                _ => vec![next],
+            };
+
+            let rb = match (instr.op, instr.modes[0]) {
+                (9, 1) => shift(a.rb[&addr], arg(words, addr, 0)),
+                (9, _) => UNKNOWN,
+                _ => a.rb[&addr],
+            };
+            for &s in succ.iter() {
+                if s < 0 || s >= len {
+                    return Err(format!("runs off the program at {}", addr));
+                }
+                let joined = match a.rb.get(&s) {
+                    None => rb,
+                    Some(&old) => {
+                        let j = (old.0.min(rb.0), old.1.max(rb.1));
+                        if j == old {
+                            continue;
+                        }
+                        // give up on bounds that keep moving, e.g. a
+                        // loop that adjusts the relative base
+                        let v = visits.entry(s).or_default();
+                        *v += 1;
+                        if *v > 3 {
+                            let lo = if j.0 < old.0 { i64::MIN } else { j.0 };
+                            let hi = if j.1 > old.1 { i64::MAX } else { j.1 };
+                            (lo, hi)
+                        } else {
+                            j
+                        }
+                    }
+                };
+                a.rb.insert(s, joined);
+                work.push(s);
+            }
+            a.succ.insert(addr, succ);
+        }
+
+        let mut code = BTreeMap::new();
+        for (&addr, instr) in a.instrs.iter() {
+            for c in addr..addr + instr.len {
+                if let Some(other) = code.insert(c, addr) {
+                    return Err(format!("instructions at {} and {} overlap", other, addr));
+                }
+            }
+        }
+        for (&addr, instr) in a.instrs.iter() {
+            let p = params(instr.op).expect("decoded");
+            for (n, &param) in p.iter().enumerate() {
+                let write = param == Param::Write;
+                let x = arg(words, addr, n);
+                match instr.modes[n] {
+                    0 if x >= 0 && x < len => {
+                        if write && code.contains_key(&x) {
+                            return Err(format!("self-modifying write to {} at {}", x, addr));
+                        }
+                        a.data.insert(x);
+                    }
+                    1 if write => return Err(format!("immediate write at {}", addr)),
+                    2 if shift(a.rb[&addr], x).0 < len => {
+                        return Err(format!("relative access at {} may touch the program", addr));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(a)
+    }
+
+    // whether any of an instruction's cells are read or written as data
+    fn is_data(&self, addr: i64) -> bool {
+        let instr = self.instrs[&addr];
+        self.data.range(addr..addr + instr.len).next().is_some()
+    }
+
+    // the address ranges an instruction reads and writes
+    fn accesses(&self, words: &[i64], addr: i64) -> (Vec<Range>, Vec<Range>) {
+        let instr = self.instrs[&addr];
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        for (n, &param) in params(instr.op).expect("decoded").iter().enumerate() {
+            let x = arg(words, addr, n);
+            let r = match instr.modes[n] {
+                0 => (x, x),
+                2 => shift(self.rb[&addr], x),
+                _ => continue,
+            };
+            if param == Param::Write {
+                writes.push(r);
+            } else {
+                reads.push(r);
+            }
+        }
+        (reads, writes)
+    }
+
+    // whether cell t is written before it is read on every path from the
+    // given addresses
+    fn dead(&self, words: &[i64], t: i64, from: &[i64]) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut work = from.to_vec();
+        while let Some(addr) = work.pop() {
+            if !seen.insert(addr) {
+                continue;
+            }
+            let (reads, writes) = self.accesses(words, addr);
+            if reads.iter().any(|r| r.0 <= t && t <= r.1) {
+                return false;
+            }
+            if writes.contains(&(t, t)) {
+                continue;
+            }
+            work.extend(self.succ[&addr].iter());
+        }
+        true
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Stats {
+    // arithmetic and comparisons of immediates
+    pub folded: usize,
+    // jumps retargeted past jumps
+    pub threaded: usize,
+    // Equals with zero and a jump on its result made one jump
+    pub fused: usize,
+    // jumps that go nowhere or are never taken
+    pub jumps_removed: usize,
+    pub words_removed: usize,
+}
+
+impl Stats {
+    fn changed(&self) -> bool {
+        *self != Self::default()
+    }
+
+    fn add(&mut self, o: &Stats) {
+        self.folded += o.folded;
+        self.threaded += o.threaded;
+        self.fused += o.fused;
+        self.jumps_removed += o.jumps_removed;
+        self.words_removed += o.words_removed;
+    }
+}
+
+// rewrites that keep every instruction where it is
+fn rewrite_in_place(words: &mut [i64], a: &Analysis, stats: &mut Stats) {
+    for (&addr, &instr) in a.instrs.iter() {
+        if a.is_data(addr) {
+            continue;
+        }
+        let at = addr as usize;
+        match instr.op {
+            1 | 2 | 7 | 8 if instr.modes[0] == 1 && instr.modes[1] == 1 => {
+                let (x, y) = (words[at + 1], words[at + 2]);
+                let v = match instr.op {
+                    1 => x + y,
+                    2 => x * y,
+                    7 => (x < y) as i64,
+                    _ => (x == y) as i64,
+                };
+                if instr.op == 1 && y == 0 {
+                    continue;
+                }
+                words[at] = 1101 + instr.modes[2] * 10000;
+                words[at + 1] = v;
+                words[at + 2] = 0;
+                stats.folded += 1;
+            }
+            5 | 6 => {
+                let mut target = words[at + 2];
+                let mut seen = BTreeSet::new();
+                while let Some(&next) = a.instrs.get(&target) {
+                    if !is_jump(next.op) || taken(words, target, next) != Some(true) {
+                        break;
+                    }
+                    if !seen.insert(target) {
+                        break;
+                    }
+                    target = arg(words, target, 1);
+                }
+                if target != words[at + 2] {
+                    words[at + 2] = target;
+                    stats.threaded += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Rewrites that drop instructions, then squeezes out those and anything
+// unreachable, moving addresses to match.
+fn rewrite_and_compact(words: &[i64], a: &Analysis, stats: &mut Stats) -> Vec<i64> {
+    let mut words = words.to_vec();
+    let mut deleted = BTreeSet::new();
+    for (&addr, &instr) in a.instrs.iter() {
+        if a.is_data(addr) || deleted.contains(&addr) {
+            continue;
+        }
+        let next = addr + instr.len;
+        if is_jump(instr.op) {
+            let target = arg(&words, addr, 1);
+            if taken(&words, addr, instr) == Some(false) || target == next {
+                deleted.insert(addr);
+                stats.jumps_removed += 1;
+            }
+            continue;
+        }
+
+        // t = x == 0, then a jump on t, where t is not needed after
+        if instr.op != 8 || instr.modes[2] == 1 {
+            continue;
+        }
+        let x = match (
+            instr.modes[0],
+            instr.modes[1],
+            arg(&words, addr, 0),
+            arg(&words, addr, 1),
+        ) {
+            (1, m, 0, x) | (m, 1, x, 0) => (m, x),
+            _ => continue,
+        };
+        let j = match a.instrs.get(&next) {
+            Some(&j) if is_jump(j.op) && j.modes[1] == 1 => j,
+            _ => continue,
+        };
+        if a.targets.contains(&next) || a.is_data(next) {
+            continue;
+        }
+        let t = match a.accesses(&words, addr).1[0] {
+            (t, t2) if t == t2 => t,
+            _ => continue,
+        };
+        // a jump on an immediate reads nothing
+        if a.accesses(&words, next).0.first() != Some(&(t, t)) || !a.dead(&words, t, &a.succ[&next])
+        {
+            continue;
+        }
+        let op = if j.op == 5 { 6 } else { 5 };
+        let n = next as usize;
+        words[n] = op + x.0 * 100 + 1000;
+        words[n + 1] = x.1;
+        deleted.insert(addr);
+        stats.fused += 1;
+    }
+
+    // keep the reachable instructions and the data they use
+    let len = words.len();
+    let mut keep = vec![false; len];
+    for (&addr, instr) in a.instrs.iter() {
+        if !deleted.contains(&addr) {
+            for c in addr..addr + instr.len {
+                keep[c as usize] = true;
+            }
+        }
+    }
+    for &c in a.data.iter() {
+        keep[c as usize] = true;
+    }
+    // a removed cell maps to the next kept one, which for a removed
+    // instruction is where it would have gone next
+    let mut map = vec![0; len + 1];
+    let kept = keep.iter().filter(|&&k| k).count();
+    map[len] = kept as i64;
+    let mut n = kept as i64;
+    for c in (0..len).rev() {
+        if keep[c] {
+            n -= 1;
+        }
+        map[c] = n;
+    }
+
+    let mut out = Vec::with_capacity(kept);
+    for (&addr, &instr) in a.instrs.iter() {
+        if deleted.contains(&addr) {
+            continue;
+        }
+        // rewritten instructions may have new modes
+        let instr = decode(&words, addr).unwrap_or(instr);
+        for i in 0..params(instr.op).expect("decoded").len() {
+            let at = (addr + 1) as usize + i;
+            let x = words[at];
+            let jump_target = is_jump(instr.op) && i == 1;
+            if (instr.modes[i] == 0 || jump_target) && x >= 0 && (x as usize) < len {
+                words[at] = map[x as usize];
+            }
+        }
+    }
+    for c in 0..len {
+        if keep[c] {
+            out.push(words[c]);
+        }
+    }
+    stats.words_removed += len - out.len();
+    out
+}
+
+// Optimize until nothing changes, returning the new program and what was
+// done, or why the program can't be optimized.
+pub fn optimize(words: &[i64]) -> Result<(Vec<i64>, Stats), String> {
+    let mut words = words.to_vec();
+    let mut total = Stats::default();
+    loop {
+        let mut stats = Stats::default();
+        let a = Analysis::new(&words)?;
+        rewrite_in_place(&mut words, &a, &mut stats);
+        let a = Analysis::new(&words)?;
+        words = rewrite_and_compact(&words, &a, &mut stats);
+        if !stats.changed() {
+            return Ok((words, total));
+        }
+        total.add(&stats);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Run {
+    outputs: Vec<i64>,
+    // how it stopped, None if it ran out of steps
+    end: Option<String>,
+}
+
+fn run(words: &[i64], input: &[i64], limit: usize) -> Run {
+    let mut code = Intcode::from_vec(words.to_vec());
+    let mut input: VecDeque<i64> = input.iter().copied().collect();
+    let mut outputs = Vec::new();
+    let mut end = None;
+    let error = unwind::catch(|| {
+        for _ in 0..limit {
+            match code.step_ext(&mut NoExtension, &mut || input.pop_front()) {
+                Err(e) => end = Some(e.to_string()),
+                Ok(Some(ExitMode::Halt)) => end = Some("halt".to_string()),
+                Ok(Some(ExitMode::AwaitingInput)) => end = Some("awaiting input".to_string()),
+                Ok(Some(ExitMode::Output(x))) => outputs.push(x),
+                Ok(None) => (),
+            }
+            if end.is_some() {
+                return;
+            }
+        }
+    });
+    if error.is_some() {
+        end = error;
+    }
+    Run { outputs, end }
+}
+
+// Run both programs on the same random inputs and compare what they
+// output and how they stop. A run that hits the step limit only has to
+// agree on the outputs so far.
+pub fn check(original: &[i64], optimized: &[i64], rounds: usize, seed: u64) -> Result<(), String> {
+    const LIMIT: usize = 100_000;
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..rounds {
+        let count = (rng.gen::<u64>() % 20) as usize;
+        let input: Vec<i64> = (0..count)
+            .map(|_| (rng.gen::<u64>() % 21) as i64 - 10)
+            .collect();
+        let a = run(original, &input, LIMIT);
+        let b = run(optimized, &input, LIMIT);
+        let agree = match (&a.end, &b.end) {
+            (Some(_), Some(_)) => a == b,
+            _ => {
+                let n = a.outputs.len().min(b.outputs.len());
+                a.outputs[..n] == b.outputs[..n]
+            }
+        };
+        if !agree {
+            return Err(format!(
+                "inputs {:?}: original {:?}, optimized {:?}",
+                input, a, b
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang;
+    use crate::program::Program;
+
+    fn words(s: &str) -> Vec<i64> {
+        Program::parse_text(s).unwrap().words
+    }
+
+    fn optimized(s: &str) -> (String, Stats) {
+        let (w, stats) = optimize(&words(s)).unwrap();
+        check(&words(s), &w, 50, 1).unwrap();
+        (Program::new(w).to_text(), stats)
+    }
+
+    #[test]
+    fn test_fold() {
+        let (p, stats) = optimized("1101,2,3,20,1102,3,4,21,1107,1,2,15,4,15,99,0");
+        assert_eq!("1101,5,0,20,1101,12,0,21,1101,1,0,15,4,15,99,0", p);
+        assert_eq!(3, stats.folded);
+    }
+
+    #[test]
+    fn test_thread() {
+        // jump to a jump back to the output, leaving the jumps unreachable
+        // or pointless
+        let (p, stats) = optimized("1105,1,7,104,1,99,0,1105,1,3");
+        assert_eq!("104,1,99", p);
+        assert_eq!(1, stats.threaded);
+        assert_eq!(1, stats.jumps_removed);
+        assert_eq!(7, stats.words_removed);
+
+        // a jump never taken
+        assert_eq!("104,1,99", optimized("1106,1,5,104,1,99").0);
+    }
+
+    #[test]
+    fn test_fuse() {
+        // read x, output 1 unless x is 0
+        let (p, stats) = optimized("3,12,1008,12,0,13,1005,13,11,104,1,99,0,0");
+        assert_eq!("3,8,1006,8,7,104,1,99,0", p);
+        assert_eq!(1, stats.fused);
+
+        // t is output after the jump, so it has to stay; the unused
+        // cells go
+        let (p, stats) = optimized("3,14,1008,14,0,15,1005,15,11,4,15,99,0,0,0,0");
+        assert_eq!(0, stats.fused);
+        assert_eq!("3,12,1008,12,0,13,1005,13,11,4,13,99,0,0", p);
+
+        // the jump doesn't look at t at all
+        let (_, stats) = optimized("3,12,1008,12,0,13,1105,1,11,104,1,99,0,0");
+        assert_eq!(0, stats.fused);
+    }
+
+    #[test]
+    fn test_refuses() {
+        let err = |s| optimize(&words(s)).unwrap_err();
+        assert_eq!("self-modifying write to 3 at 0", err("1,0,0,3,99"));
+        assert_eq!("indirect jump at 0", err("105,1,4,99,99"));
+        assert_eq!(
+            "relative access at 0 may touch the program",
+            err("204,0,99")
+        );
+        assert_eq!("invalid code 98 at 2", err("104,1,98"));
+        assert_eq!("runs off the program at 0", err("104,1"));
+        // relative accesses past the end are fine
+        assert!(optimize(&words("109,10,204,0,99")).is_ok());
+    }
+
+    #[test]
+    fn test_compiled() {
+        let src = "
+            var sum = 0;
+            var x = input();
+            while (x != 0) {
+                if (x > 0 && 1 == 1) { sum = sum + x; } else { sum = sum - 1; }
+                x = input();
+            }
+            output(sum);
+        ";
+        let c = lang::compile(src).unwrap();
+        let (w, stats) = optimize(&c.words).unwrap();
+        assert!(w.len() < c.words.len());
+        assert!(stats.fused > 0 && stats.folded > 0, "{:?}", stats);
+        check(&c.words, &w, 200, 2).unwrap();
+
+        // array elements are reached by self-modifying code
+        let c = lang::compile("var a[2]; var i = input(); a[i] = 1; output(a[i]);").unwrap();
+        assert!(optimize(&c.words)
+            .unwrap_err()
+            .starts_with("self-modifying write"));
+    }
+
+    #[test]
+    fn test_check() {
+        let a = words("3,5,4,5,99,0");
+        let b = words("3,5,104,1,99,0");
+        assert!(check(&a, &a, 20, 3).is_ok());
+        assert!(check(&a, &b, 20, 3).is_err());
+    }
+}
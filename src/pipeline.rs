@@ -0,0 +1,174 @@
+use crate::intcode::ExitMode;
+use crate::intcode::Intcode;
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+struct Machine {
+    code: Intcode,
+    queue: VecDeque<i64>,
+    halted: bool,
+}
+
+// an edge carries every output of `from` to the input queue of `to`, or
+// out of the pipeline when `to` is None
+#[derive(Debug)]
+struct Edge {
+    from: usize,
+    to: Option<usize>,
+    values: Vec<i64>,
+}
+
+// Intcode machines wired together by queues. Outputs are copied along
+// every edge leaving a machine (fan-out) and edges from several machines
+// can feed the same input queue (fan-in).
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    machines: Vec<Machine>,
+    edges: Vec<Edge>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add a machine, returning its id
+    pub fn add(&mut self, code: &Intcode) -> usize {
+        self.machines.push(Machine {
+            code: code.clone(),
+            queue: VecDeque::new(),
+            halted: false,
+        });
+        self.machines.len() - 1
+    }
+
+    // connect the output of one machine to the input of another,
+    // returning the edge id
+    pub fn connect(&mut self, from: usize, to: usize) -> usize {
+        self.edges.push(Edge {
+            from,
+            to: Some(to),
+            values: Vec::new(),
+        });
+        self.edges.len() - 1
+    }
+
+    // an edge taking the output of a machine out of the pipeline
+    pub fn sink(&mut self, from: usize) -> usize {
+        self.edges.push(Edge {
+            from,
+            to: None,
+            values: Vec::new(),
+        });
+        self.edges.len() - 1
+    }
+
+    // queue initial input for a machine, e.g. a phase setting
+    pub fn seed(&mut self, id: usize, input: &[i64]) {
+        self.machines[id].queue.extend(input);
+    }
+
+    // every value sent along an edge
+    pub fn values(&self, edge: usize) -> &[i64] {
+        &self.edges[edge].values
+    }
+
+    pub fn halted(&self) -> bool {
+        self.machines.iter().all(|m| m.halted)
+    }
+
+    fn send(&mut self, from: usize, x: i64) {
+        for e in self.edges.iter_mut().filter(|e| e.from == from) {
+            e.values.push(x);
+            if let Some(to) = e.to {
+                self.machines[to].queue.push_back(x);
+            }
+        }
+    }
+
+    // Run the machines in turn until nothing can make progress: every
+    // machine has halted or is waiting on an empty queue.
+    pub fn run(&mut self) {
+        loop {
+            for i in 0..self.machines.len() {
+                if self.machines[i].halted {
+                    continue;
+                }
+                loop {
+                    let m = &mut self.machines[i];
+                    match m.code.run_queue(&mut m.queue) {
+                        ExitMode::Halt => {
+                            m.halted = true;
+                            break;
+                        }
+                        ExitMode::Output(x) => self.send(i, x),
+                        ExitMode::AwaitingInput => break,
+                    }
+                }
+            }
+
+            if self.machines.iter().all(|m| m.halted || m.queue.is_empty()) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE: &str = "3,9,102,2,9,9,4,9,99,0";
+    const ADD: &str = "3,11,3,12,1,11,12,13,4,13,99,0,0,0";
+
+    #[test]
+    fn test_chain() {
+        let double = Intcode::new(DOUBLE);
+        let mut p = Pipeline::new();
+        let ids: Vec<usize> = (0..4).map(|_| p.add(&double)).collect();
+        for w in ids.windows(2) {
+            p.connect(w[0], w[1]);
+        }
+        let out = p.sink(ids[3]);
+        p.seed(ids[0], &[3]);
+        p.run();
+        assert!(p.halted());
+        assert_eq!(&[48], p.values(out));
+    }
+
+    #[test]
+    fn test_fan_out_fan_in() {
+        let double = Intcode::new(DOUBLE);
+        let add = Intcode::new(ADD);
+        let mut p = Pipeline::new();
+        let a = p.add(&double);
+        let b = p.add(&double);
+        let c = p.add(&double);
+        let d = p.add(&add);
+        let ab = p.connect(a, b);
+        p.connect(a, c);
+        p.connect(b, d);
+        p.connect(c, d);
+        let out = p.sink(d);
+        p.seed(a, &[5]);
+        p.run();
+        assert_eq!(&[10], p.values(ab));
+        assert_eq!(&[40], p.values(out));
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // the adder only gets one of its two inputs
+        let double = Intcode::new(DOUBLE);
+        let add = Intcode::new(ADD);
+        let mut p = Pipeline::new();
+        let a = p.add(&double);
+        let b = p.add(&add);
+        p.connect(a, b);
+        let out = p.sink(b);
+        p.seed(a, &[1]);
+        p.run();
+        assert!(!p.halted());
+        assert!(p.values(out).is_empty());
+    }
+}
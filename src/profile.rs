@@ -0,0 +1,200 @@
+use crate::intcode::{Extension, Intcode, Param};
+use crate::stack::CallStack;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+// A function call, timed in instructions executed
+#[derive(PartialEq, Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    pub start: u64,
+    pub dur: u64,
+    // 1 for the outermost frame
+    pub depth: usize,
+}
+
+// Profiles a run by function, with functions inferred from the relative
+// base as in the debugger. Run a machine with this as its extension, then
+// call finish.
+#[derive(Debug, Default)]
+pub struct Profile {
+    // names for function entry addresses, fn@<entry> otherwise
+    pub names: HashMap<i64, String>,
+    stack: CallStack,
+    last: Option<i64>,
+    ticks: u64,
+    // the start of each open frame
+    open: Vec<u64>,
+    pub spans: Vec<Span>,
+    // instructions executed under each stack, names joined by ';'
+    pub samples: BTreeMap<String, u64>,
+}
+
+impl Extension for Profile {
+    fn params(&self, _code: i64) -> Option<Vec<Param>> {
+        None
+    }
+
+    fn exec(&mut self, code: i64, _args: &[i64]) -> Vec<i64> {
+        panic!("invalid code {}", code)
+    }
+
+    fn step(&mut self, code: &Intcode) {
+        self.update(code);
+        let stack = self.path();
+        *self.samples.entry(stack).or_default() += 1;
+        self.last = Some(code.pc());
+        self.ticks += 1;
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn name(&self, entry: i64) -> String {
+        match self.names.get(&entry) {
+            Some(n) => n.clone(),
+            None => format!("fn@{}", entry),
+        }
+    }
+
+    fn path(&self) -> String {
+        let mut s = "main".to_string();
+        for f in self.stack.frames.iter() {
+            s.push(';');
+            s.push_str(&self.name(f.entry));
+        }
+        s
+    }
+
+    // account for the relative base set by the last instruction
+    fn update(&mut self, code: &Intcode) {
+        let last = match self.last.take() {
+            Some(l) => l,
+            None => return,
+        };
+        let before = self.stack.frames.clone();
+        self.stack.step(last, code);
+        let depth = self.stack.depth();
+        for (d, f) in before.iter().enumerate().skip(depth).rev() {
+            let start = self.open.pop().expect("open frame");
+            self.spans.push(Span {
+                name: self.name(f.entry),
+                start,
+                dur: self.ticks - start,
+                depth: d + 1,
+            });
+        }
+        if depth > before.len() {
+            self.open.push(self.ticks);
+        }
+    }
+
+    // close the frames still open when the machine stopped
+    pub fn finish(&mut self, code: &Intcode) {
+        self.update(code);
+        while let Some(f) = self.stack.frames.pop() {
+            let start = self.open.pop().expect("open frame");
+            self.spans.push(Span {
+                name: self.name(f.entry),
+                start,
+                dur: self.ticks - start,
+                depth: self.stack.depth() + 1,
+            });
+        }
+        self.spans.sort_by_key(|s| (s.start, s.depth));
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.ticks
+    }
+
+    // trace event JSON for chrome://tracing or Perfetto, one microsecond
+    // per instruction
+    pub fn chrome_trace(&self) -> String {
+        let events: Vec<_> = self
+            .spans
+            .iter()
+            .map(|s| {
+                json!({
+                    "name": s.name,
+                    "ph": "X",
+                    "ts": s.start,
+                    "dur": s.dur,
+                    "pid": 1,
+                    "tid": 1,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events }).to_string()
+    }
+
+    // "main;f;g count" lines, as read by flamegraph.pl and inferno
+    pub fn folded(&self) -> String {
+        self.samples
+            .iter()
+            .map(|(stack, n)| format!("{} {}\n", stack, n))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::ExitMode;
+
+    fn profile(code: &mut Intcode) -> Profile {
+        let mut p = Profile::new();
+        while let Ok(ExitMode::Output(_)) = code.run_ext(&mut p, || None) {}
+        p.finish(code);
+        p
+    }
+
+    #[test]
+    fn test_profile() {
+        // set up a stack, call a function that outputs 2, output 1 on
+        // return
+        let mut code =
+            Intcode::new("109,100,21101,0,9,0,1105,1,12,104,1,99,109,1,104,2,109,-1,2105,1,0");
+        let mut p = profile(&mut code);
+        assert_eq!(9, p.instructions());
+        assert_eq!("main 1\nmain;fn@0 6\nmain;fn@0;fn@12 2\n", p.folded());
+        let span = |name: &str, start, dur, depth| Span {
+            name: name.to_string(),
+            start,
+            dur,
+            depth,
+        };
+        assert_eq!(vec![span("fn@0", 1, 8, 1), span("fn@12", 4, 2, 2)], p.spans);
+
+        let trace: serde_json::Value = serde_json::from_str(&p.chrome_trace()).unwrap();
+        assert_eq!("fn@12", trace["traceEvents"][1]["name"]);
+        assert_eq!("X", trace["traceEvents"][1]["ph"]);
+        assert_eq!(4, trace["traceEvents"][1]["ts"]);
+
+        let mut code =
+            Intcode::new("109,100,21101,0,9,0,1105,1,12,104,1,99,109,1,104,2,109,-1,2105,1,0");
+        p = Profile::new();
+        p.names.insert(12, "f".to_string());
+        while let Ok(ExitMode::Output(_)) = code.run_ext(&mut p, || None) {}
+        p.finish(&code);
+        assert!(p.folded().contains("main;fn@0;f 2\n"));
+    }
+
+    #[test]
+    fn test_nested() {
+        let src = "fn g() { return 1; } fn f() { return g() + g(); } output(f());";
+        let mut code = Intcode::from_vec(crate::lang::compile(src).unwrap().words);
+        let p = profile(&mut code);
+        let depths: Vec<usize> = p.spans.iter().map(|s| s.depth).collect();
+        assert_eq!(vec![1, 2, 3, 3], depths);
+        let (f, g) = (&p.spans[1], &p.spans[2]);
+        assert!(f.start < g.start && g.start + g.dur <= f.start + f.dur);
+        assert_eq!(p.spans[2].name, p.spans[3].name);
+        let total: u64 = p.samples.values().sum();
+        assert_eq!(p.instructions(), total);
+    }
+}
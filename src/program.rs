@@ -0,0 +1,344 @@
+use crate::intcode::Intcode;
+use std::fs;
+use std::io;
+
+// Binary program container, version 1. All integers are LEB128 varints,
+// words are zigzag encoded first so small negative values stay short.
+//
+//   "ICB" version:u8 flags:u8
+//   [metadata fields: tag:u8 len bytes..., ending with tag 0] if flags & 1
+//   count words...
+//   checksum:u32le  (FNV-1a of everything before it)
+const MAGIC: &[u8] = b"ICB";
+const VERSION: u8 = 1;
+const FLAG_METADATA: u8 = 1;
+
+const TAG_END: u8 = 0;
+const TAG_NAME: u8 = 1;
+const TAG_PUZZLE: u8 = 2;
+const TAG_WORD_SIZE: u8 = 3;
+const TAG_NOTE: u8 = 4;
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    // e.g. "day23"
+    pub puzzle: Option<String>,
+    // the word size in bits the program expects
+    pub word_size: Option<u64>,
+    // notes on entry points, patched addresses and the like
+    pub notes: Vec<String>,
+}
+
+impl Metadata {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Program {
+    pub words: Vec<i64>,
+    pub meta: Metadata,
+}
+
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |h: u32, &b| {
+        (h ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn put_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn put_bytes(out: &mut Vec<u8>, tag: u8, b: &[u8]) {
+    out.push(tag);
+    put_varint(out, b.len() as u64);
+    out.extend(b);
+}
+
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+fn unzigzag(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| format!("unexpected end at byte {}", self.pos))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut x = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            // the tenth byte only has room for the top bit
+            if shift == 63 && b > 1 {
+                return Err(format!("varint too big at byte {}", self.pos));
+            }
+            x |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.pos))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.varint()? as usize;
+        if len > self.data.len() - self.pos {
+            return Err(format!("field too long at byte {}", self.pos));
+        }
+        let b = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(b)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let b = self.bytes()?;
+        String::from_utf8(b.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl Program {
+    pub fn new(words: Vec<i64>) -> Self {
+        Self {
+            words,
+            meta: Metadata::default(),
+        }
+    }
+
+    pub fn intcode(&self) -> Intcode {
+        Intcode::from_vec(self.words.clone())
+    }
+
+    pub fn to_text(&self) -> String {
+        let s: Vec<String> = self.words.iter().map(|x| x.to_string()).collect();
+        s.join(",")
+    }
+
+    pub fn parse_text(s: &str) -> Result<Self, String> {
+        let words = s
+            .trim()
+            .split(',')
+            .enumerate()
+            .map(|(i, x)| {
+                x.trim()
+                    .parse()
+                    .map_err(|e| format!("word {}: {}: {:?}", i, e, x))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(words))
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+
+        if self.meta.is_empty() {
+            out.push(0);
+        } else {
+            out.push(FLAG_METADATA);
+            let m = &self.meta;
+            if let Some(name) = &m.name {
+                put_bytes(&mut out, TAG_NAME, name.as_bytes());
+            }
+            if let Some(puzzle) = &m.puzzle {
+                put_bytes(&mut out, TAG_PUZZLE, puzzle.as_bytes());
+            }
+            if let Some(size) = m.word_size {
+                let mut b = Vec::new();
+                put_varint(&mut b, size);
+                put_bytes(&mut out, TAG_WORD_SIZE, &b);
+            }
+            for note in m.notes.iter() {
+                put_bytes(&mut out, TAG_NOTE, note.as_bytes());
+            }
+            out.push(TAG_END);
+        }
+
+        put_varint(&mut out, self.words.len() as u64);
+        for &w in self.words.iter() {
+            put_varint(&mut out, zigzag(w));
+        }
+
+        let sum = fnv1a(&out);
+        out.extend(&sum.to_le_bytes());
+        out
+    }
+
+    pub fn parse_binary(data: &[u8]) -> Result<Self, String> {
+        if !data.starts_with(MAGIC) {
+            return Err("not a binary program".to_string());
+        }
+        if data.len() < MAGIC.len() + 2 + 4 {
+            return Err("binary program too short".to_string());
+        }
+        let (body, sum) = data.split_at(data.len() - 4);
+        let expected = u32::from_le_bytes([sum[0], sum[1], sum[2], sum[3]]);
+        if fnv1a(body) != expected {
+            return Err("checksum mismatch".to_string());
+        }
+
+        let mut r = Reader {
+            data: body,
+            pos: MAGIC.len(),
+        };
+        let version = r.byte()?;
+        if version != VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+
+        let mut meta = Metadata::default();
+        if r.byte()? & FLAG_METADATA != 0 {
+            loop {
+                match r.byte()? {
+                    TAG_END => break,
+                    TAG_NAME => meta.name = Some(r.string()?),
+                    TAG_PUZZLE => meta.puzzle = Some(r.string()?),
+                    TAG_WORD_SIZE => {
+                        let b = r.bytes()?;
+                        meta.word_size = Some(Reader { data: b, pos: 0 }.varint()?);
+                    }
+                    TAG_NOTE => meta.notes.push(r.string()?),
+                    // skip fields from newer writers
+                    _ => {
+                        r.bytes()?;
+                    }
+                }
+            }
+        }
+
+        let count = r.varint()? as usize;
+        let mut words = Vec::with_capacity(count.min(body.len()));
+        for _ in 0..count {
+            words.push(unzigzag(r.varint()?));
+        }
+        if r.pos != body.len() {
+            return Err(format!("{} trailing bytes", body.len() - r.pos));
+        }
+        Ok(Self { words, meta })
+    }
+
+    // binary if it starts with the magic bytes, text otherwise
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.starts_with(MAGIC) {
+            Self::parse_binary(data)
+        } else {
+            let s = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+            Self::parse_text(s)
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str, binary: bool) -> io::Result<()> {
+        if binary {
+            fs::write(path, self.to_binary())
+        } else {
+            fs::write(path, self.to_text())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for &x in [0, 1, -1, 63, -64, 64, 1 << 40, i64::MAX, i64::MIN].iter() {
+            let mut b = Vec::new();
+            put_varint(&mut b, zigzag(x));
+            let mut r = Reader { data: &b, pos: 0 };
+            assert_eq!(x, unzigzag(r.varint().unwrap()));
+        }
+
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let mut r = Reader { data: &max, pos: 0 };
+        assert_eq!(Ok(u64::MAX), r.varint());
+        for last in [0x02, 0x81].iter() {
+            let mut b = max.to_vec();
+            b[9] = *last;
+            let mut r = Reader { data: &b, pos: 0 };
+            assert_eq!(Err("varint too big at byte 10".to_string()), r.varint());
+        }
+    }
+
+    #[test]
+    fn test_binary() {
+        let p = Program::parse_text("1002,4,3,4,33,-1").unwrap();
+        let b = p.to_binary();
+        assert_eq!(
+            vec![b'I', b'C', b'B', 1, 0, 6, 0xd4, 0x0f, 8, 6, 8, 66, 1],
+            b[..b.len() - 4].to_vec()
+        );
+        assert_eq!(Ok(p), Program::parse_binary(&b));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut p = Program::new(vec![104, -5, 99]);
+        p.meta = Metadata {
+            name: Some("negative".to_string()),
+            puzzle: Some("day9".to_string()),
+            word_size: Some(64),
+            notes: vec!["entry 0".to_string(), "halts at 2".to_string()],
+        };
+        assert_eq!(Ok(p.clone()), Program::parse(&p.to_binary()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let p = Program::new(vec![1, 2, 3]);
+        let mut b = p.to_binary();
+        b[6] ^= 1;
+        assert_eq!(
+            Err("checksum mismatch".to_string()),
+            Program::parse_binary(&b)
+        );
+
+        let mut b = p.to_binary();
+        b[3] = 2;
+        let n = b.len() - 4;
+        let sum = fnv1a(&b[..n]).to_le_bytes();
+        b[n..].copy_from_slice(&sum);
+        assert_eq!(
+            Err("unsupported version 2".to_string()),
+            Program::parse_binary(&b)
+        );
+
+        assert_eq!(
+            Err("word 1: invalid digit found in string: \"x\"".to_string()),
+            Program::parse(b"1,x,3")
+        );
+    }
+
+    #[test]
+    fn test_parse_detects_format() {
+        let p = Program::parse(b"3,0,4,0,99\n").unwrap();
+        assert_eq!(Ok(p.clone()), Program::parse(&p.to_binary()));
+        assert_eq!("3,0,4,0,99", p.to_text());
+        assert_eq!("3,0,4,0,99", p.intcode().to_string());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::device::{Bus, Device};
+use crate::intcode::{Error, ExitMode, Intcode, NoExtension};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Event {
+    Input(i64),
+    Output(i64),
+    Halt,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Input(x) => write!(f, "in {}", x),
+            Event::Output(x) => write!(f, "out {}", x),
+            Event::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+// The IO of a session, each event with the number of instructions
+// executed before it: an input is consumed by the next instruction, an
+// output or halt was the last one.
+//
+//   # intcode session
+//   0 in 5
+//   2 out 10
+//   3 halt
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Log {
+    pub events: Vec<(u64, Event)>,
+}
+
+impl Log {
+    pub fn to_text(&self) -> String {
+        let mut s = "# intcode session\n".to_string();
+        for (n, e) in self.events.iter() {
+            s.push_str(&format!("{} {}\n", n, e));
+        }
+        s
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut log = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = || format!("line {}: bad event {:?}", i + 1, line);
+            let v: Vec<&str> = line.split_whitespace().collect();
+            let n = v[0].parse().map_err(|_| bad())?;
+            let value = |s: &str| s.parse().map_err(|_| bad());
+            let e = match v[1..] {
+                ["in", x] => Event::Input(value(x)?),
+                ["out", x] => Event::Output(value(x)?),
+                ["halt"] => Event::Halt,
+                _ => return Err(bad()),
+            };
+            log.events.push((n, e));
+        }
+        Ok(log)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&s).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// Execute one instruction, adding any input it consumed and any output or
+// halt to events. Waiting for input executes nothing.
+fn step<F>(
+    code: &mut Intcode,
+    steps: &mut u64,
+    events: &mut Vec<(u64, Event)>,
+    mut input: F,
+) -> Result<Option<ExitMode>, Error>
+where
+    F: FnMut() -> Option<i64>,
+{
+    let mut consumed = None;
+    let exit = code.step_ext(&mut NoExtension, &mut || {
+        consumed = input();
+        consumed
+    })?;
+    if exit == Some(ExitMode::AwaitingInput) {
+        return Ok(exit);
+    }
+    if let Some(x) = consumed {
+        events.push((*steps, Event::Input(x)));
+    }
+    *steps += 1;
+    match exit {
+        Some(ExitMode::Output(x)) => events.push((*steps, Event::Output(x))),
+        Some(ExitMode::Halt) => events.push((*steps, Event::Halt)),
+        _ => (),
+    }
+    Ok(exit)
+}
+
+// Runs a machine against a device, as on a bus, logging the IO.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    pub log: Log,
+    steps: u64,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // run until the machine halts or the device has no input to give
+    pub fn run(&mut self, code: &mut Intcode, device: &mut dyn Device) -> Result<ExitMode, Error> {
+        loop {
+            match step(code, &mut self.steps, &mut self.log.events, || {
+                device.input()
+            })? {
+                Some(ExitMode::Output(x)) => device.output(x),
+                Some(exit) => return Ok(exit),
+                None => (),
+            }
+        }
+    }
+}
+
+// run against a device on a bus, logging the IO if there is a recorder
+pub fn run(
+    code: &mut Intcode,
+    device: &mut dyn Device,
+    recorder: Option<&mut Recorder>,
+) -> Result<ExitMode, Error> {
+    match recorder {
+        Some(r) => r.run(code, device),
+        None => {
+            let mut bus = Bus::new();
+            bus.attach(device);
+            bus.run(code)
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Divergence {
+    // the index of the first event that differs
+    pub index: usize,
+    pub expected: Option<(u64, Event)>,
+    pub got: Option<(u64, Event)>,
+    // an error from the machine, or the instruction count when an
+    // expected event failed to happen
+    pub note: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |e: &Option<(u64, Event)>| match e {
+            Some((n, e)) => format!("{} at instruction {}", e, n),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "event {}: expected {}, got {}",
+            self.index,
+            show(&self.expected),
+            show(&self.got)
+        )?;
+        if !self.note.is_empty() {
+            write!(f, " ({})", self.note)?;
+        }
+        Ok(())
+    }
+}
+
+// Feed a machine the inputs of a log and check it does exactly what the
+// log says, returning the first difference.
+pub fn replay(code: &mut Intcode, log: &Log) -> Result<(), Divergence> {
+    let mut inputs: VecDeque<i64> = log
+        .events
+        .iter()
+        .filter_map(|e| match e.1 {
+            Event::Input(x) => Some(x),
+            _ => None,
+        })
+        .collect();
+    // how far a machine that has done everything in the log may run on
+    // before it is taken to be lost
+    let last = log.events.last().map(|e| e.0).unwrap_or(0);
+    let limit = last * 2 + 1_000_000;
+
+    let mut steps = 0;
+    let mut got = Vec::new();
+    let diverge = |index: usize, got: Option<(u64, Event)>, note: String| Divergence {
+        index,
+        expected: log.events.get(index).copied(),
+        got,
+        note,
+    };
+    loop {
+        let n = got.len();
+        let exit = step(code, &mut steps, &mut got, || inputs.pop_front())
+            .map_err(|e| diverge(n, None, e.to_string()))?;
+        for (i, &e) in got.iter().enumerate().skip(n) {
+            if log.events.get(i) != Some(&e) {
+                return Err(diverge(i, Some(e), String::new()));
+            }
+        }
+        let next = log.events.get(got.len());
+        match exit {
+            Some(ExitMode::Halt) | Some(ExitMode::AwaitingInput) => {
+                return match next {
+                    Some(_) => Err(diverge(got.len(), None, "stopped".to_string())),
+                    None => Ok(()),
+                };
+            }
+            _ => (),
+        }
+        let late = match next {
+            Some(&(at, _)) => steps > at,
+            None => steps > limit,
+        };
+        if late {
+            let note = format!("still running at instruction {}", steps);
+            return Err(diverge(got.len(), None, note));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Script {
+        input: VecDeque<i64>,
+        output: Vec<i64>,
+    }
+
+    impl Device for Script {
+        fn input(&mut self) -> Option<i64> {
+            self.input.pop_front()
+        }
+
+        fn output(&mut self, value: i64) {
+            self.output.push(value)
+        }
+    }
+
+    // output the sum of each pair of inputs until a pair sums to 0
+    const SUM: &str = "3,100,3,101,1,100,101,102,4,102,1005,102,0,99";
+
+    fn record(src: &str, input: &[i64]) -> Log {
+        let mut script = Script {
+            input: input.iter().copied().collect(),
+            ..Script::default()
+        };
+        let mut r = Recorder::new();
+        r.run(&mut Intcode::new(src), &mut script).unwrap();
+        r.log
+    }
+
+    #[test]
+    fn test_record() {
+        let log = record(SUM, &[1, 2, 3, -3]);
+        let expected = "# intcode session
+0 in 1
+1 in 2
+4 out 3
+5 in 3
+6 in -3
+9 out 0
+11 halt
+";
+        assert_eq!(expected, log.to_text());
+        assert_eq!(Ok(log.clone()), Log::parse(&log.to_text()));
+        assert_eq!(
+            Err("line 2: bad event \"1 sideways 3\"".to_string()),
+            Log::parse("# intcode session\n1 sideways 3\n")
+        );
+
+        // running out of input ends the session without a halt
+        let log = record(SUM, &[1, 2, 3]);
+        assert_eq!(Some(&(5, Event::Input(3))), log.events.last());
+    }
+
+    #[test]
+    fn test_replay() {
+        let log = record(SUM, &[1, 2, 3, -3]);
+        assert_eq!(Ok(()), replay(&mut Intcode::new(SUM), &log));
+
+        // multiplies instead
+        let d = replay(
+            &mut Intcode::new("3,100,3,101,2,100,101,102,4,102,1005,102,0,99"),
+            &log,
+        )
+        .unwrap_err();
+        assert_eq!(
+            "event 2: expected out 3 at instruction 4, got out 2 at instruction 4",
+            d.to_string()
+        );
+
+        // reads the second input a step later
+        let d = replay(
+            &mut Intcode::new("3,100,1101,0,0,103,3,101,1,100,101,102,4,102,1005,102,0,99"),
+            &log,
+        )
+        .unwrap_err();
+        assert_eq!(1, d.index);
+        assert_eq!(None, d.got);
+        assert_eq!("still running at instruction 2", d.note);
+
+        // halts early
+        let d = replay(
+            &mut Intcode::new("3,100,3,101,1,100,101,102,4,102,99"),
+            &log,
+        )
+        .unwrap_err();
+        assert_eq!(
+            "event 3: expected in 3 at instruction 5, got halt at instruction 5",
+            d.to_string()
+        );
+
+        // never outputs
+        let d = replay(&mut Intcode::new("3,100,3,101,1105,1,4"), &log).unwrap_err();
+        assert_eq!("event 2: expected out 3 at instruction 4, got nothing (still running at instruction 5)", d.to_string());
+    }
+}
@@ -0,0 +1,907 @@
+use crate::bench;
+use crate::bench::{Config, History, Report, Run, HISTORY};
+use crate::coverage::Coverage;
+use crate::dap;
+use crate::day7::{Circuit, Optimizer};
+use crate::device;
+use crate::fuzz;
+use crate::fuzz::Fuzzer;
+use crate::gdb::Stub;
+use crate::input;
+use crate::input::{ParseError, Source};
+use crate::intcode::{Error, ExitMode, Extension, Intcode, Taint};
+use crate::lang;
+use crate::optimize;
+use crate::profile::Profile;
+use crate::program::Program;
+use crate::record::Log;
+use crate::server::{Mode, Server};
+use crate::solution::{load, Answer, Solution};
+use crate::verify;
+use crate::verify::{record, Answers, Status, Table, ANSWERS};
+use crate::{
+    day1, day10, day11, day12, day13, day14, day15, day16, day17, day19, day2, day21, day22, day23,
+    day24, day25, day3, day4, day5, day6, day7, day8, day9,
+};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "usage:
+  run <day> <part> [--input FILE|-]
+  run --all
+  verify [--record]
+  bench [DAY...] [--threshold PERCENT]
+  record <day> FILE
+  replay <day> FILE
+  phases [--feedback] [--repeat] [--top N]
+  play [--record FILE]
+  capture FILE
+  fuzz [COUNT] [--seed N]
+  compile SOURCE OUTPUT [--binary]
+  optimize FILE OUTPUT [--binary]
+  exec FILE [--input FILE|-]
+  coverage FILE [--input FILE|-] [--ascii] [--lcov]
+  profile FILE [--input FILE|-] [--ascii] [--folded]
+  taint FILE [--input FILE|-] [--ascii]
+  dump FILE [--input FILE|-] [--ascii]
+  serve FILE ADDRESS [--lines]
+  gdb FILE ADDRESS
+  dap
+  list";
+
+// A solved day, with how many of its parts are done.
+pub struct Day {
+    pub day: u32,
+    pub parts: u32,
+    pub load: fn(&str) -> Result<Box<dyn Solution>, ParseError>,
+}
+
+impl Day {
+    pub fn parts(&self) -> Vec<u32> {
+        (1..=self.parts).collect()
+    }
+
+    // the input checked in next to the sources
+    pub fn input_file(&self) -> String {
+        format!("day{}.txt", self.day)
+    }
+
+    // the checked in input, parsed
+    pub fn solution(&self) -> Result<Box<dyn Solution>, String> {
+        let data = read_input(self, &Input::Default)?;
+        (self.load)(&data).map_err(|e| format!("{}: {}", self.input_file(), e))
+    }
+}
+
+pub fn solve(solution: &dyn Solution, part: u32) -> Result<Answer, String> {
+    match part {
+        1 => solution.part1(),
+        _ => solution.part2(),
+    }
+}
+
+pub const DAYS: &[Day] = &[
+    Day {
+        day: 1,
+        parts: 2,
+        load: load::<day1::Day1>,
+    },
+    Day {
+        day: 2,
+        parts: 2,
+        load: load::<day2::Day2>,
+    },
+    Day {
+        day: 3,
+        parts: 2,
+        load: load::<day3::Day3>,
+    },
+    Day {
+        day: 4,
+        parts: 2,
+        load: load::<day4::Day4>,
+    },
+    Day {
+        day: 5,
+        parts: 2,
+        load: load::<day5::Day5>,
+    },
+    Day {
+        day: 6,
+        parts: 2,
+        load: load::<day6::Day6>,
+    },
+    Day {
+        day: 7,
+        parts: 2,
+        load: load::<day7::Day7>,
+    },
+    Day {
+        day: 8,
+        parts: 2,
+        load: load::<day8::Day8>,
+    },
+    Day {
+        day: 9,
+        parts: 2,
+        load: load::<day9::Day9>,
+    },
+    Day {
+        day: 10,
+        parts: 2,
+        load: load::<day10::Day10>,
+    },
+    Day {
+        day: 11,
+        parts: 2,
+        load: load::<day11::Day11>,
+    },
+    Day {
+        day: 12,
+        parts: 2,
+        load: load::<day12::Day12>,
+    },
+    Day {
+        day: 13,
+        parts: 2,
+        load: load::<day13::Day13>,
+    },
+    Day {
+        day: 14,
+        parts: 2,
+        load: load::<day14::Day14>,
+    },
+    Day {
+        day: 15,
+        parts: 2,
+        load: load::<day15::Day15>,
+    },
+    // the real signal of part 2 is too long for day16
+    Day {
+        day: 16,
+        parts: 1,
+        load: load::<day16::Day16>,
+    },
+    Day {
+        day: 17,
+        parts: 2,
+        load: load::<day17::Day17>,
+    },
+    Day {
+        day: 19,
+        parts: 2,
+        load: load::<day19::Day19>,
+    },
+    Day {
+        day: 21,
+        parts: 2,
+        load: load::<day21::Day21>,
+    },
+    Day {
+        day: 22,
+        parts: 2,
+        load: load::<day22::Day22>,
+    },
+    Day {
+        day: 23,
+        parts: 2,
+        load: load::<day23::Day23>,
+    },
+    Day {
+        day: 24,
+        parts: 2,
+        load: load::<day24::Day24>,
+    },
+    // day 25 has no second puzzle
+    Day {
+        day: 25,
+        parts: 1,
+        load: load::<day25::Day25>,
+    },
+];
+
+pub fn find(day: u32) -> Option<&'static Day> {
+    DAYS.iter().find(|d| d.day == day)
+}
+
+// where to read the puzzle input from
+#[derive(PartialEq, Debug, Clone)]
+pub enum Input {
+    // the day's own input file
+    Default,
+    File(String),
+    Stdin,
+}
+
+fn source(day: &Day, input: &Input) -> Source {
+    match input {
+        Input::Default => Source::File(day.input_file()),
+        Input::File(path) => Source::File(path.to_string()),
+        Input::Stdin => Source::Stdin,
+    }
+}
+
+pub fn read_input(day: &Day, input: &Input) -> Result<String, String> {
+    source(day, input).read()
+}
+
+// an Intcode program to run, serve or debug, as text or binary, or a
+// dump of a machine to carry on from
+fn program(path: &str) -> Result<Intcode, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let code = if data.starts_with(b"pc ") {
+        String::from_utf8(data)
+            .map_err(|e| e.to_string())
+            .and_then(|s| Intcode::load_dump(&s))
+    } else {
+        Program::parse(&data).map(|p| p.intcode())
+    };
+    code.map_err(|e| format!("{}: {}", path, e))
+}
+
+// where a program's input comes from, by default nothing at all
+fn program_source(input: &Input) -> Source {
+    match input {
+        Input::Default => Source::Embedded(""),
+        Input::File(path) => Source::File(path.to_string()),
+        Input::Stdin => Source::Stdin,
+    }
+}
+
+// the values to feed a program: comma separated integers, or the text
+// itself as ASCII
+fn values(from: &Input, ascii: bool) -> Result<Vec<i64>, String> {
+    let source = program_source(from);
+    let data = source.read()?;
+    if ascii {
+        return Ok(data.chars().map(|c| c as i64).collect());
+    }
+    if data.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    input::list(&data, ",").map_err(|e| format!("{}: {}", source, e))
+}
+
+// run until the machine halts or runs out of input, ignoring its output
+fn run_ext<E: Extension>(
+    code: &mut Intcode,
+    ext: &mut E,
+    input: &mut VecDeque<i64>,
+) -> Result<(), Error> {
+    while let ExitMode::Output(_) = code.run_ext(ext, || input.pop_front())? {}
+    Ok(())
+}
+
+// what to report on a run of a program
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Tool {
+    // an annotated listing
+    Coverage,
+    Lcov,
+    // trace events for chrome://tracing
+    Profile,
+    // stacks for flamegraphs
+    Folded,
+    // the inputs each output depends on
+    Taint,
+    // the machine once it stops
+    Dump,
+}
+
+// run a program with a tool watching it and return the tool's report
+fn report(tool: Tool, path: &str, input: &[i64]) -> Result<String, String> {
+    let mut code = program(path)?;
+    let start = code.clone();
+    let mut input: VecDeque<i64> = input.iter().copied().collect();
+    let err = |e: Error| format!("{}: {}", path, e);
+    match tool {
+        Tool::Coverage | Tool::Lcov => {
+            let mut c = Coverage::new();
+            run_ext(&mut code, &mut c, &mut input).map_err(err)?;
+            if tool == Tool::Lcov {
+                return Ok(c.lcov(&start, path));
+            }
+            let mut s = c.listing(&start);
+            let data: Vec<String> = c.data_only(&start).iter().map(|a| a.to_string()).collect();
+            writeln!(s, "data only: {}", data.join(", ")).unwrap();
+            Ok(s)
+        }
+        Tool::Profile | Tool::Folded => {
+            let mut p = Profile::new();
+            run_ext(&mut code, &mut p, &mut input).map_err(err)?;
+            p.finish(&code);
+            eprintln!("{} instructions", p.instructions());
+            Ok(match tool {
+                Tool::Folded => p.folded(),
+                _ => p.chrome_trace(),
+            })
+        }
+        Tool::Taint => {
+            let mut taint = Taint::new();
+            let mut s = String::new();
+            while let ExitMode::Output(x) = code.run_taint(&mut taint, &mut input) {
+                let labels = taint.outputs.last().cloned().unwrap_or_default();
+                let from: Vec<String> = labels.iter().map(|i| i.to_string()).collect();
+                writeln!(s, "{} from inputs: {}", x, from.join(", ")).unwrap();
+            }
+            Ok(s)
+        }
+        Tool::Dump => {
+            while let ExitMode::Output(_) = code.run_queue(&mut input) {}
+            Ok(code.dump())
+        }
+    }
+}
+
+pub fn run(day: u32, part: u32, input: &Input) -> Result<Answer, String> {
+    let d = find(day).ok_or_else(|| format!("day {} is not implemented", day))?;
+    if !d.parts().contains(&part) {
+        return Err(format!("day {} has no part {}", day, part));
+    }
+    let data = read_input(d, input)?;
+    let solution = (d.load)(&data).map_err(|e| format!("{}: {}", source(d, input), e))?;
+    solve(solution.as_ref(), part)
+}
+
+// run every part of every day, carrying on past failures. Returns the
+// number that failed.
+pub fn run_all() -> usize {
+    let mut failed = 0;
+    for d in DAYS.iter() {
+        let solution = d.solution();
+        for p in d.parts() {
+            let answer = match &solution {
+                Ok(s) => solve(s.as_ref(), p),
+                Err(e) => Err(e.clone()),
+            };
+            match answer {
+                Ok(a) => println!("day {} part {}: {}", d.day, p, a),
+                Err(e) => {
+                    println!("day {} part {} failed: {}", d.day, p, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    failed
+}
+
+type Record = fn(&str) -> Result<Log, String>;
+type Replay = fn(&str, &Log) -> Result<(), String>;
+
+// the days with a part played interactively, whose IO can be recorded
+// for a bug report and replayed as a check
+fn session(day: u32) -> Result<(&'static Day, Record, Replay), String> {
+    let (record, replay): (Record, Replay) = match day {
+        13 => (day13::record, day13::replay),
+        25 => (day25::record, day25::replay),
+        _ => return Err(format!("day {} has no session to record", day)),
+    };
+    let d = find(day).expect("a registered day");
+    Ok((d, record, replay))
+}
+
+pub fn list() -> String {
+    DAYS.iter()
+        .map(|d| {
+            let parts: Vec<String> = d.parts().iter().map(|p| p.to_string()).collect();
+            format!("day {:2}: part {}\n", d.day, parts.join(", "))
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Debug)]
+enum Command {
+    Run(u32, u32, Input),
+    All,
+    // check against the known answers, saving any new ones with record
+    Verify(bool),
+    // the days to time, all of them if none, and the slowdown to flag
+    Bench(Vec<u32>, f64),
+    // a day's session and the file it goes in
+    Record(u32, String),
+    Replay(u32, String),
+    // a program and where to listen, a TCP address or else a unix socket
+    Serve(String, String, Mode),
+    // a program and the TCP address to wait for gdb on
+    Gdb(String, String),
+    // a debug adapter on stdin and stdout
+    Dap,
+    // day 7's phase settings ranked by output: the circuit, whether a
+    // phase can be used more than once and how many to show
+    Phases(Circuit, bool, usize),
+    // the day 25 game, carrying on from stdin once the script runs out,
+    // and the file to record the session in
+    Play(Option<String>),
+    // the file to capture the day 23 network's part 2 run in
+    Capture(String),
+    // how many random programs to try, and the seed
+    Fuzz(usize, u64),
+    // a source or program file, where to write the result and whether
+    // it's binary
+    Compile(String, String, bool),
+    Optimize(String, String, bool),
+    // a program run at the console, typing in its input
+    Exec(String, Input),
+    // a program run with a tool watching, its input and whether the input
+    // is ASCII text
+    Tool(Tool, String, Input, bool),
+    List,
+}
+
+fn number<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse()
+        .map_err(|_| format!("bad number {:?}\n{}", s, USAGE))
+}
+
+// a program's input, from a file or - for stdin
+fn input_arg(arg: Option<&&str>) -> Result<Input, String> {
+    match arg {
+        Some(&"-") => Ok(Input::Stdin),
+        Some(path) => Ok(Input::File(path.to_string())),
+        None => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    match args[..] {
+        ["list"] => Ok(Command::List),
+        ["phases", ref rest @ ..] => {
+            let mut circuit = Circuit::Chain;
+            let mut repeat = false;
+            let mut top = 10;
+            let mut rest = rest.iter();
+            while let Some(&a) = rest.next() {
+                match a {
+                    "--feedback" => circuit = Circuit::Feedback,
+                    "--repeat" => repeat = true,
+                    "--top" => top = number(rest.next().ok_or_else(|| USAGE.to_string())?)?,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            Ok(Command::Phases(circuit, repeat, top))
+        }
+        ["play"] => Ok(Command::Play(None)),
+        ["play", "--record", path] => Ok(Command::Play(Some(path.to_string()))),
+        ["capture", path] => Ok(Command::Capture(path.to_string())),
+        ["fuzz", ref rest @ ..] => {
+            let mut count = 1000;
+            let mut seed = 0;
+            let mut rest = rest.iter();
+            while let Some(&a) = rest.next() {
+                match a {
+                    "--seed" => seed = number(rest.next().ok_or_else(|| USAGE.to_string())?)?,
+                    n => count = number(n)?,
+                }
+            }
+            Ok(Command::Fuzz(count, seed))
+        }
+        ["compile", src, out] => Ok(Command::Compile(src.to_string(), out.to_string(), false)),
+        ["compile", src, out, "--binary"] => {
+            Ok(Command::Compile(src.to_string(), out.to_string(), true))
+        }
+        ["optimize", path, out] => Ok(Command::Optimize(path.to_string(), out.to_string(), false)),
+        ["optimize", path, out, "--binary"] => {
+            Ok(Command::Optimize(path.to_string(), out.to_string(), true))
+        }
+        ["exec", path] => Ok(Command::Exec(path.to_string(), Input::Default)),
+        ["exec", path, "--input", from] => {
+            Ok(Command::Exec(path.to_string(), input_arg(Some(&from))?))
+        }
+        [tool @ ("coverage" | "profile" | "taint" | "dump"), path, ref rest @ ..] => {
+            let mut tool = match tool {
+                "coverage" => Tool::Coverage,
+                "profile" => Tool::Profile,
+                "taint" => Tool::Taint,
+                _ => Tool::Dump,
+            };
+            let mut input = Input::Default;
+            let mut ascii = false;
+            let mut rest = rest.iter();
+            while let Some(&a) = rest.next() {
+                match (a, tool) {
+                    ("--input", _) => input = input_arg(rest.next())?,
+                    ("--ascii", _) => ascii = true,
+                    ("--lcov", Tool::Coverage) => tool = Tool::Lcov,
+                    ("--folded", Tool::Profile) => tool = Tool::Folded,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            Ok(Command::Tool(tool, path.to_string(), input, ascii))
+        }
+        ["serve", path, addr] => Ok(Command::Serve(
+            path.to_string(),
+            addr.to_string(),
+            Mode::Integers,
+        )),
+        ["serve", path, addr, "--lines"] => Ok(Command::Serve(
+            path.to_string(),
+            addr.to_string(),
+            Mode::Lines,
+        )),
+        ["gdb", path, addr] => Ok(Command::Gdb(path.to_string(), addr.to_string())),
+        ["dap"] => Ok(Command::Dap),
+        ["run", "--all"] => Ok(Command::All),
+        ["verify"] => Ok(Command::Verify(false)),
+        ["verify", "--record"] => Ok(Command::Verify(true)),
+        ["bench", ref rest @ ..] => {
+            let mut days = Vec::new();
+            let mut threshold = 10.0;
+            let mut rest = rest.iter();
+            while let Some(&a) = rest.next() {
+                match a {
+                    "--threshold" => {
+                        let t = rest.next().ok_or_else(|| USAGE.to_string())?;
+                        threshold = t
+                            .parse()
+                            .map_err(|_| format!("bad percentage {:?}\n{}", t, USAGE))?;
+                    }
+                    day => days.push(number(day)?),
+                }
+            }
+            Ok(Command::Bench(days, threshold / 100.0))
+        }
+        ["record", day, path] => Ok(Command::Record(number(day)?, path.to_string())),
+        ["replay", day, path] => Ok(Command::Replay(number(day)?, path.to_string())),
+        ["run", day, part] => Ok(Command::Run(number(day)?, number(part)?, Input::Default)),
+        ["run", day, part, "--input", "-"] => {
+            Ok(Command::Run(number(day)?, number(part)?, Input::Stdin))
+        }
+        ["run", day, part, "--input", path] => Ok(Command::Run(
+            number(day)?,
+            number(part)?,
+            Input::File(path.to_string()),
+        )),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+// the command line, without the program name
+pub fn main(args: &[String]) -> Result<(), String> {
+    match parse_args(args)? {
+        Command::Run(day, part, input) => {
+            let answer = run(day, part, &input)?;
+            println!("day {} part {}: {}", day, part, answer);
+            Ok(())
+        }
+        Command::All => match run_all() {
+            0 => Ok(()),
+            n => Err(format!("{} failed", n)),
+        },
+        Command::Verify(save) => {
+            let mut answers = Answers::load(ANSWERS)?;
+            let checks = verify::verify(&answers);
+            println!("{}", Table(&checks));
+            if save && record(&mut answers, &checks) > 0 {
+                answers
+                    .save(ANSWERS)
+                    .map_err(|e| format!("{}: {}", ANSWERS, e))?;
+            }
+            let failed = checks
+                .iter()
+                .filter(|c| matches!(c.status, Status::Fail { .. } | Status::Error(_)))
+                .count();
+            match failed {
+                0 => Ok(()),
+                n => Err(format!("{} failed", n)),
+            }
+        }
+        Command::Bench(days, threshold) => {
+            let days = match days.len() {
+                0 => DAYS.iter().collect(),
+                _ => days
+                    .iter()
+                    .map(|&d| find(d).ok_or_else(|| format!("day {} is not implemented", d)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            };
+            let run = Run::new(bench::bench(&days, &Config::default()));
+            let mut history = History::load(HISTORY)?;
+            println!(
+                "{}",
+                Report {
+                    run: &run,
+                    history: &history,
+                    threshold,
+                }
+            );
+            history.runs.push(run);
+            history
+                .save(HISTORY)
+                .map_err(|e| format!("{}: {}", HISTORY, e))
+        }
+        Command::Record(day, path) => {
+            let (d, record, _) = session(day)?;
+            let log = record(&read_input(d, &Input::Default)?)?;
+            log.save(&path).map_err(|e| format!("{}: {}", path, e))
+        }
+        Command::Replay(day, path) => {
+            let (d, _, replay) = session(day)?;
+            let log = Log::load(&path)?;
+            replay(&read_input(d, &Input::Default)?, &log)?;
+            println!("{}: replayed ok", path);
+            Ok(())
+        }
+        Command::Serve(path, addr, mode) => {
+            let server = Server::new(&program(&path)?, mode);
+            let e = |e: io::Error| format!("{}: {}", addr, e);
+            if addr.parse::<SocketAddr>().is_ok() {
+                return server
+                    .serve_tcp(TcpListener::bind(&addr).map_err(e)?)
+                    .map_err(e);
+            }
+            #[cfg(unix)]
+            return server
+                .serve_unix(UnixListener::bind(&addr).map_err(e)?)
+                .map_err(e);
+            #[cfg(not(unix))]
+            Err(format!("bad address {:?}", addr))
+        }
+        Command::Gdb(path, addr) => {
+            let mut stub = Stub::new(&program(&path)?, &[]);
+            let e = |e: io::Error| format!("{}: {}", addr, e);
+            stub.listen(TcpListener::bind(&addr).map_err(e)?).map_err(e)
+        }
+        Command::Dap => dap::run_stdio().map_err(|e| format!("dap: {}", e)),
+        Command::Phases(circuit, repeat, top) => {
+            let d = find(7).expect("a registered day");
+            let code = Intcode::parse(&read_input(d, &Input::Default)?)
+                .map_err(|e| format!("{}: {}", d.input_file(), e))?;
+            let phases = circuit.phases();
+            let ranked = Optimizer::new(&code, &phases, phases.len(), repeat, circuit).ranked(0);
+            for (setting, output) in ranked.iter().take(top) {
+                let setting: Vec<String> = setting.iter().map(|p| p.to_string()).collect();
+                println!("{} {}", setting.join(","), output);
+            }
+            Ok(())
+        }
+        Command::Play(path) => {
+            let d = find(25).expect("a registered day");
+            day25::play(&read_input(d, &Input::Default)?, path.as_deref())
+        }
+        Command::Capture(path) => {
+            let d = find(23).expect("a registered day");
+            day23::capture(&read_input(d, &Input::Default)?, &path)
+        }
+        Command::Fuzz(count, seed) => match Fuzzer::new(seed, fuzz::implementations()).run(count) {
+            Some(d) => Err(d.to_string()),
+            None => {
+                println!("{} programs agree", count);
+                Ok(())
+            }
+        },
+        Command::Compile(src, out, binary) => {
+            let text = Source::File(src.clone()).read()?;
+            let mut c = lang::compile(&text).map_err(|e| format!("{}: {}", src, e))?;
+            Program::new(c.words)
+                .save(&out, binary)
+                .map_err(|e| format!("{}: {}", out, e))?;
+            // next to the program, for the debug adapter
+            let map = format!("{}.map", out);
+            c.map.source = Some(src);
+            fs::write(&map, c.map.to_string()).map_err(|e| format!("{}: {}", map, e))
+        }
+        Command::Optimize(path, out, binary) => {
+            let p = Program::load(&path)?;
+            let (words, stats) =
+                optimize::optimize(&p.words).map_err(|e| format!("{}: {}", path, e))?;
+            optimize::check(&p.words, &words, 1000, 1)
+                .map_err(|e| format!("{}: optimized program differs: {}", path, e))?;
+            println!(
+                "folded {}, threaded {}, fused {}, jumps removed {}, words removed {}",
+                stats.folded, stats.threaded, stats.fused, stats.jumps_removed, stats.words_removed
+            );
+            Program {
+                words,
+                meta: p.meta,
+            }
+            .save(&out, binary)
+            .map_err(|e| format!("{}: {}", out, e))
+        }
+        Command::Exec(path, input) => {
+            let mut code = program(&path)?;
+            let keys = program_source(&input).read()?;
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1);
+            let (terminal, exit) =
+                device::console(&mut code, &keys, seed).map_err(|e| format!("{}: {}", path, e))?;
+            print!("{}", terminal.text);
+            for v in terminal.values {
+                println!("{}", v);
+            }
+            match exit {
+                ExitMode::Halt => Ok(()),
+                _ => Err(format!("{}: waiting for input", path)),
+            }
+        }
+        Command::Tool(tool, path, input, ascii) => {
+            print!("{}", report(tool, &path, &values(&input, ascii)?)?);
+            Ok(())
+        }
+        Command::List => {
+            print!("{}", list());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(Ok(Command::List), parse_args(&args("list")));
+        assert_eq!(Ok(Command::Dap), parse_args(&args("dap")));
+        assert_eq!(
+            Ok(Command::Phases(Circuit::Chain, false, 10)),
+            parse_args(&args("phases"))
+        );
+        assert_eq!(
+            Ok(Command::Phases(Circuit::Feedback, true, 3)),
+            parse_args(&args("phases --repeat --top 3 --feedback"))
+        );
+        assert_eq!(Ok(Command::Play(None)), parse_args(&args("play")));
+        assert_eq!(
+            Ok(Command::Play(Some("x.session".to_string()))),
+            parse_args(&args("play --record x.session"))
+        );
+        assert_eq!(
+            Ok(Command::Capture("x.cap".to_string())),
+            parse_args(&args("capture x.cap"))
+        );
+        assert_eq!(Ok(Command::Fuzz(1000, 0)), parse_args(&args("fuzz")));
+        assert_eq!(
+            Ok(Command::Fuzz(50, 7)),
+            parse_args(&args("fuzz --seed 7 50"))
+        );
+        assert_eq!(
+            Ok(Command::Compile(
+                "a.src".to_string(),
+                "a.icb".to_string(),
+                true
+            )),
+            parse_args(&args("compile a.src a.icb --binary"))
+        );
+        assert_eq!(
+            Ok(Command::Optimize(
+                "a.int".to_string(),
+                "b.int".to_string(),
+                false
+            )),
+            parse_args(&args("optimize a.int b.int"))
+        );
+        assert_eq!(
+            Ok(Command::Exec("a.int".to_string(), Input::Stdin)),
+            parse_args(&args("exec a.int --input -"))
+        );
+        assert_eq!(
+            Ok(Command::Tool(
+                Tool::Lcov,
+                "a.int".to_string(),
+                Input::File("in.txt".to_string()),
+                true
+            )),
+            parse_args(&args("coverage a.int --lcov --input in.txt --ascii"))
+        );
+        assert_eq!(
+            Ok(Command::Tool(
+                Tool::Dump,
+                "a.int".to_string(),
+                Input::Default,
+                false
+            )),
+            parse_args(&args("dump a.int"))
+        );
+        assert_eq!(
+            Err(USAGE.to_string()),
+            parse_args(&args("profile a.int --lcov"))
+        );
+        assert_eq!(
+            Err(USAGE.to_string()),
+            parse_args(&args("taint a.int --folded"))
+        );
+        assert_eq!(
+            Ok(Command::Serve(
+                "a.int".to_string(),
+                "127.0.0.1:4000".to_string(),
+                Mode::Lines
+            )),
+            parse_args(&args("serve a.int 127.0.0.1:4000 --lines"))
+        );
+        assert_eq!(
+            Ok(Command::Gdb("a.int".to_string(), ":1234".to_string())),
+            parse_args(&args("gdb a.int :1234"))
+        );
+        assert_eq!(Ok(Command::All), parse_args(&args("run --all")));
+        assert_eq!(
+            Ok(Command::Verify(true)),
+            parse_args(&args("verify --record"))
+        );
+        assert_eq!(
+            Ok(Command::Run(3, 2, Input::Default)),
+            parse_args(&args("run 3 2"))
+        );
+        assert_eq!(
+            Ok(Command::Run(3, 1, Input::Stdin)),
+            parse_args(&args("run 3 1 --input -"))
+        );
+        assert_eq!(
+            Ok(Command::Run(3, 1, Input::File("x.txt".to_string()))),
+            parse_args(&args("run 3 1 --input x.txt"))
+        );
+        assert_eq!(
+            Ok(Command::Record(13, "x.session".to_string())),
+            parse_args(&args("record 13 x.session"))
+        );
+        assert_eq!(Err(USAGE.to_string()), parse_args(&args("run 3")));
+        assert!(parse_args(&args("run three 1")).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        // add two inputs and output the sum, then the first input
+        let path = std::env::temp_dir().join(format!("runner-{}.int", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        fs::write(&path, "3,13,3,14,1,13,14,15,4,15,4,13,99,0,0,0").unwrap();
+        let taint = report(Tool::Taint, &path, &[2, 3]);
+        let dump = report(Tool::Dump, &path, &[2]);
+        let coverage = report(Tool::Coverage, &path, &[2, 3]);
+        fs::write(&path, dump.clone().unwrap()).unwrap();
+        let resumed = program(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Ok("5 from inputs: 0, 1\n2 from inputs: 0\n".to_string()),
+            taint
+        );
+
+        assert!(dump.unwrap().starts_with("pc 2\nrb 0\nprogram 16\n"));
+        assert_eq!(2, resumed.unwrap().pc());
+        assert_eq!(Ok(Vec::new()), values(&Input::Default, false));
+        assert!(coverage.unwrap().ends_with("data only: 13, 14, 15\n"));
+    }
+
+    #[test]
+    fn test_registry() {
+        let days: Vec<u32> = DAYS.iter().map(|d| d.day).collect();
+        let mut sorted = days.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, days);
+        assert_eq!(vec![1], find(16).unwrap().parts());
+        assert!(find(18).is_none());
+        assert!(list().starts_with("day  1: part 1, 2\n"));
+        assert_eq!(
+            Err("day 20 is not implemented".to_string()),
+            run(20, 1, &Input::Default)
+        );
+        assert_eq!(
+            Err("day 25 has no part 2".to_string()),
+            run(25, 2, &Input::Default)
+        );
+        assert!(run(1, 1, &Input::File("no such file".to_string())).is_err());
+        assert!(session(25).is_ok());
+        assert_eq!(
+            Some("day 1 has no session to record".to_string()),
+            session(1).err()
+        );
+
+        let d = find(1).unwrap();
+        let solution = (d.load)("12\n14\n1969\n").unwrap();
+        assert_eq!(Ok(Answer::Int(2 + 2 + 654)), solve(solution.as_ref(), 1));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::intcode::ExitMode;
+use crate::intcode::Intcode;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Mode {
+    // ASCII: each line is sent as its characters and a newline, outputs
+    // below 128 are written as characters and anything else as a number
+    // on its own line
+    Lines,
+    // integers separated by commas or whitespace in, one per line out
+    Integers,
+}
+
+// Run one session: feed input read from the connection to the machine
+// whenever it is waiting, until it halts or the connection closes.
+fn session<R: BufRead, W: Write>(
+    code: &mut Intcode,
+    mode: Mode,
+    mut reader: R,
+    writer: W,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let mut queue = VecDeque::new();
+    loop {
+        match code.run_queue(&mut queue) {
+            ExitMode::Halt => return writer.flush(),
+            ExitMode::Output(x) => match mode {
+                Mode::Lines if (0..128).contains(&x) => write!(writer, "{}", x as u8 as char)?,
+                _ => writeln!(writer, "{}", x)?,
+            },
+            ExitMode::AwaitingInput => {
+                writer.flush()?;
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                let line = line.trim_end_matches(&['\r', '\n'][..]);
+                match mode {
+                    Mode::Lines => {
+                        queue.extend(line.chars().map(|c| c as i64));
+                        queue.push_back('\n' as i64);
+                    }
+                    Mode::Integers => {
+                        let tokens = line
+                            .split(|c: char| c == ',' || c.is_whitespace())
+                            .filter(|t| !t.is_empty());
+                        for t in tokens {
+                            match t.parse() {
+                                Ok(x) => queue.push_back(x),
+                                Err(_) => writeln!(writer, "error: bad integer {:?}", t)?,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Serves a program over a socket. Every connection gets its own copy of
+// the machine, run on its own thread.
+pub struct Server {
+    code: Intcode,
+    mode: Mode,
+}
+
+impl Server {
+    pub fn new(code: &Intcode, mode: Mode) -> Self {
+        Self {
+            code: code.clone(),
+            mode,
+        }
+    }
+
+    fn spawn<R, W>(&self, reader: R, writer: W)
+    where
+        R: BufRead + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let mut code = self.code.clone();
+        let mode = self.mode;
+        thread::spawn(move || {
+            if let Err(e) = session(&mut code, mode, reader, writer) {
+                eprintln!("session ended: {}", e);
+            }
+        });
+    }
+
+    // e.g. TcpListener::bind("127.0.0.1:4000"). Anyone who can connect
+    // can run the program, so only loopback addresses are served.
+    pub fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        let addr = listener.local_addr()?;
+        if !addr.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a loopback address", addr),
+            ));
+        }
+        for stream in listener.incoming() {
+            // a failed connection shouldn't stop the others
+            match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+                Ok((reader, writer)) => self.spawn(BufReader::new(reader), writer),
+                Err(e) => eprintln!("accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+                Ok((reader, writer)) => self.spawn(BufReader::new(reader), writer),
+                Err(e) => eprintln!("accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpStream;
+
+    // output the sum of each pair of inputs, forever
+    const SUM: &str = "3,100,3,101,1,100,101,102,4,102,1105,1,0";
+
+    #[test]
+    fn test_session_lines() {
+        // echo one line back, upper-cased by subtracting 32 from a-z
+        let mut code =
+            Intcode::new("3,100,1007,100,97,101,1005,101,13,1001,100,-32,100,4,100,1105,1,0");
+        let mut out = Vec::new();
+        session(&mut code, Mode::Lines, &b"hi!\n"[..], &mut out).unwrap();
+        assert_eq!("HI!\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_session_integers() {
+        let mut code = Intcode::new(SUM);
+        let mut out = Vec::new();
+        session(&mut code, Mode::Integers, &b"1 2,3\n4 x\n"[..], &mut out).unwrap();
+        assert_eq!(
+            "3\nerror: bad integer \"x\"\n7\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    fn sum(stream: &mut (impl Read + Write), a: i64, b: i64) -> String {
+        writeln!(stream, "{} {}", a, b).unwrap();
+        let mut buf = [0; 32];
+        let n = stream.read(&mut buf).unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(&Intcode::new(SUM), Mode::Integers);
+        thread::spawn(move || server.serve_tcp(listener));
+
+        let mut a = TcpStream::connect(addr).unwrap();
+        let mut b = TcpStream::connect(addr).unwrap();
+        assert_eq!("3\n", sum(&mut a, 1, 2));
+        assert_eq!("30\n", sum(&mut b, 10, 20));
+        assert_eq!("-1\n", sum(&mut a, 4, -5));
+    }
+
+    #[test]
+    fn test_tcp_loopback_only() {
+        let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+        let server = Server::new(&Intcode::new(SUM), Mode::Integers);
+        let e = server.serve_tcp(listener).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix() {
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("intcode-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = Server::new(&Intcode::new(SUM), Mode::Integers);
+        thread::spawn(move || server.serve_unix(listener));
+
+        let mut a = UnixStream::connect(&path).unwrap();
+        assert_eq!("5\n", sum(&mut a, 2, 3));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
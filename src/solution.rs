@@ -0,0 +1,91 @@
+use crate::input::ParseError;
+use std::fmt;
+
+// The answer to a part, to print or check
+#[derive(PartialEq, Debug, Clone)]
+pub enum Answer {
+    Int(i64),
+    Text(String),
+    // lines of ASCII art, e.g. letters painted on the hull
+    Art(String),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Answer::Int(x) => write!(f, "{}", x),
+            Answer::Text(s) => write!(f, "{}", s),
+            Answer::Art(s) => write!(f, "\n{}", s.trim_end()),
+        }
+    }
+}
+
+impl From<i64> for Answer {
+    fn from(x: i64) -> Self {
+        Answer::Int(x)
+    }
+}
+
+impl From<i32> for Answer {
+    fn from(x: i32) -> Self {
+        Answer::Int(x as i64)
+    }
+}
+
+impl From<usize> for Answer {
+    fn from(x: usize) -> Self {
+        Answer::Int(x as i64)
+    }
+}
+
+impl From<u64> for Answer {
+    fn from(x: u64) -> Self {
+        Answer::Int(x as i64)
+    }
+}
+
+impl From<String> for Answer {
+    fn from(s: String) -> Self {
+        Answer::Text(s)
+    }
+}
+
+impl From<&str> for Answer {
+    fn from(s: &str) -> Self {
+        Answer::Text(s.to_string())
+    }
+}
+
+// A day's puzzle: parse the input once, then solve each part from it.
+pub trait Solution {
+    fn parse(input: &str) -> Result<Self, ParseError>
+    where
+        Self: Sized;
+
+    fn part1(&self) -> Result<Answer, String>;
+
+    fn part2(&self) -> Result<Answer, String> {
+        Err("no second part".to_string())
+    }
+}
+
+// parse as S, for a registry of days with different input types
+pub fn load<S: Solution + 'static>(input: &str) -> Result<Box<dyn Solution>, ParseError> {
+    Ok(Box::new(S::parse(input)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer() {
+        assert_eq!(Answer::Int(3), 3usize.into());
+        assert_eq!("-7", Answer::from(-7i64).to_string());
+        assert_eq!("ok", Answer::from("ok").to_string());
+        assert_eq!(
+            "\n# #\n ##",
+            Answer::Art("# #\n ##\n".to_string()).to_string()
+        );
+    }
+}
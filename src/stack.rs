@@ -0,0 +1,89 @@
+use crate::intcode::Intcode;
+
+// A function frame, inferred from the relative base. Intcode has no call
+// instruction, but compiled programs move the relative base up on entry to
+// a function and back down before returning.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Frame {
+    // the relative base inside the frame
+    pub base: i64,
+    // the instruction that moved the relative base up
+    pub entry: i64,
+    // the instruction executed before the entry, normally the jump into
+    // the function
+    pub call: i64,
+}
+
+// Tracks frames as a machine runs: call step with the address of each
+// instruction after executing it. Several adjustments in a row, e.g. to
+// make room for locals, each count as a frame.
+#[derive(Debug, Default, Clone)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    base: i64,
+    last: i64,
+}
+
+impl CallStack {
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn step(&mut self, pc: i64, code: &Intcode) {
+        let rb = code.relative_base();
+        if rb > self.base {
+            self.frames.push(Frame {
+                base: rb,
+                entry: pc,
+                call: self.last,
+            });
+        } else if rb < self.base {
+            while let Some(f) = self.frames.last() {
+                if f.base <= rb {
+                    break;
+                }
+                self.frames.pop();
+            }
+        }
+        self.base = rb;
+        self.last = pc;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::NoExtension;
+
+    #[test]
+    fn test_call_stack() {
+        // set up a stack, call a function that outputs 2, output 1 on
+        // return
+        let mut code =
+            Intcode::new("109,100,21101,0,9,0,1105,1,12,104,1,99,109,1,104,2,109,-1,2105,1,0");
+        let mut stack = CallStack::default();
+        let mut depths = Vec::new();
+        loop {
+            let pc = code.pc();
+            let exit = code.step_ext(&mut NoExtension, &mut || None).unwrap();
+            stack.step(pc, &code);
+            if exit == Some(crate::intcode::ExitMode::Halt) {
+                break;
+            }
+            if pc == 14 {
+                let f = stack.frames[1];
+                assert_eq!(
+                    Frame {
+                        base: 101,
+                        entry: 12,
+                        call: 6
+                    },
+                    f
+                );
+            }
+            depths.push(stack.depth());
+        }
+        // 109, 21101, 1105, 109, 104, 109, 2105, 104
+        assert_eq!(vec![1, 1, 1, 2, 2, 1, 1, 1], depths);
+    }
+}
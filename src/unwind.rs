@@ -0,0 +1,57 @@
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+
+// held while the panic hook is swapped out, so two threads can't restore
+// each other's
+static HOOK: Mutex<()> = Mutex::new(());
+
+// run f, returning the panic message if it panics
+pub fn catch<F: FnOnce()>(f: F) -> Option<String> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(()) => None,
+        Err(e) => Some(
+            e.downcast_ref::<String>()
+                .cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "panic".to_string()),
+        ),
+    }
+}
+
+// run f without printing the message of any panic caught inside it
+pub fn quietly<T, F: FnOnce() -> T>(f: F) -> T {
+    let _lock = HOOK.lock().unwrap_or_else(|e| e.into_inner());
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    match result {
+        Ok(x) => x,
+        Err(e) => panic::resume_unwind(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(None, catch(|| ()));
+        assert_eq!(Some("boom".to_string()), catch(|| panic!("boom")));
+        assert_eq!(Some("x = 3".to_string()), catch(|| panic!("x = {}", 3)));
+    }
+
+    #[test]
+    fn test_quietly() {
+        assert_eq!(
+            Some("inner".to_string()),
+            quietly(|| catch(|| panic!("inner")))
+        );
+        assert_eq!(
+            Some("outer".to_string()),
+            catch(|| quietly(|| panic!("outer")))
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use crate::runner::{solve, DAYS};
+use crate::solution::{Answer, Solution};
+use crate::unwind;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+pub const ANSWERS: &str = "answers.toml";
+
+// The known answers for our inputs, by day and part. Stored as a small
+// subset of TOML, with ASCII art in multi-line literal strings:
+//
+//   [day1]
+//   part1 = 3434390
+//
+//   [day8]
+//   part2 = '''
+//   #### #
+//   '''
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Answers {
+    pub known: BTreeMap<(u32, u32), Answer>,
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let s = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?),
+            '"' => return None,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+impl Answers {
+    pub fn get(&self, day: u32, part: u32) -> Option<&Answer> {
+        self.known.get(&(day, part))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        let mut last = None;
+        for (&(day, part), a) in self.known.iter() {
+            if last != Some(day) {
+                if last.is_some() {
+                    s.push('\n');
+                }
+                s.push_str(&format!("[day{}]\n", day));
+                last = Some(day);
+            }
+            let value = match a {
+                Answer::Int(x) => x.to_string(),
+                Answer::Text(t) => quote(t),
+                Answer::Art(art) => {
+                    let lines: Vec<&str> = art.trim_end().lines().map(|l| l.trim_end()).collect();
+                    format!("'''\n{}\n'''", lines.join("\n"))
+                }
+            };
+            s.push_str(&format!("part{} = {}\n", part, value));
+        }
+        s
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut answers = Self::default();
+        let mut day = None;
+        let mut lines = s.lines().enumerate();
+        while let Some((i, line)) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {} {:?}", i + 1, what, line);
+            if let Some(section) = line.strip_prefix('[') {
+                let n = section
+                    .strip_suffix(']')
+                    .and_then(|s| s.strip_prefix("day"))
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| bad("bad section"))?;
+                day = Some(n);
+                continue;
+            }
+            let v: Vec<&str> = line.splitn(2, '=').map(|x| x.trim()).collect();
+            let part = v[0]
+                .strip_prefix("part")
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| bad("bad key"))?;
+            let value = *v.get(1).ok_or_else(|| bad("missing value"))?;
+            let day = day.ok_or_else(|| bad("answer outside a day"))?;
+            let answer = if value == "'''" {
+                let mut art = String::new();
+                loop {
+                    match lines.next() {
+                        Some((_, "'''")) => break,
+                        Some((_, l)) => {
+                            art.push_str(l);
+                            art.push('\n');
+                        }
+                        None => return Err(bad("unclosed art")),
+                    }
+                }
+                Answer::Art(art)
+            } else if value.starts_with('"') {
+                Answer::Text(unquote(value).ok_or_else(|| bad("bad string"))?)
+            } else {
+                Answer::Int(value.parse().map_err(|_| bad("bad number"))?)
+            };
+            answers.known.insert((day, part), answer);
+        }
+        Ok(answers)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    // no file is no known answers yet
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => Self::parse(&s).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+}
+
+// art matches ignoring trailing space, which editors tend to strip
+fn same(expected: &Answer, got: &Answer) -> bool {
+    let lines = |s: &str| -> Vec<String> {
+        s.trim_end()
+            .lines()
+            .map(|l| l.trim_end().to_string())
+            .collect()
+    };
+    match (expected, got) {
+        (Answer::Art(a), Answer::Art(b)) => lines(a) == lines(b),
+        _ => expected == got,
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Status {
+    Pass,
+    Fail { expected: Answer, got: Answer },
+    // no known answer to check against
+    Missing(Answer),
+    // the input failed to load or parse, or the part panicked
+    Error(String),
+}
+
+impl Status {
+    fn label(&self) -> &str {
+        match self {
+            Status::Pass => "pass",
+            Status::Fail { .. } => "FAIL",
+            Status::Missing(_) => "missing",
+            Status::Error(_) => "ERROR",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Check {
+    pub day: u32,
+    pub part: u32,
+    pub status: Status,
+    // parsing the input, shared by both parts
+    pub parse: Duration,
+    pub time: Duration,
+}
+
+// e.g. 420ns, 850us, 12.3ms, 1.20s
+pub fn duration(d: Duration) -> String {
+    let us = d.as_micros();
+    if us == 0 {
+        format!("{}ns", d.as_nanos())
+    } else if us < 1000 {
+        format!("{}us", us)
+    } else if us < 1_000_000 {
+        format!("{:.1}ms", us as f64 / 1000.0)
+    } else {
+        format!("{:.2}s", d.as_secs_f64())
+    }
+}
+
+// solve a part against its known answer, if any, turning a panic into
+// an error so the other parts still run
+fn check(solution: &dyn Solution, part: u32, expected: Option<&Answer>) -> Status {
+    let mut result = Err("no result".to_string());
+    if let Some(e) = unwind::quietly(|| unwind::catch(|| result = solve(solution, part))) {
+        return Status::Error(format!("panicked: {}", e));
+    }
+    match (result, expected) {
+        (Ok(got), Some(expected)) if same(expected, &got) => Status::Pass,
+        (Ok(got), Some(expected)) => Status::Fail {
+            expected: expected.clone(),
+            got,
+        },
+        (Ok(got), None) => Status::Missing(got),
+        (Err(e), _) => Status::Error(e),
+    }
+}
+
+// run every part of every day against the known answers
+pub fn verify(answers: &Answers) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for d in DAYS.iter() {
+        let start = Instant::now();
+        let solution = d.solution();
+        let parse = start.elapsed();
+        for part in d.parts() {
+            let start = Instant::now();
+            let status = match &solution {
+                Ok(s) => check(s.as_ref(), part, answers.get(d.day, part)),
+                Err(e) => Status::Error(e.clone()),
+            };
+            checks.push(Check {
+                day: d.day,
+                part,
+                status,
+                parse,
+                time: start.elapsed(),
+            });
+        }
+    }
+    checks
+}
+
+// add the answers of the checks with none known
+pub fn record(answers: &mut Answers, checks: &[Check]) -> usize {
+    let mut n = 0;
+    for c in checks.iter() {
+        if let Status::Missing(a) = &c.status {
+            answers.known.insert((c.day, c.part), a.clone());
+            n += 1;
+        }
+    }
+    n
+}
+
+pub struct Table<'a>(pub &'a [Check]);
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>3} {:>4}  {:<7} {:>8} {:>8}",
+            "day", "part", "result", "parse", "time"
+        )?;
+        for c in self.0.iter() {
+            writeln!(
+                f,
+                "{:>3} {:>4}  {:<7} {:>8} {:>8}",
+                c.day,
+                c.part,
+                c.status.label(),
+                duration(c.parse),
+                duration(c.time)
+            )?;
+        }
+        for c in self.0.iter() {
+            match &c.status {
+                Status::Fail { expected, got } => writeln!(
+                    f,
+                    "day {} part {}: expected {}, got {}",
+                    c.day, c.part, expected, got
+                )?,
+                Status::Error(e) => writeln!(f, "day {} part {}: {}", c.day, c.part, e)?,
+                _ => (),
+            }
+        }
+        let count = |label| self.0.iter().filter(|c| c.status.label() == label).count();
+        let total: Duration = self.0.iter().map(|c| c.time).sum();
+        write!(
+            f,
+            "{} passed, {} failed, {} missing, {} errors in {}",
+            count("pass"),
+            count("FAIL"),
+            count("missing"),
+            count("ERROR"),
+            duration(total)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answers() {
+        let mut answers = Answers::default();
+        answers.known.insert((1, 1), Answer::Int(-3));
+        answers
+            .known
+            .insert((1, 2), Answer::Text("say \"01\"".to_string()));
+        answers
+            .known
+            .insert((8, 2), Answer::Art("# #\n ##\n".to_string()));
+        let expected = "[day1]
+part1 = -3
+part2 = \"say \\\"01\\\"\"
+
+[day8]
+part2 = '''
+# #
+ ##
+'''
+";
+        assert_eq!(expected, answers.to_text());
+        assert_eq!(Ok(answers.clone()), Answers::parse(&answers.to_text()));
+        assert_eq!(
+            Err("line 2: answer outside a day \"part1 = 3\"".to_string()),
+            Answers::parse("# answers\npart1 = 3\n")
+        );
+        assert!(Answers::parse("[day1]\npart1 = three\n").is_err());
+        assert!(Answers::parse("[day8]\npart2 = '''\n# #\n").is_err());
+    }
+
+    #[test]
+    fn test_same() {
+        let art = |s: &str| Answer::Art(s.to_string());
+        assert!(same(&art("# #\n ##\n"), &art("# #  \n ##\n\n")));
+        assert!(!same(&art("# #\n"), &art("## \n")));
+        assert!(!same(&Answer::Int(1), &Answer::Text("1".to_string())));
+    }
+
+    struct Stub;
+
+    impl Solution for Stub {
+        fn parse(_input: &str) -> Result<Self, crate::input::ParseError> {
+            Ok(Stub)
+        }
+
+        fn part1(&self) -> Result<Answer, String> {
+            Ok(Answer::Int(4))
+        }
+
+        fn part2(&self) -> Result<Answer, String> {
+            panic!("index out of bounds")
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(Status::Pass, check(&Stub, 1, Some(&Answer::Int(4))));
+        assert_eq!(Status::Missing(Answer::Int(4)), check(&Stub, 1, None));
+        assert_eq!(
+            Status::Error("panicked: index out of bounds".to_string()),
+            check(&Stub, 2, Some(&Answer::Int(4)))
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let check = |part, status| Check {
+            day: 3,
+            part,
+            status,
+            parse: Duration::from_micros(20),
+            time: Duration::from_micros(1500),
+        };
+        let checks = [
+            check(1, Status::Pass),
+            check(
+                2,
+                Status::Fail {
+                    expected: Answer::Int(4),
+                    got: Answer::Int(5),
+                },
+            ),
+        ];
+        let expected = "day part  result     parse     time
+  3    1  pass        20us    1.5ms
+  3    2  FAIL        20us    1.5ms
+day 3 part 2: expected 4, got 5
+1 passed, 1 failed, 0 missing, 0 errors in 3.0ms";
+        assert_eq!(expected, Table(&checks).to_string());
+
+        let mut answers = Answers::default();
+        assert_eq!(
+            1,
+            record(&mut answers, &[check(1, Status::Missing(7.into()))])
+        );
+        assert_eq!(Some(&Answer::Int(7)), answers.get(3, 1));
+    }
+}